@@ -0,0 +1,207 @@
+use crate::{Input, EARTH_RADIUS};
+use clap::Args;
+use image::imageops::flip_vertical;
+use image::Rgb;
+use nalgebra::Vector2;
+use small_world_model::colormap::Colormap;
+use small_world_model::composite::{composite, field_mask, gray_to_rgb, BlendMode, Layer};
+use small_world_model::geojson::{lines_to_geojson, load_geojson};
+use small_world_model::geometry::GeoGrid;
+use small_world_model::gradients::{
+    convert_nc_to_gradient_map, gradient_field, min_neighbor_radius,
+};
+use small_world_model::image::save_webp_lossy;
+use small_world_model::map_helpers::read_age_grid_shape;
+use small_world_model::overlay::Overlay;
+use small_world_model::partition::{label_partitions, partition_crust};
+use small_world_model::relief::{depth_from_age, hillshade, normal_map, Light};
+use small_world_model::resample::{resample, resample_rgb, Resampling};
+use small_world_model::scalar::{export_scalars, quantile, Field, LosslessFormat};
+use small_world_model::streamlines::{streamlines, StreamlineOptions};
+use small_world_model::tiles::{export_tiles, max_level_for};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct GradientArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Directory the viewer serves.
+    #[arg(long, default_value = "public")]
+    out: PathBuf,
+    /// Coastlines drawn over the map, skipped if the file doesn't exist.
+    #[arg(long, default_value = "data/coastlines.geojson")]
+    coastlines: PathBuf,
+    /// Meters around each cell the gradient's bearing is fit over.
+    #[arg(long, default_value_t = 2620.0)]
+    neighbor_radius: f32,
+    /// Width of the full-resolution map the tiles are cut from.
+    #[arg(long, default_value_t = 16384)]
+    width: u32,
+    #[arg(long, default_value_t = 256)]
+    tile_size: u32,
+    /// Width of the scalar fields, streamlines and normal map.
+    #[arg(long, default_value_t = 4096)]
+    scalar_width: u32,
+    /// Myr between isochrons.
+    #[arg(long, default_value_t = 20.0)]
+    isochron_interval: f32,
+    /// Partitions are of the crust at least this old (Myr).
+    #[arg(long, default_value_t = 100.0)]
+    partition_age: f32,
+    /// The ocean floor is too flat to see otherwise.
+    #[arg(long, default_value_t = 50.0)]
+    normal_exaggeration: f32,
+}
+
+pub fn run(args: &GradientArgs) -> Result<(), Box<dyn Error>> {
+    let grid = read_age_grid_shape(&args.input.ages, EARTH_RADIUS)?;
+    let min_radius = min_neighbor_radius(&grid);
+    if args.neighbor_radius < min_radius {
+        return Err(format!(
+            "--neighbor-radius must be at least the input's cell size, {min_radius:.0} m, got {}",
+            args.neighbor_radius
+        )
+        .into());
+    }
+    let img = convert_nc_to_gradient_map(&args.input.ages, args.neighbor_radius)?;
+
+    let (width, height) = (args.width, args.width / 2);
+    let img = resample_rgb(&img, width, height);
+
+    // Shade the ocean floor by its depth, as predicted from crust age
+    let (src, src_ages) = args.input.read()?;
+    let grid = GeoGrid {
+        nx: width as usize,
+        ny: height as usize,
+        radius: EARTH_RADIUS,
+    };
+    let ages = resample(&src, &src_ages, &grid, Resampling::Conservative);
+    let depths: Vec<f32> = ages.iter().map(|&a| depth_from_age(a)).collect();
+    let cell = std::f32::consts::PI * EARTH_RADIUS / height as f32;
+    let shade = hillshade(&grid, &depths, 1.5 * cell, -1.0, Light::default());
+    let ocean = field_mask(&grid, &ages, |a| !a.is_nan());
+
+    let relief = Layer::new(gray_to_rgb(&shade), BlendMode::Multiply).with_mask(ocean);
+
+    let mut isochrons = Overlay::new(width, height);
+    isochrons.isochrons(&grid, &ages, args.isochron_interval);
+    let mut graticule = Overlay::new(width, height);
+    graticule.graticule(30.0, 4.0).graticule_labels(30.0, 8);
+    let mut coastlines = Overlay::new(width, height);
+    if args.coastlines.exists() {
+        coastlines.geometries(&load_geojson(&args.coastlines)?, 6.0);
+    }
+
+    // The gradient map is stored south-up, like the other viewer textures,
+    // while the layers are north-up
+    let img = composite(
+        flip_vertical(&img),
+        &[
+            relief,
+            isochrons.layer(Rgb([0, 0, 0]), 0.35),
+            coastlines.layer(Rgb([255, 255, 255]), 0.9),
+            graticule.layer(Rgb([255, 255, 255]), 0.5),
+        ],
+    );
+
+    let tiles_out = args.out.join("tiles");
+    let max_level = max_level_for(width, args.tile_size);
+    export_tiles(&img, &tiles_out, args.tile_size, max_level, 60.0)?;
+    println!("Saved → {:?}", tiles_out);
+
+    // Single texture, for when tiles aren't used
+    let img = flip_vertical(&resample_rgb(&img, 8192, 4096));
+    let png_out = args.out.join("age.2020.1.GTS2012.webp");
+    std::fs::create_dir_all(&args.out)?;
+    save_webp_lossy(&img, 50.0, &png_out)?;
+
+    println!("Saved → {:?}", png_out);
+
+    export_scalar_fields(args, &src, &src_ages)?;
+
+    Ok(())
+}
+
+/// Raw fields for colorizing in the viewer: age with gradient magnitude, the
+/// gradient's east and north components, and partition ids. Also flow lines
+/// along the gradient, as GeoJSON, and a normal map of the ocean floor for
+/// lighting.
+fn export_scalar_fields(
+    args: &GradientArgs,
+    src: &GeoGrid,
+    src_ages: &[f32],
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (args.scalar_width, args.scalar_width / 2);
+    let grid = GeoGrid {
+        nx: width as usize,
+        ny: height as usize,
+        radius: EARTH_RADIUS,
+    };
+    let ages = resample(src, src_ages, &grid, Resampling::Conservative);
+
+    let cell = std::f32::consts::PI * EARTH_RADIUS / height as f32;
+    let gradients = gradient_field(&grid, &ages, 1.5 * cell);
+    let per_km = |f: fn(&Vector2<f32>) -> f32| -> Vec<f32> {
+        gradients.iter().map(|g| f(g) * 1000.0).collect()
+    };
+    let magnitude = per_km(|g| g.norm());
+    // leave out the steepest 1%, at fracture zones and ridge jumps
+    let max = quantile(&magnitude, 0.99);
+
+    let flow = streamlines(&grid, &gradients, &StreamlineOptions::default());
+    let flow_out = args.out.join("streamlines.geojson");
+    std::fs::write(&flow_out, lines_to_geojson(&flow))?;
+    println!("Saved {} streamlines → {:?}", flow.len(), flow_out);
+
+    let depths: Vec<f32> = ages.iter().map(|&a| depth_from_age(a)).collect();
+    let normals = normal_map(&grid, &depths, 1.5 * cell, -args.normal_exaggeration);
+    let normals_out = args.out.join("normals.webp");
+    save_webp_lossy(&normals, 90.0, &normals_out)?;
+    println!("Saved → {:?}", normals_out);
+
+    let (nx, ny) = (grid.nx, grid.ny);
+    let patches = partition_crust(&ages, (nx, ny), args.partition_age);
+    let partitions: Vec<f32> = label_partitions(&patches, nx * ny)
+        .into_iter()
+        .map(|id| {
+            if id < patches.len() {
+                id as f32
+            } else {
+                f32::NAN
+            }
+        })
+        .collect();
+
+    let age = Field::new("age", "Myr", ages);
+    let magnitude = Field::new("magnitude", "Myr/km", magnitude).with_range(0.0, max);
+    let east = Field::new("east", "Myr/km", per_km(|g| g.x)).with_range(-max, max);
+    let north = Field::new("north", "Myr/km", per_km(|g| g.y)).with_range(-max, max);
+    let partition = Field::new("partition", "id", partitions).with_range(0.0, 65534.0);
+
+    let out: &Path = &args.out.join("scalars");
+    export_scalars(
+        out,
+        width,
+        height,
+        &[
+            ("age_magnitude", &age, Some(&magnitude)),
+            ("gradient", &east, Some(&north)),
+            ("partition", &partition, None),
+        ],
+        LosslessFormat::WebP,
+    )?;
+
+    let colormaps = out.join("colormaps");
+    std::fs::create_dir_all(&colormaps)?;
+    for (name, cmap) in [
+        ("age_rainbow", Colormap::age_rainbow()),
+        ("viridis", Colormap::viridis()),
+        ("cividis", Colormap::cividis()),
+    ] {
+        cmap.strip(256)
+            .save(colormaps.join(format!("{name}.png")))?;
+    }
+    println!("Saved → {:?}", out);
+    Ok(())
+}
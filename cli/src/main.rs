@@ -0,0 +1,110 @@
+mod gradient;
+mod partition;
+mod pipeline;
+mod reconstruct;
+mod render;
+mod run;
+mod stats;
+mod video;
+
+use clap::{Args, Parser, Subcommand};
+use small_world_model::geojson::LonLat;
+use small_world_model::geometry::GeoGrid;
+use small_world_model::map_helpers::read_age_grid;
+use small_world_model::resample::{resample, Resampling};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+const EARTH_RADIUS: f32 = 6_371_008.8; // meters
+
+/// An expansion tectonic model of the Earth.
+#[derive(Parser)]
+#[command(name = "small-world", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Age gradients, and the viewer's data: map tiles, a single texture,
+    /// scalar fields, streamlines and a normal map.
+    Gradient(gradient::GradientArgs),
+    /// Partitions of the crust at least some age old, in random colors.
+    Partition(partition::PartitionArgs),
+    /// A still image of the globe at some age, in a map projection or as a
+    /// globe.
+    Render(render::RenderArgs),
+    /// The reconstruction from today back in time, as a video.
+    Video(video::VideoArgs),
+    /// The globe at some age, as an image and how well its crust fits.
+    Reconstruct(reconstruct::ReconstructArgs),
+    /// Radius and fit of the reconstructions over a range of ages.
+    Stats(stats::StatsArgs),
+    /// The steps and outputs of a pipeline file.
+    Run(run::RunArgs),
+}
+
+/// The ocean floor ages everything starts from.
+#[derive(Args)]
+struct Input {
+    /// NetCDF grid of crust ages (Myr), NaN on continents.
+    #[arg(long, default_value = "data/age.2020.1.GTS2012.1m.classic.nc")]
+    ages: PathBuf,
+}
+
+impl Input {
+    fn read(&self) -> Result<(GeoGrid, Vec<f32>), Box<dyn Error>> {
+        read_age_grid(&self.ages, EARTH_RADIUS)
+    }
+
+    fn read_work_grid(&self, nx: usize) -> Result<(GeoGrid, Vec<f32>), Box<dyn Error>> {
+        let (grid, ages) = self.read()?;
+        Ok(work_grid(&grid, &ages, nx))
+    }
+}
+
+/// `ages` on a coarser grid `nx` cells wide, for reconstructions; the 1' grid
+/// is far more than an image needs.
+fn work_grid(grid: &GeoGrid, ages: &[f32], nx: usize) -> (GeoGrid, Vec<f32>) {
+    let work = GeoGrid {
+        nx,
+        ny: nx / 2,
+        radius: EARTH_RADIUS,
+    };
+    let ages = resample(grid, ages, &work, Resampling::Conservative);
+    (work, ages)
+}
+
+fn main() {
+    let result = match Cli::parse().command {
+        Command::Gradient(args) => gradient::run(&args),
+        Command::Partition(args) => partition::run(&args),
+        Command::Render(args) => render::run(&args),
+        Command::Video(args) => video::run(&args),
+        Command::Reconstruct(args) => reconstruct::run(&args),
+        Command::Stats(args) => stats::run(&args),
+        Command::Run(args) => run::run(&args),
+    };
+    // errors span lines, e.g. every problem with a pipeline
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// `lon,lat` in degrees, e.g. `-30,20`.
+fn parse_lon_lat(s: &str) -> Result<LonLat, String> {
+    let (lon, lat) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected LON,LAT, got {s:?}"))?;
+    let parse = |v: &str| v.trim().parse::<f32>().map_err(|e| format!("{v:?}: {e}"));
+    Ok((parse(lon)?, parse(lat)?))
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,41 @@
+use crate::{create_parent, Input};
+use clap::Args;
+use image::RgbImage;
+use small_world_model::geometry::GeoGrid;
+use small_world_model::partition::{generate_colors, label_partitions, partition_crust};
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct PartitionArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Partitions are of the crust at least this old (Myr).
+    #[arg(long, default_value_t = 10.0)]
+    min_age: f32,
+    #[arg(short, long, default_value = "partitions.png")]
+    out: PathBuf,
+}
+
+pub fn run(args: &PartitionArgs) -> Result<(), Box<dyn Error>> {
+    let (grid, ages) = args.input.read()?;
+    let img = partition_map(&grid, &ages, args.min_age);
+
+    create_parent(&args.out)?;
+    img.save(&args.out)?;
+    println!("Saved → {:?}", args.out);
+    Ok(())
+}
+
+/// Partitions of the crust at least `min_age` old in random colors, north up.
+pub fn partition_map(grid: &GeoGrid, ages: &[f32], min_age: f32) -> RgbImage {
+    let patches = partition_crust(ages, (grid.nx, grid.ny), min_age);
+    println!("Found {} partitions", patches.len());
+
+    // the last color is for cells in no partition
+    let colors = generate_colors(patches.len() + 1);
+    let labels = label_partitions(&patches, ages.len());
+    RgbImage::from_fn(grid.nx as u32, grid.ny as u32, |x, y| {
+        colors[labels[y as usize * grid.nx + x as usize]]
+    })
+}
@@ -0,0 +1,945 @@
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use small_world_model::geometry::GeoGrid;
+use small_world_model::gradients::min_neighbor_radius;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// A run of the model as a TOML or YAML file: named steps that make images, and the
+/// outputs to save them as. Steps run when an output needs them, in any
+/// order they're written in.
+///
+/// ```toml
+/// [steps.gradient]
+/// kind = "gradient"
+///
+/// [steps.map]
+/// kind = "composite"
+/// base = "gradient"
+/// layers = [{ kind = "graticule" }]
+///
+/// [[output]]
+/// source = "map"
+/// path = "public/map.webp"
+/// ```
+///
+/// Every field but the ones naming steps and outputs has a default, which
+/// [`Pipeline::resolved`] fills in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    #[serde(default)]
+    pub input: InputSpec,
+    #[serde(default)]
+    pub steps: BTreeMap<String, Step>,
+    #[serde(default, rename = "output")]
+    pub outputs: Vec<Output>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct InputSpec {
+    /// NetCDF grid of crust ages (Myr), NaN on continents.
+    pub ages: PathBuf,
+}
+
+impl Default for InputSpec {
+    fn default() -> Self {
+        Self {
+            ages: "data/age.2020.1.GTS2012.1m.classic.nc".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Step {
+    Gradient(GradientStep),
+    Partition(PartitionStep),
+    Colormap(ColormapStep),
+    Reconstruct(ReconstructStep),
+    Composite(CompositeStep),
+}
+
+/// Bearings of the age gradient as colors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct GradientStep {
+    /// Meters around each cell the gradient is fit over.
+    pub neighbor_radius: f64,
+}
+
+impl Default for GradientStep {
+    fn default() -> Self {
+        Self {
+            neighbor_radius: 2620.0,
+        }
+    }
+}
+
+/// Partitions of the crust at least `min_age` (Myr) old, in random colors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PartitionStep {
+    pub min_age: f64,
+}
+
+impl Default for PartitionStep {
+    fn default() -> Self {
+        Self { min_age: 10.0 }
+    }
+}
+
+/// Present-day ages through a colormap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ColormapStep {
+    pub colormap: ColormapName,
+    /// Ages (Myr) at the ends of the colormap.
+    pub range: [f64; 2],
+}
+
+impl Default for ColormapStep {
+    fn default() -> Self {
+        Self {
+            colormap: ColormapName::AgeRainbow,
+            range: [0.0, 180.0],
+        }
+    }
+}
+
+/// The globe at `age` (Ma), oceanic crust colored by its age.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReconstructStep {
+    pub age: f64,
+    /// Cells around the equator of the grid reconstructed on.
+    #[serde(default = "default_work_width")]
+    pub work_width: usize,
+    #[serde(default)]
+    pub colormap: ColormapName,
+    #[serde(default = "default_age_range")]
+    pub range: [f64; 2],
+}
+
+fn default_work_width() -> usize {
+    2160
+}
+
+fn default_age_range() -> [f64; 2] {
+    [0.0, 180.0]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColormapName {
+    #[default]
+    AgeRainbow,
+    Viridis,
+    Cividis,
+}
+
+/// `layers` stacked over the image of step `base`, bottom to top, at its
+/// size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompositeStep {
+    pub base: String,
+    #[serde(default)]
+    pub layers: Vec<LayerSpec>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayerSpec {
+    Image(ImageLayer),
+    Relief(ReliefLayer),
+    Isochrons(LineLayer),
+    Graticule(GraticuleLayer),
+    Coastlines(CoastlineLayer),
+}
+
+/// The image of step `source`, resized to the base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageLayer {
+    pub source: String,
+    #[serde(default)]
+    pub mode: BlendModeName,
+    #[serde(default = "opaque")]
+    pub opacity: f64,
+}
+
+fn opaque() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendModeName {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+}
+
+/// The ocean floor shaded by its depth, as predicted from present-day crust
+/// age.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ReliefLayer {
+    pub opacity: f64,
+}
+
+impl Default for ReliefLayer {
+    fn default() -> Self {
+        Self { opacity: 1.0 }
+    }
+}
+
+/// Lines of equal present-day age every `interval` Myr.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct LineLayer {
+    pub interval: f64,
+    pub color: [u8; 3],
+    pub opacity: f64,
+}
+
+impl Default for LineLayer {
+    fn default() -> Self {
+        Self {
+            interval: 20.0,
+            color: [0, 0, 0],
+            opacity: 0.35,
+        }
+    }
+}
+
+/// Parallels and meridians every `spacing` degrees, `width` pixels wide.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct GraticuleLayer {
+    pub spacing: f64,
+    pub width: f64,
+    /// Scale of the degree labels; none if 0.
+    pub labels: u32,
+    pub color: [u8; 3],
+    pub opacity: f64,
+}
+
+impl Default for GraticuleLayer {
+    fn default() -> Self {
+        Self {
+            spacing: 30.0,
+            width: 4.0,
+            labels: 8,
+            color: [255, 255, 255],
+            opacity: 0.5,
+        }
+    }
+}
+
+/// The geometries of a GeoJSON file, `width` pixels wide.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct CoastlineLayer {
+    pub path: PathBuf,
+    pub width: f64,
+    pub color: [u8; 3],
+    pub opacity: f64,
+}
+
+impl Default for CoastlineLayer {
+    fn default() -> Self {
+        Self {
+            path: "data/coastlines.geojson".into(),
+            width: 6.0,
+            color: [255, 255, 255],
+            opacity: 0.9,
+        }
+    }
+}
+
+/// The image of step `source` saved to `path`: PNG or lossy WebP by its
+/// extension, or for a `reconstruct` step its fit metrics as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    pub source: String,
+    pub path: PathBuf,
+    /// Pixels; the source's width if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Pixels, only for `globe` projections; square if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Equirectangular if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub projection: Option<ProjectionSpec>,
+    /// Of lossy WebP, 0–100.
+    #[serde(default = "default_quality")]
+    pub quality: f64,
+}
+
+fn default_quality() -> f64 {
+    90.0
+}
+
+/// Map projections of [`small_world_model::projection`], and the globe as
+/// `render_globe` sees it. Centers are `[lon, lat]` in degrees.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProjectionSpec {
+    Equirectangular,
+    Orthographic {
+        center: [f64; 2],
+    },
+    Mollweide {
+        #[serde(default)]
+        central_meridian: f64,
+    },
+    Robinson {
+        #[serde(default)]
+        central_meridian: f64,
+    },
+    Globe {
+        center: [f64; 2],
+        /// Distance of the camera, in present-day radii.
+        #[serde(default = "default_dist")]
+        dist: f64,
+    },
+}
+
+fn default_dist() -> f64 {
+    2.2
+}
+
+// Steps and layers are tagged by `kind`, as serde's internally tagged enums
+// are. Those buffer the table before picking the variant, which loses where
+// its fields are in the file; when `kind` comes first, the rest of the table
+// is read straight into the variant instead, so errors keep their line.
+trait Kinded: Sized {
+    const KINDS: &'static [&'static str];
+
+    fn deserialize_kind<'de, D: Deserializer<'de>>(kind: &str, de: D) -> Result<Self, D::Error>;
+}
+
+impl Kinded for Step {
+    const KINDS: &'static [&'static str] = &[
+        "gradient",
+        "partition",
+        "colormap",
+        "reconstruct",
+        "composite",
+    ];
+
+    fn deserialize_kind<'de, D: Deserializer<'de>>(kind: &str, de: D) -> Result<Self, D::Error> {
+        Ok(match kind {
+            "gradient" => Step::Gradient(Deserialize::deserialize(de)?),
+            "partition" => Step::Partition(Deserialize::deserialize(de)?),
+            "colormap" => Step::Colormap(Deserialize::deserialize(de)?),
+            "reconstruct" => Step::Reconstruct(Deserialize::deserialize(de)?),
+            "composite" => Step::Composite(Deserialize::deserialize(de)?),
+            _ => return Err(de::Error::unknown_variant(kind, Self::KINDS)),
+        })
+    }
+}
+
+impl Kinded for LayerSpec {
+    const KINDS: &'static [&'static str] =
+        &["image", "relief", "isochrons", "graticule", "coastlines"];
+
+    fn deserialize_kind<'de, D: Deserializer<'de>>(kind: &str, de: D) -> Result<Self, D::Error> {
+        Ok(match kind {
+            "image" => LayerSpec::Image(Deserialize::deserialize(de)?),
+            "relief" => LayerSpec::Relief(Deserialize::deserialize(de)?),
+            "isochrons" => LayerSpec::Isochrons(Deserialize::deserialize(de)?),
+            "graticule" => LayerSpec::Graticule(Deserialize::deserialize(de)?),
+            "coastlines" => LayerSpec::Coastlines(Deserialize::deserialize(de)?),
+            _ => return Err(de::Error::unknown_variant(kind, Self::KINDS)),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        de.deserialize_map(KindVisitor(PhantomData))
+    }
+}
+
+impl<'de> Deserialize<'de> for LayerSpec {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        de.deserialize_map(KindVisitor(PhantomData))
+    }
+}
+
+struct KindVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Kinded> Visitor<'de> for KindVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a table with a `kind`, one of {}", T::KINDS.join(", "))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let Some(first) = map.next_key::<String>()? else {
+            return Err(de::Error::missing_field("kind"));
+        };
+        if first == "kind" {
+            let kind = map.next_value_seed(KindSeed(T::KINDS))?;
+            return T::deserialize_kind(&kind, MapAccessDeserializer::new(map));
+        }
+
+        // JSON values hold all TOML and YAML give a pipeline
+        let mut table = serde_json::Map::new();
+        table.insert(first, map.next_value()?);
+        while let Some(key) = map.next_key::<String>()? {
+            table.insert(key, map.next_value()?);
+        }
+        let kind = match table.remove("kind") {
+            Some(serde_json::Value::String(kind)) => kind,
+            Some(_) => return Err(de::Error::custom("`kind` must be a string")),
+            None => return Err(de::Error::missing_field("kind")),
+        };
+        T::deserialize_kind(&kind, serde_json::Value::Object(table)).map_err(de::Error::custom)
+    }
+}
+
+// A `kind`, checked as it's read so that an unknown one is reported at its
+// value.
+struct KindSeed(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for KindSeed {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<String, D::Error> {
+        de.deserialize_str(self)
+    }
+}
+
+impl Visitor<'_> for KindSeed {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "one of {}", self.0.join(", "))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+        if self.0.contains(&v) {
+            Ok(v.to_string())
+        } else {
+            Err(E::unknown_variant(v, self.0))
+        }
+    }
+}
+
+/// Syntax of a pipeline file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// YAML for `.yaml` and `.yml` files, TOML otherwise.
+    pub fn of(path: &Path) -> Self {
+        match extension(path).as_deref() {
+            Some("yaml") | Some("yml") => Format::Yaml,
+            _ => Format::Toml,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Toml => "toml",
+            Format::Yaml => "yaml",
+        }
+    }
+}
+
+/// What's wrong with a pipeline, at the dotted path of the field, e.g.
+/// `steps.map.layers[1].source`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalid {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl Pipeline {
+    /// Parse and validate a pipeline. Syntax and type errors point at the
+    /// line and column of the bad field, other errors at its path.
+    pub fn parse(text: &str, format: Format) -> Result<Self, Box<dyn Error>> {
+        let pipeline: Pipeline = match format {
+            Format::Toml => toml::from_str(text)?,
+            Format::Yaml => serde_yaml::from_str(text)?,
+        };
+        invalid(pipeline.validate())?;
+        Ok(pipeline)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, Format::of(path)).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    /// The pipeline with every default filled in, to save with the outputs
+    /// so they can be made again.
+    pub fn resolved(&self, format: Format) -> String {
+        match format {
+            Format::Toml => toml::to_string(self).expect("pipelines serialize"),
+            Format::Yaml => serde_yaml::to_string(self).expect("pipelines serialize"),
+        }
+    }
+
+    /// Everything wrong with the pipeline that parsing can't catch: missing
+    /// and circular steps, and values out of range.
+    pub fn validate(&self) -> Vec<Invalid> {
+        let mut errors = Vec::new();
+        let mut err = |path: String, message: String| errors.push(Invalid { path, message });
+
+        for (name, step) in &self.steps {
+            let at = format!("steps.{name}");
+            match step {
+                Step::Gradient(s) => {
+                    positive(&mut err, &at, "neighbor_radius", s.neighbor_radius);
+                }
+                Step::Partition(s) => {
+                    if s.min_age < 0.0 {
+                        err(format!("{at}.min_age"), "must not be negative".into());
+                    }
+                }
+                Step::Colormap(s) => increasing(&mut err, &at, s.range),
+                Step::Reconstruct(s) => {
+                    if s.age < 0.0 {
+                        err(format!("{at}.age"), "must not be negative".into());
+                    }
+                    if s.work_width < 2 || s.work_width % 2 != 0 {
+                        err(
+                            format!("{at}.work_width"),
+                            format!("must be an even number of cells, got {}", s.work_width),
+                        );
+                    }
+                    increasing(&mut err, &at, s.range);
+                }
+                Step::Composite(s) => {
+                    if !self.steps.contains_key(&s.base) {
+                        err(format!("{at}.base"), format!("no step {:?}", s.base));
+                    }
+                    for (i, layer) in s.layers.iter().enumerate() {
+                        let at = format!("{at}.layers[{i}]");
+                        match layer {
+                            LayerSpec::Image(l) => {
+                                if !self.steps.contains_key(&l.source) {
+                                    err(format!("{at}.source"), format!("no step {:?}", l.source));
+                                }
+                                unit(&mut err, &at, l.opacity);
+                            }
+                            LayerSpec::Relief(l) => unit(&mut err, &at, l.opacity),
+                            LayerSpec::Isochrons(l) => {
+                                positive(&mut err, &at, "interval", l.interval);
+                                unit(&mut err, &at, l.opacity);
+                            }
+                            LayerSpec::Graticule(l) => {
+                                positive(&mut err, &at, "spacing", l.spacing);
+                                positive(&mut err, &at, "width", l.width);
+                                unit(&mut err, &at, l.opacity);
+                            }
+                            LayerSpec::Coastlines(l) => {
+                                positive(&mut err, &at, "width", l.width);
+                                unit(&mut err, &at, l.opacity);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(cycle) = self.cycle() {
+            err(
+                format!("steps.{}", cycle[0]),
+                format!("depends on itself: {}", cycle.join(" → ")),
+            );
+        }
+
+        for (i, output) in self.outputs.iter().enumerate() {
+            let at = format!("output[{i}]");
+            let source = self.steps.get(&output.source);
+            if source.is_none() {
+                err(
+                    format!("{at}.source"),
+                    format!("no step {:?}", output.source),
+                );
+            }
+            match extension(&output.path).as_deref() {
+                Some("png") | Some("webp") => {}
+                Some("json") => {
+                    if !matches!(source, Some(Step::Reconstruct(_)) | None) {
+                        err(
+                            format!("{at}.path"),
+                            "only reconstruct steps have metrics to save as JSON".into(),
+                        );
+                    }
+                }
+                _ => err(
+                    format!("{at}.path"),
+                    format!("expected .png, .webp or .json, got {:?}", output.path),
+                ),
+            }
+            if output.width == Some(0) {
+                err(format!("{at}.width"), "must be positive".into());
+            }
+            if output.height.is_some()
+                && !matches!(output.projection, Some(ProjectionSpec::Globe { .. }))
+            {
+                err(
+                    format!("{at}.height"),
+                    "only globe projections take a height".into(),
+                );
+            }
+            if output.height == Some(0) {
+                err(format!("{at}.height"), "must be positive".into());
+            }
+            if !(0.0..=100.0).contains(&output.quality) {
+                err(
+                    format!("{at}.quality"),
+                    format!("must be within 0–100, got {}", output.quality),
+                );
+            }
+            if let Some(ProjectionSpec::Globe { dist, .. }) = &output.projection {
+                if *dist <= 1.0 {
+                    err(
+                        format!("{at}.projection.dist"),
+                        format!("the camera must be outside the globe, got {dist}"),
+                    );
+                }
+            }
+        }
+        errors
+    }
+
+    /// Everything wrong with the pipeline for input ages on `grid`: gradients
+    /// fit over less than a cell have too few neighbors to fit.
+    pub fn validate_input(&self, grid: &GeoGrid) -> Vec<Invalid> {
+        let min_radius = min_neighbor_radius(grid) as f64;
+        self.steps
+            .iter()
+            .filter_map(|(name, step)| match step {
+                Step::Gradient(s) if s.neighbor_radius < min_radius => Some(Invalid {
+                    path: format!("steps.{name}.neighbor_radius"),
+                    message: format!(
+                        "must be at least the input's cell size, {min_radius:.0} m on its {}×{} grid, got {}",
+                        grid.nx, grid.ny, s.neighbor_radius
+                    ),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// [`Pipeline::validate_input`] as one error.
+    pub fn check_input(&self, grid: &GeoGrid) -> Result<(), Box<dyn Error>> {
+        invalid(self.validate_input(grid))
+    }
+
+    /// The steps a step needs, directly.
+    pub fn sources(step: &Step) -> Vec<&str> {
+        match step {
+            Step::Composite(s) => std::iter::once(s.base.as_str())
+                .chain(s.layers.iter().filter_map(|l| match l {
+                    LayerSpec::Image(l) => Some(l.source.as_str()),
+                    _ => None,
+                }))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    // Names of steps that lead back to the first, if any do.
+    fn cycle(&self) -> Option<Vec<String>> {
+        fn visit<'a>(
+            pipeline: &'a Pipeline,
+            name: &'a str,
+            path: &mut Vec<&'a str>,
+            done: &mut BTreeSet<&'a str>,
+        ) -> Option<Vec<String>> {
+            if let Some(start) = path.iter().position(|&n| n == name) {
+                let mut cycle: Vec<String> = path[start..].iter().map(|n| n.to_string()).collect();
+                cycle.push(name.to_string());
+                return Some(cycle);
+            }
+            if done.contains(name) {
+                return None;
+            }
+            let step = pipeline.steps.get(name)?;
+            path.push(name);
+            for source in Pipeline::sources(step) {
+                if let Some(cycle) = visit(pipeline, source, path, done) {
+                    return Some(cycle);
+                }
+            }
+            path.pop();
+            done.insert(name);
+            None
+        }
+
+        let mut done = BTreeSet::new();
+        self.steps
+            .keys()
+            .find_map(|name| visit(self, name, &mut vec![], &mut done))
+    }
+}
+
+fn invalid(errors: Vec<Invalid>) -> Result<(), Box<dyn Error>> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<String> = errors.iter().map(|e| format!("  {e}")).collect();
+    Err(format!("invalid pipeline:\n{}", lines.join("\n")).into())
+}
+
+fn positive(err: &mut impl FnMut(String, String), at: &str, field: &str, v: f64) {
+    if v <= 0.0 || v.is_nan() {
+        err(
+            format!("{at}.{field}"),
+            format!("must be positive, got {v}"),
+        );
+    }
+}
+
+fn unit(err: &mut impl FnMut(String, String), at: &str, opacity: f64) {
+    if !(0.0..=1.0).contains(&opacity) {
+        err(
+            format!("{at}.opacity"),
+            format!("must be within 0–1, got {opacity}"),
+        );
+    }
+}
+
+fn increasing(err: &mut impl FnMut(String, String), at: &str, [lo, hi]: [f64; 2]) {
+    if lo >= hi || lo.is_nan() || hi.is_nan() {
+        err(
+            format!("{at}.range"),
+            format!("must be [low, high], got [{lo}, {hi}]"),
+        );
+    }
+}
+
+pub fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(text: &str) -> Vec<String> {
+        let pipeline: Pipeline = toml::from_str(text).unwrap();
+        pipeline.validate().into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn test_defaults_are_resolved() {
+        let pipeline = Pipeline::parse(
+            r#"
+            [steps.past]
+            kind = "reconstruct"
+            age = 100
+
+            [[output]]
+            source = "past"
+            path = "past.png"
+            "#,
+            Format::Toml,
+        )
+        .unwrap();
+        let resolved = pipeline.resolved(Format::Toml);
+        assert!(resolved.contains("work_width = 2160"), "{resolved}");
+        assert!(resolved.contains("ages = \"data/"), "{resolved}");
+
+        // `kind` needn't come first
+        let pipeline = Pipeline::parse(
+            r#"
+            [steps.gradient]
+            neighbor_radius = 1000
+            kind = "gradient"
+            "#,
+            Format::Toml,
+        )
+        .unwrap();
+        assert!(matches!(
+            pipeline.steps["gradient"],
+            Step::Gradient(GradientStep { neighbor_radius }) if neighbor_radius == 1000.0
+        ));
+
+        // and the resolved pipeline is the same pipeline
+        let again = Pipeline::parse(&resolved, Format::Toml).unwrap();
+        assert_eq!(again.resolved(Format::Toml), resolved);
+    }
+
+    #[test]
+    fn test_errors_point_at_the_field() {
+        let err = Pipeline::parse(
+            r#"
+            [steps.gradient]
+            kind = "gradient"
+            neighbour_radius = 1000
+            "#,
+            Format::Toml,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("line 4"), "{err}");
+        assert!(err.contains("neighbour_radius"), "{err}");
+
+        let err = Pipeline::parse(
+            r#"
+            [steps.map]
+            kind = "composite"
+            base = "gradient"
+            layers = [
+                { kind = "graticule" },
+                { kind = "isochrone" },
+            ]
+            "#,
+            Format::Toml,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("line 7"), "{err}");
+        assert!(err.contains("isochrone"), "{err}");
+
+        assert_eq!(
+            paths(
+                r#"
+                [steps.map]
+                kind = "composite"
+                base = "gradient"
+                layers = [
+                    { kind = "graticule" },
+                    { kind = "image", source = "partitions", opacity = 1.5 },
+                ]
+
+                [[output]]
+                source = "map"
+                path = "map.jpg"
+                "#
+            ),
+            [
+                "steps.map.base",
+                "steps.map.layers[1].source",
+                "steps.map.layers[1].opacity",
+                "output[0].path",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_neighbor_radius_covers_a_cell() {
+        let pipeline = Pipeline::parse(
+            r#"
+            [steps.fine]
+            kind = "gradient"
+            neighbor_radius = 1000.0
+
+            [steps.coarse]
+            kind = "gradient"
+            neighbor_radius = 200000.0
+            "#,
+            Format::Toml,
+        )
+        .unwrap();
+        // cells of 55.6 km
+        let grid = GeoGrid {
+            nx: 720,
+            ny: 360,
+            radius: 6_371_008.8,
+        };
+        let paths: Vec<String> = pipeline
+            .validate_input(&grid)
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, ["steps.fine.neighbor_radius"]);
+    }
+
+    #[test]
+    fn test_cycles_are_invalid() {
+        assert_eq!(
+            paths(
+                r#"
+                [steps.a]
+                kind = "composite"
+                base = "b"
+
+                [steps.b]
+                kind = "composite"
+                base = "c"
+                layers = [{ kind = "image", source = "a" }]
+
+                [steps.c]
+                kind = "gradient"
+                "#
+            ),
+            ["steps.a"]
+        );
+    }
+
+    #[test]
+    fn test_yaml_pipelines() {
+        let yaml = r#"
+steps:
+  gradient:
+    kind: gradient
+    neighbor_radius: 3000
+  map:
+    base: gradient
+    kind: composite
+    layers:
+      - kind: graticule
+        spacing: 15
+output:
+  - source: map
+    path: map.png
+"#;
+        let pipeline = Pipeline::parse(yaml, Format::Yaml).unwrap();
+        assert!(matches!(
+            &pipeline.steps["map"],
+            Step::Composite(s) if matches!(&s.layers[..], [LayerSpec::Graticule(l)] if l.spacing == 15.0)
+        ));
+        // the same pipeline, as TOML
+        let toml = pipeline.resolved(Format::Toml);
+        let again = Pipeline::parse(&toml, Format::Toml).unwrap();
+        assert_eq!(
+            again.resolved(Format::Yaml),
+            pipeline.resolved(Format::Yaml)
+        );
+
+        let err = Pipeline::parse(
+            "steps:\n  gradient:\n    kind: gradient\n    neighbour_radius: 3000\n",
+            Format::Yaml,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("line 4"), "{err}");
+        assert!(err.contains("neighbour_radius"), "{err}");
+    }
+
+    #[test]
+    fn test_example_pipelines_are_valid() {
+        Pipeline::parse(include_str!("../../pipelines/gradient.toml"), Format::Toml).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{create_parent, Input};
+use clap::Args;
+use small_world_model::colormap::Colormap;
+use small_world_model::metrics::fit_metrics;
+use small_world_model::reconstruct::{reconstruct, render_reconstruction};
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct ReconstructArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Ma.
+    #[arg(long)]
+    age: f32,
+    /// Cells around the equator of the grid reconstructed on.
+    #[arg(long, default_value_t = 2160)]
+    work_width: usize,
+    /// Age (Myr) at the end of the colormap.
+    #[arg(long, default_value_t = 180.0)]
+    color_max: f32,
+    /// Equirectangular image, north up.
+    #[arg(short, long, default_value = "reconstruction.png")]
+    out: PathBuf,
+    /// Where to save the fit metrics, as JSON.
+    #[arg(long)]
+    metrics: Option<PathBuf>,
+}
+
+pub fn run(args: &ReconstructArgs) -> Result<(), Box<dyn Error>> {
+    let (grid, ages) = args.input.read_work_grid(args.work_width)?;
+    let rec = reconstruct(&grid, &ages, args.age);
+    let fit = fit_metrics(&rec.grid, &rec.patches);
+    println!(
+        "{} Ma: radius {:.0} km, gaps {:.1}% ({} regions), overlaps {:.1}%",
+        args.age,
+        rec.grid.radius / 1000.0,
+        fit.gap_fraction * 100.0,
+        fit.gap_count,
+        fit.overlap_fraction * 100.0
+    );
+
+    let cmap = Colormap::age_rainbow().rescaled(0.0, args.color_max);
+    create_parent(&args.out)?;
+    render_reconstruction(&rec, &cmap).save(&args.out)?;
+    println!("Saved → {:?}", args.out);
+
+    if let Some(path) = &args.metrics {
+        create_parent(path)?;
+        fit.save_json(path)?;
+        println!("Saved → {:?}", path);
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::{create_parent, parse_lon_lat, Input, EARTH_RADIUS};
+use clap::{Args, ValueEnum};
+use image::Rgb;
+use small_world_model::colormap::Colormap;
+use small_world_model::geojson::LonLat;
+use small_world_model::globe::{render_globe, GlobeCamera};
+use small_world_model::projection::{warp_image, Projection};
+use small_world_model::reconstruct::{reconstruct, render_reconstruction};
+use small_world_model::resample::resample_rgb;
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum View {
+    Equirectangular,
+    Orthographic,
+    Mollweide,
+    Robinson,
+    /// Perspective view of the globe, shrunk to its radius at the age, as in
+    /// the viewer.
+    Globe,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Ma.
+    #[arg(long, default_value_t = 0.0)]
+    age: f32,
+    #[arg(long, value_enum, default_value_t = View::Equirectangular)]
+    projection: View,
+    /// LON,LAT in degrees: the center of orthographic and globe views, and the
+    /// central meridian of the others.
+    #[arg(long, value_parser = parse_lon_lat, default_value = "0,0", allow_hyphen_values = true)]
+    center: LonLat,
+    #[arg(long, default_value_t = 2048)]
+    width: u32,
+    /// Only for globe views, which are square by default; maps take the
+    /// height of their projection.
+    #[arg(long)]
+    height: Option<u32>,
+    /// Distance of the globe view's camera, in present-day radii.
+    #[arg(long, default_value_t = 2.2)]
+    dist: f32,
+    /// Cells around the equator of the grid reconstructed on.
+    #[arg(long, default_value_t = 2160)]
+    work_width: usize,
+    /// Age (Myr) at the end of the colormap.
+    #[arg(long, default_value_t = 180.0)]
+    color_max: f32,
+    #[arg(short, long, default_value = "render.png")]
+    out: PathBuf,
+}
+
+pub fn run(args: &RenderArgs) -> Result<(), Box<dyn Error>> {
+    let (grid, ages) = args.input.read_work_grid(args.work_width)?;
+    let rec = reconstruct(&grid, &ages, args.age);
+    let cmap = Colormap::age_rainbow().rescaled(0.0, args.color_max);
+    let img = render_reconstruction(&rec, &cmap);
+
+    let background = Rgb([0, 0, 0]);
+    let (lon, _) = args.center;
+    let out = match args.projection {
+        View::Equirectangular => resample_rgb(&img, args.width, args.width / 2),
+        View::Orthographic => {
+            let projection = Projection::Orthographic {
+                center: args.center,
+            };
+            warp_image(&img, &projection, args.width, background)
+        }
+        View::Mollweide => {
+            let projection = Projection::Mollweide {
+                central_meridian: lon,
+            };
+            warp_image(&img, &projection, args.width, background)
+        }
+        View::Robinson => {
+            let projection = Projection::Robinson {
+                central_meridian: lon,
+            };
+            warp_image(&img, &projection, args.width, background)
+        }
+        View::Globe => {
+            let mut camera = GlobeCamera::looking_at(args.center, args.dist);
+            camera.radius = rec.grid.radius / EARTH_RADIUS;
+            camera.background = background;
+            let height = args.height.unwrap_or(args.width);
+            render_globe(&img, &camera, args.width, height)
+        }
+    };
+
+    create_parent(&args.out)?;
+    out.save(&args.out)?;
+    println!("Saved → {:?}", args.out);
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use crate::partition::partition_map;
+use crate::pipeline::{
+    extension, BlendModeName, ColormapName, Format, LayerSpec, Output, Pipeline, ProjectionSpec,
+    Step,
+};
+use crate::{create_parent, work_grid, EARTH_RADIUS};
+use clap::Args;
+use image::imageops::flip_vertical;
+use image::{Rgb, RgbImage};
+use small_world_model::colormap::Colormap;
+use small_world_model::composite::{composite, field_mask, gray_to_rgb, BlendMode, Layer};
+use small_world_model::geojson::load_geojson;
+use small_world_model::geometry::GeoGrid;
+use small_world_model::globe::{render_globe, GlobeCamera};
+use small_world_model::gradients::convert_nc_to_gradient_map;
+use small_world_model::image::save_webp_lossy;
+use small_world_model::map_helpers::{read_age_grid, read_age_grid_shape};
+use small_world_model::metrics::{fit_metrics, FitMetrics};
+use small_world_model::overlay::Overlay;
+use small_world_model::projection::{warp_image, Projection};
+use small_world_model::reconstruct::{reconstruct, render_reconstruction};
+use small_world_model::relief::{depth_from_age, hillshade, Light};
+use small_world_model::resample::{resample, resample_rgb, Resampling};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// PNG text chunk, and JSON field, that outputs keep their pipeline in.
+const PIPELINE_KEY: &str = "small-world-pipeline";
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Pipeline file: TOML, or YAML if it ends in `.yaml` or `.yml`.
+    pipeline: PathBuf,
+    /// Only validate the pipeline against its input, and print it with its
+    /// defaults filled in.
+    #[arg(long)]
+    check: bool,
+}
+
+pub fn run(args: &RunArgs) -> Result<(), Box<dyn Error>> {
+    let pipeline = Pipeline::load(&args.pipeline)?;
+    let ages = &pipeline.input.ages;
+    let grid = read_age_grid_shape(ages, EARTH_RADIUS)
+        .map_err(|e| format!("input.ages ({}): {e}", ages.display()))?;
+    pipeline
+        .check_input(&grid)
+        .map_err(|e| format!("{}: {e}", args.pipeline.display()))?;
+    let format = Format::of(&args.pipeline);
+    let resolved = pipeline.resolved(format);
+    if args.check {
+        print!("{resolved}");
+        return Ok(());
+    }
+
+    let mut runner = Runner {
+        pipeline: &pipeline,
+        ages: None,
+        done: BTreeMap::new(),
+    };
+    for (i, output) in pipeline.outputs.iter().enumerate() {
+        runner
+            .save(output, &resolved, format)
+            .map_err(|e| format!("output[{i}] ({}): {e}", output.path.display()))?;
+    }
+    Ok(())
+}
+
+/// What a step makes.
+struct Product {
+    /// North up, equirectangular.
+    image: RgbImage,
+    /// Of the globe, relative to the present day's.
+    radius: f32,
+    metrics: Option<FitMetrics>,
+}
+
+/// Runs the steps of a pipeline as outputs need them, each once.
+struct Runner<'a> {
+    pipeline: &'a Pipeline,
+    ages: Option<(GeoGrid, Vec<f32>)>,
+    done: BTreeMap<&'a str, Product>,
+}
+
+impl<'a> Runner<'a> {
+    fn ages(&mut self) -> Result<&(GeoGrid, Vec<f32>), Box<dyn Error>> {
+        if self.ages.is_none() {
+            let path = &self.pipeline.input.ages;
+            let ages = read_age_grid(path, EARTH_RADIUS)
+                .map_err(|e| format!("input.ages ({}): {e}", path.display()))?;
+            self.ages = Some(ages);
+        }
+        Ok(self.ages.as_ref().unwrap())
+    }
+
+    /// Present-day ages on a grid the size of `img`.
+    fn ages_like(&mut self, img: &RgbImage) -> Result<(GeoGrid, Vec<f32>), Box<dyn Error>> {
+        let (src, ages) = self.ages()?;
+        let grid = GeoGrid {
+            nx: img.width() as usize,
+            ny: img.height() as usize,
+            radius: EARTH_RADIUS,
+        };
+        let ages = resample(src, ages, &grid, Resampling::Conservative);
+        Ok((grid, ages))
+    }
+
+    fn product(&mut self, name: &'a str) -> Result<&Product, Box<dyn Error>> {
+        if !self.done.contains_key(name) {
+            println!("Running step {name:?}");
+            let step = &self.pipeline.steps[name];
+            let product = self
+                .run_step(name, step)
+                .map_err(|e| format!("steps.{name}: {e}"))?;
+            self.done.insert(name, product);
+        }
+        Ok(&self.done[name])
+    }
+
+    fn run_step(&mut self, name: &str, step: &'a Step) -> Result<Product, Box<dyn Error>> {
+        let image = |image| Product {
+            image,
+            radius: 1.0,
+            metrics: None,
+        };
+        Ok(match step {
+            Step::Gradient(s) => {
+                let img = convert_nc_to_gradient_map(
+                    &self.pipeline.input.ages,
+                    s.neighbor_radius as f32,
+                )?;
+                // the model's gradient map is south-up; flipped here to the
+                // north-up every product is
+                image(flip_vertical(&img))
+            }
+            Step::Partition(s) => {
+                let (grid, ages) = self.ages()?;
+                image(partition_map(grid, ages, s.min_age as f32))
+            }
+            Step::Colormap(s) => {
+                let cmap = colormap(s.colormap, s.range);
+                let (grid, ages) = self.ages()?;
+                image(RgbImage::from_fn(grid.nx as u32, grid.ny as u32, |x, y| {
+                    cmap.color(ages[y as usize * grid.nx + x as usize])
+                }))
+            }
+            Step::Reconstruct(s) => {
+                let (grid, ages) = self.ages()?;
+                let (work, ages) = work_grid(grid, ages, s.work_width);
+                let rec = reconstruct(&work, &ages, s.age as f32);
+                let metrics = fit_metrics(&rec.grid, &rec.patches);
+                Product {
+                    image: render_reconstruction(&rec, &colormap(s.colormap, s.range)),
+                    radius: rec.grid.radius / EARTH_RADIUS,
+                    metrics: Some(metrics),
+                }
+            }
+            Step::Composite(s) => {
+                let base = self.product(&s.base)?;
+                let (base, radius) = (base.image.clone(), base.radius);
+                let (w, h) = base.dimensions();
+                let mut layers = Vec::with_capacity(s.layers.len());
+                for (i, layer) in s.layers.iter().enumerate() {
+                    let layer = self
+                        .layer(layer, &base)
+                        .map_err(|e| format!("layers[{i}]: {e}"))?;
+                    layers.push(layer);
+                }
+                println!(
+                    "Compositing {} layers over {name:?} ({w}×{h})",
+                    layers.len()
+                );
+                Product {
+                    image: composite(base, &layers),
+                    radius,
+                    metrics: None,
+                }
+            }
+        })
+    }
+
+    fn layer(&mut self, layer: &'a LayerSpec, base: &RgbImage) -> Result<Layer, Box<dyn Error>> {
+        let (w, h) = base.dimensions();
+        let rgb = |[r, g, b]: [u8; 3]| Rgb([r, g, b]);
+        Ok(match layer {
+            LayerSpec::Image(l) => {
+                let src = &self.product(&l.source)?.image;
+                let img = if src.dimensions() == (w, h) {
+                    src.clone()
+                } else {
+                    resample_rgb(src, w, h)
+                };
+                Layer::new(img, blend_mode(l.mode)).with_opacity(l.opacity as f32)
+            }
+            LayerSpec::Relief(l) => {
+                let (grid, ages) = self.ages_like(base)?;
+                let depths: Vec<f32> = ages.iter().map(|&a| depth_from_age(a)).collect();
+                let cell = std::f32::consts::PI * EARTH_RADIUS / h as f32;
+                let shade = hillshade(&grid, &depths, 1.5 * cell, -1.0, Light::default());
+                let ocean = field_mask(&grid, &ages, |a| !a.is_nan());
+                Layer::new(gray_to_rgb(&shade), BlendMode::Multiply)
+                    .with_mask(ocean)
+                    .with_opacity(l.opacity as f32)
+            }
+            LayerSpec::Isochrons(l) => {
+                let (grid, ages) = self.ages_like(base)?;
+                let mut overlay = Overlay::new(w, h);
+                overlay.isochrons(&grid, &ages, l.interval as f32);
+                overlay.layer(rgb(l.color), l.opacity as f32)
+            }
+            LayerSpec::Graticule(l) => {
+                let mut overlay = Overlay::new(w, h);
+                overlay.graticule(l.spacing as f32, l.width as f32);
+                if l.labels > 0 {
+                    overlay.graticule_labels(l.spacing as f32, l.labels);
+                }
+                overlay.layer(rgb(l.color), l.opacity as f32)
+            }
+            LayerSpec::Coastlines(l) => {
+                let geometries = load_geojson(&l.path)
+                    .map_err(|e| format!("path ({}): {e}", l.path.display()))?;
+                let mut overlay = Overlay::new(w, h);
+                overlay.geometries(&geometries, l.width as f32);
+                overlay.layer(rgb(l.color), l.opacity as f32)
+            }
+        })
+    }
+
+    fn save(
+        &mut self,
+        output: &'a Output,
+        resolved: &str,
+        format: Format,
+    ) -> Result<(), Box<dyn Error>> {
+        let product = self.product(&output.source)?;
+        let path = &output.path;
+        create_parent(path)?;
+        match extension(path).as_deref() {
+            Some("json") => {
+                let metrics = product.metrics.as_ref().ok_or("no metrics to save")?;
+                let json = serde_json::json!({
+                    PIPELINE_KEY: resolved,
+                    "metrics": metrics,
+                });
+                std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
+            }
+            Some("png") => save_png(&project(product, output), resolved, path)?,
+            _ => {
+                // the WebP encoder writes no metadata, so the pipeline goes
+                // next to the image
+                save_webp_lossy(&project(product, output), output.quality as f32, path)?;
+                let sidecar = format!("webp.{}", format.extension());
+                std::fs::write(path.with_extension(sidecar), resolved)?;
+            }
+        }
+        println!("Saved → {:?}", path);
+        Ok(())
+    }
+}
+
+/// The product's image in the output's projection and size.
+fn project(product: &Product, output: &Output) -> RgbImage {
+    let img = &product.image;
+    let width = output.width.unwrap_or(img.width());
+    let background = Rgb([0, 0, 0]);
+    let warp = |projection| warp_image(img, &projection, width, background);
+    match output.projection {
+        None | Some(ProjectionSpec::Equirectangular) => {
+            if width == img.width() {
+                img.clone()
+            } else {
+                resample_rgb(img, width, width / 2)
+            }
+        }
+        Some(ProjectionSpec::Orthographic { center: [lon, lat] }) => {
+            warp(Projection::Orthographic {
+                center: (lon as f32, lat as f32),
+            })
+        }
+        Some(ProjectionSpec::Mollweide { central_meridian }) => warp(Projection::Mollweide {
+            central_meridian: central_meridian as f32,
+        }),
+        Some(ProjectionSpec::Robinson { central_meridian }) => warp(Projection::Robinson {
+            central_meridian: central_meridian as f32,
+        }),
+        Some(ProjectionSpec::Globe {
+            center: [lon, lat],
+            dist,
+        }) => {
+            let mut camera = GlobeCamera::looking_at((lon as f32, lat as f32), dist as f32);
+            camera.radius = product.radius;
+            camera.background = background;
+            render_globe(img, &camera, width, output.height.unwrap_or(width))
+        }
+    }
+}
+
+/// Save `img` as PNG with the pipeline that made it in a text chunk.
+fn save_png(img: &RgbImage, pipeline: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        img.width(),
+        img.height(),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_itxt_chunk(PIPELINE_KEY.to_string(), pipeline.to_string())?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(img.as_raw())?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn colormap(name: ColormapName, [lo, hi]: [f64; 2]) -> Colormap {
+    let cmap = match name {
+        ColormapName::AgeRainbow => Colormap::age_rainbow(),
+        ColormapName::Viridis => Colormap::viridis(),
+        ColormapName::Cividis => Colormap::cividis(),
+    };
+    cmap.rescaled(lo as f32, hi as f32)
+}
+
+fn blend_mode(mode: BlendModeName) -> BlendMode {
+    match mode {
+        BlendModeName::Normal => BlendMode::Normal,
+        BlendModeName::Multiply => BlendMode::Multiply,
+        BlendModeName::Screen => BlendMode::Screen,
+        BlendModeName::Overlay => BlendMode::Overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_keeps_its_pipeline() {
+        let path = std::env::temp_dir().join("small-world-run-test.png");
+        let img = RgbImage::from_pixel(4, 2, Rgb([10, 20, 30]));
+        save_png(&img, "[steps.a]\nkind = \"gradient\"\n", &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let chunk = &reader.info().utf8_text[0];
+        assert_eq!(chunk.keyword, PIPELINE_KEY);
+        assert_eq!(
+            chunk.get_text().unwrap(),
+            "[steps.a]\nkind = \"gradient\"\n"
+        );
+        std::fs::remove_file(path).ok();
+    }
+}
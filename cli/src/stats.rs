@@ -0,0 +1,81 @@
+use crate::{create_parent, Input};
+use clap::Args;
+use serde::Serialize;
+use small_world_model::metrics::fit_metrics;
+use small_world_model::reconstruct::reconstruct;
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Ma.
+    #[arg(long, default_value_t = 0.0)]
+    from: f32,
+    /// Ma.
+    #[arg(long, default_value_t = 180.0)]
+    to: f32,
+    /// Myr.
+    #[arg(long, default_value_t = 10.0)]
+    step: f32,
+    /// Cells around the equator of the grid reconstructed on.
+    #[arg(long, default_value_t = 2160)]
+    work_width: usize,
+    /// Where to also save the table, as JSON.
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+/// One row of the table.
+#[derive(Serialize)]
+struct Stats {
+    age: f32,
+    /// km
+    radius: f32,
+    gap_fraction: f64,
+    gap_count: usize,
+    overlap_fraction: f64,
+}
+
+pub fn run(args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    if args.step <= 0.0 {
+        return Err(format!("--step must be positive, got {}", args.step).into());
+    }
+    let (grid, ages) = args.input.read_work_grid(args.work_width)?;
+
+    println!(
+        "{:>8} {:>12} {:>8} {:>8} {:>10}",
+        "age Ma", "radius km", "gaps %", "regions", "overlaps %"
+    );
+    let mut rows = Vec::new();
+    let count = ((args.to - args.from) / args.step).floor() as i32 + 1;
+    for i in 0..count.max(0) {
+        let age = args.from + i as f32 * args.step;
+        let rec = reconstruct(&grid, &ages, age);
+        let fit = fit_metrics(&rec.grid, &rec.patches);
+        let row = Stats {
+            age,
+            radius: rec.grid.radius / 1000.0,
+            gap_fraction: fit.gap_fraction,
+            gap_count: fit.gap_count,
+            overlap_fraction: fit.overlap_fraction,
+        };
+        println!(
+            "{:>8} {:>12.0} {:>8.1} {:>8} {:>10.1}",
+            row.age,
+            row.radius,
+            row.gap_fraction * 100.0,
+            row.gap_count,
+            row.overlap_fraction * 100.0
+        );
+        rows.push(row);
+    }
+
+    if let Some(path) = &args.json {
+        create_parent(path)?;
+        std::fs::write(path, serde_json::to_string_pretty(&rows)?)?;
+        println!("Saved → {:?}", path);
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::{create_parent, Input, EARTH_RADIUS};
+use clap::{Args, ValueEnum};
+use image::Rgb;
+use small_world_model::colormap::Colormap;
+use small_world_model::font::draw_label;
+use small_world_model::globe::{render_globe, GlobeCamera};
+use small_world_model::metrics::fit_metrics;
+use small_world_model::reconstruct::{reconstruct, render_reconstruction};
+use small_world_model::resample::resample_rgb;
+use small_world_model::video::{
+    make_video_parallel, print_progress, Codec, FrameFormat, VideoOptions,
+};
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum VideoCodec {
+    Av1,
+    Vp9,
+    H264,
+    /// PNG frames in the output directory.
+    Png,
+    /// Lossless WebP frames in the output directory.
+    Webp,
+}
+
+impl From<VideoCodec> for Codec {
+    fn from(codec: VideoCodec) -> Self {
+        match codec {
+            VideoCodec::Av1 => Codec::Av1,
+            VideoCodec::Vp9 => Codec::Vp9,
+            VideoCodec::H264 => Codec::H264,
+            VideoCodec::Png => Codec::ImageSequence(FrameFormat::Png),
+            VideoCodec::Webp => Codec::ImageSequence(FrameFormat::WebP),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct VideoArgs {
+    #[command(flatten)]
+    input: Input,
+    #[arg(short, long, default_value = "public/earth.webm")]
+    out: PathBuf,
+    /// Ma, of the last frame.
+    #[arg(long, default_value_t = 180.0)]
+    max_age: f32,
+    /// Myr per frame.
+    #[arg(long, default_value_t = 1.0)]
+    step: f32,
+    #[arg(long, default_value_t = 10)]
+    fps: u32,
+    #[arg(long, value_enum, default_value_t = VideoCodec::Vp9)]
+    codec: VideoCodec,
+    /// A rotating, shrinking globe instead of equirectangular frames.
+    #[arg(long)]
+    globe: bool,
+    /// Pixels, of the globe's square frames.
+    #[arg(long, default_value_t = 1024)]
+    size: u32,
+    /// Degrees the globe turns per frame.
+    #[arg(long, default_value_t = 2.0)]
+    turn: f32,
+    /// Distance of the globe's camera, in present-day radii.
+    #[arg(long, default_value_t = 2.2)]
+    dist: f32,
+    /// Cells around the equator of the grid reconstructed on.
+    #[arg(long, default_value_t = 2160)]
+    work_width: usize,
+    /// Frames held at once, rendering ahead of the encoder.
+    #[arg(long, default_value_t = 8)]
+    lookahead: usize,
+    /// First frame to render, to resume an interrupted image sequence.
+    #[arg(long, default_value_t = 0)]
+    start_frame: u32,
+}
+
+/// Renders the reconstruction from today back to `--max-age`: equirectangular
+/// frames for the viewer, or with `--globe` a rotating, shrinking globe.
+pub fn run(args: &VideoArgs) -> Result<(), Box<dyn Error>> {
+    if args.step <= 0.0 {
+        return Err(format!("--step must be positive, got {}", args.step).into());
+    }
+    let (work, ages) = args.input.read_work_grid(args.work_width)?;
+
+    let (width, height) = if args.globe {
+        (args.size, args.size)
+    } else {
+        (2048, 1024)
+    };
+    let frame_count = (args.max_age / args.step) as u32 + 1;
+    let cmap = Colormap::age_rainbow().rescaled(0.0, args.max_age);
+
+    let options = VideoOptions {
+        fps: args.fps,
+        codec: args.codec.into(),
+        start_frame: args.start_frame,
+        ..Default::default()
+    };
+
+    let out = args.out.to_str().ok_or("output path isn't valid UTF-8")?;
+    create_parent(&args.out)?;
+    make_video_parallel(
+        width,
+        height,
+        frame_count,
+        out,
+        &options,
+        args.lookahead,
+        |frame_idx| {
+            let age = frame_idx as f32 * args.step;
+            let rec = reconstruct(&work, &ages, age);
+            let fit = fit_metrics(&rec.grid, &rec.patches);
+            println!(
+                "{age} Ma: radius {:.0} km, gaps {:.1}%, overlaps {:.1}%",
+                rec.grid.radius / 1000.0,
+                fit.gap_fraction * 100.0,
+                fit.overlap_fraction * 100.0
+            );
+
+            // frames are north up, as the viewer uploads video without flipping
+            let img = render_reconstruction(&rec, &cmap);
+            let mut frame = if args.globe {
+                let mut camera = GlobeCamera::looking_at((0.0, 20.0), args.dist)
+                    .rotated(frame_idx as f32 * args.turn);
+                camera.radius = rec.grid.radius / EARTH_RADIUS;
+                render_globe(&img, &camera, width, height)
+            } else {
+                resample_rgb(&img, width, height)
+            };
+            let label = format!("{age:.0} Ma   R = {:.0} km", rec.grid.radius / 1000.0);
+            draw_label(
+                &mut frame,
+                16,
+                16,
+                &label,
+                3,
+                Rgb([255, 255, 255]),
+                Rgb([0, 0, 0]),
+                8,
+            );
+            frame
+        },
+        print_progress,
+    )?;
+    Ok(())
+}
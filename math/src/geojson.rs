@@ -0,0 +1,148 @@
+//! The geometries of GeoJSON documents, read the same way by the model's
+//! overlays and the viewer's vector layers.
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde_json::Value;
+
+/// Longitude and latitude, in degrees.
+pub type LonLat = (f32, f32);
+
+/// A GeoJSON geometry, with the `Multi*` kinds split into their parts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point(LonLat),
+    LineString(Vec<LonLat>),
+    /// Outer ring first, then holes. Rings are closed (last point == first).
+    Polygon(Vec<Vec<LonLat>>),
+}
+
+/// Parse the geometries of a GeoJSON document (a `FeatureCollection`,
+/// `Feature` or bare geometry). Properties are ignored, as are features with a
+/// null geometry; positions past the longitude and latitude (altitude) are
+/// dropped.
+pub fn parse_geojson(text: &str) -> Result<Vec<Geometry>, String> {
+    let doc: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    collect(&doc, &mut out)?;
+    Ok(out)
+}
+
+fn collect(obj: &Value, out: &mut Vec<Geometry>) -> Result<(), String> {
+    let kind = obj["type"].as_str().ok_or("object without a \"type\"")?;
+    let coords = &obj["coordinates"];
+    match kind {
+        "FeatureCollection" => {
+            for feature in array(&obj["features"])? {
+                collect(feature, out)?;
+            }
+        }
+        // features may have a null geometry
+        "Feature" if obj["geometry"].is_null() => {}
+        "Feature" => collect(&obj["geometry"], out)?,
+        "GeometryCollection" => {
+            for geometry in array(&obj["geometries"])? {
+                collect(geometry, out)?;
+            }
+        }
+        "Point" => out.push(Geometry::Point(position(coords)?)),
+        "MultiPoint" => {
+            for p in array(coords)? {
+                out.push(Geometry::Point(position(p)?));
+            }
+        }
+        "LineString" => out.push(Geometry::LineString(line(coords)?)),
+        "MultiLineString" => {
+            for l in array(coords)? {
+                out.push(Geometry::LineString(line(l)?));
+            }
+        }
+        "Polygon" => out.push(Geometry::Polygon(rings(coords)?)),
+        "MultiPolygon" => {
+            for p in array(coords)? {
+                out.push(Geometry::Polygon(rings(p)?));
+            }
+        }
+        other => return Err(format!("unknown GeoJSON type {other:?}")),
+    }
+    Ok(())
+}
+
+fn array(v: &Value) -> Result<&Vec<Value>, String> {
+    v.as_array()
+        .ok_or_else(|| format!("expected an array, got {v}"))
+}
+
+fn position(v: &Value) -> Result<LonLat, String> {
+    match array(v)?.as_slice() {
+        [lon, lat, ..] => match (lon.as_f64(), lat.as_f64()) {
+            (Some(lon), Some(lat)) => Ok((lon as f32, lat as f32)),
+            _ => Err(format!("bad position {v}")),
+        },
+        _ => Err(format!("bad position {v}")),
+    }
+}
+
+fn line(v: &Value) -> Result<Vec<LonLat>, String> {
+    array(v)?.iter().map(position).collect()
+}
+
+// Empty rings are left out rather than closed
+fn rings(v: &Value) -> Result<Vec<Vec<LonLat>>, String> {
+    let mut out = Vec::new();
+    for ring in array(v)? {
+        let mut ring = line(ring)?;
+        match (ring.first(), ring.last()) {
+            (None, _) => continue,
+            (Some(&first), Some(&last)) if first != last => ring.push(first),
+            _ => {}
+        }
+        out.push(ring);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_parse_feature_collection() {
+        let text = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "properties": {"name": "ridge"},
+                 "geometry": {"type": "MultiLineString",
+                              "coordinates": [[[0, 0], [1, 1, 5]], [[2, 2], [3, 3]]]}},
+                {"type": "Feature", "properties": {}, "geometry": null},
+                {"type": "Feature", "properties": {},
+                 "geometry": {"type": "Polygon",
+                              "coordinates": [[[0, 0], [10, 0], [10, 10]], []]}},
+                {"type": "Feature", "properties": {},
+                 "geometry": {"type": "Point", "coordinates": [-170.5, 45, 12]}}
+            ]
+        }"#;
+        assert_eq!(
+            parse_geojson(text).unwrap(),
+            vec![
+                Geometry::LineString(vec![(0.0, 0.0), (1.0, 1.0)]),
+                Geometry::LineString(vec![(2.0, 2.0), (3.0, 3.0)]),
+                Geometry::Polygon(vec![vec![
+                    (0.0, 0.0),
+                    (10.0, 0.0),
+                    (10.0, 10.0),
+                    (0.0, 0.0)
+                ]]),
+                Geometry::Point((-170.5, 45.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject_non_geojson() {
+        assert!(parse_geojson(r#"{"type": "Circle"}"#).is_err());
+        assert!(parse_geojson(r#"{"type": "Point", "coordinates": [1]}"#).is_err());
+        assert!(parse_geojson(r#"{"coordinates": [1, 2]}"#).is_err());
+    }
+}
@@ -0,0 +1,29 @@
+//! Sphere coordinates, quaternions and projection matrices shared by the
+//! model and the viewer, so both put a longitude and latitude at the same
+//! point of the screen. `no_std`: plain arrays and `libm`. The `geojson`
+//! feature adds a GeoJSON reader, which needs `alloc`.
+#![no_std]
+
+#[cfg(feature = "geojson")]
+extern crate alloc;
+
+#[cfg(feature = "geojson")]
+pub mod geojson;
+pub mod mat4;
+pub mod quat;
+pub mod sphere;
+
+/// Column-major 4×4 matrix, as WebGL takes them.
+pub type Mat4 = [f32; 16];
+/// Quaternion `(x, y, z, w)`; the identity is `(0, 0, 0, 1)`.
+pub type Quat = [f32; 4];
+pub type Vec3 = [f32; 3];
+
+pub fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn normalize(v: Vec3) -> Vec3 {
+    let n = libm::sqrtf(dot(v, v));
+    [v[0] / n, v[1] / n, v[2] / n]
+}
@@ -0,0 +1,118 @@
+//! Column-major 4×4 matrices for the globe's model-view-projection:
+//! `perspective · translate_z(-dist) · from_quat(orient) · scale(radius)`.
+
+use crate::{Mat4, Quat, Vec3};
+
+/// `a · b`
+pub fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [0.0; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            out[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k]).sum();
+        }
+    }
+    out
+}
+
+#[rustfmt::skip]
+pub fn from_quat(q: Quat) -> Mat4 {
+    let (x,y,z,w) = (q[0],q[1],q[2],q[3]);
+    let (xx,yy,zz) = (x*x, y*y, z*z);
+    let (xy,xz,yz) = (x*y, x*z, y*z);
+    let (wx,wy,wz) = (w*x, w*y, w*z);
+    [
+        1.0 - 2.0*(yy+zz),  2.0*(xy+ wz),     2.0*(xz - wy),     0.0,
+        2.0*(xy - wz),      1.0 - 2.0*(xx+zz),2.0*(yz + wx),     0.0,
+        2.0*(xz + wy),      2.0*(yz - wx),    1.0 - 2.0*(xx+yy), 0.0,
+        0.0,                0.0,              0.0,               1.0,
+    ]
+}
+
+/// `fovy` in radians.
+#[rustfmt::skip]
+pub fn perspective(fovy: f32, aspect: f32, znear: f32, zfar: f32) -> Mat4 {
+    let f = 1.0 / libm::tanf(0.5*fovy);
+    let nf = 1.0 / (znear - zfar);
+    [
+        f/aspect, 0.0, 0.0,  0.0,
+        0.0,      f,   0.0,  0.0,
+        0.0,      0.0,(zfar+znear)*nf, -1.0,
+        0.0,      0.0,(2.0*zfar*znear)*nf, 0.0,
+    ]
+}
+
+#[rustfmt::skip]
+pub fn scale(s: f32) -> Mat4 {
+    [
+        s,  0.0,0.0,0.0,
+        0.0,s,  0.0,0.0,
+        0.0,0.0,s,  0.0,
+        0.0,0.0,0.0,1.0,
+    ]
+}
+
+#[rustfmt::skip]
+pub fn translate_z(z: f32) -> Mat4 {
+    [
+        1.0,0.0,0.0,0.0,
+        0.0,1.0,0.0,0.0,
+        0.0,0.0,1.0,0.0,
+        0.0,0.0,z,  1.0,
+    ]
+}
+
+/// Maps the part `x` × `y` of normalized device coordinates to all of them,
+/// to render a piece of a larger image.
+#[rustfmt::skip]
+pub fn crop(x: [f32;2], y: [f32;2]) -> Mat4 {
+    let (sx, sy) = (2.0 / (x[1] - x[0]), 2.0 / (y[1] - y[0]));
+    [
+        sx,  0.0,0.0,0.0,
+        0.0,sy,  0.0,0.0,
+        0.0,0.0,1.0,0.0,
+        -(x[0] + x[1]) / (x[1] - x[0]), -(y[0] + y[1]) / (y[1] - y[0]), 0.0, 1.0,
+    ]
+}
+
+/// Normalized device coordinates of a point, `None` behind the camera.
+pub fn project(m: &Mat4, p: Vec3) -> Option<[f32; 2]> {
+    let clip: [f32; 4] = core::array::from_fn(|row| {
+        m[row] * p[0] + m[4 + row] * p[1] + m[8 + row] * p[2] + m[12 + row]
+    });
+    (clip[3] > 0.0).then(|| [clip[0] / clip[3], clip[1] / clip[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{quat, sphere};
+
+    // the viewer's model-view-projection
+    fn mvp(lon: f32, lat: f32, dist: f32) -> Mat4 {
+        let proj = perspective(60f32.to_radians(), 1.5, 0.01, 100.0);
+        let view = mul(&translate_z(-dist), &from_quat(quat::looking_at(lon, lat)));
+        mul(&proj, &view)
+    }
+
+    #[test]
+    fn test_project_globe() {
+        let m = mvp(-40.0, 20.0, 2.2);
+        let [x, y] = project(&m, sphere::from_lon_lat(-40.0, 20.0)).unwrap();
+        assert!(x.abs() < 1e-5 && y.abs() < 1e-5);
+        // north is up and east is right
+        let [_, y] = project(&m, sphere::from_lon_lat(-40.0, 25.0)).unwrap();
+        assert!(y > 0.0);
+        let [x, _] = project(&m, sphere::from_lon_lat(-35.0, 20.0)).unwrap();
+        assert!(x > 0.0);
+    }
+
+    #[test]
+    fn test_crop() {
+        let m = mvp(10.0, -5.0, 3.0);
+        let p = sphere::from_lon_lat(12.0, -3.0);
+        let [x, y] = project(&m, p).unwrap();
+        // the right half of the image, from the piece's point of view
+        let [cx, cy] = project(&mul(&crop([0.0, 1.0], [-1.0, 1.0]), &m), p).unwrap();
+        assert!((cx - (2.0 * x - 1.0)).abs() < 1e-5 && (cy - y).abs() < 1e-5);
+    }
+}
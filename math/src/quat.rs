@@ -0,0 +1,107 @@
+//! Quaternions `(x, y, z, w)` turning the globe's model space into the
+//! camera's, which looks down -Z with Y up.
+
+use crate::{Quat, Vec3};
+use core::f32::consts::{FRAC_PI_2, TAU};
+
+#[rustfmt::skip]
+pub fn mul(a: Quat, b: Quat) -> Quat {
+    // (ax,ay,az,aw) * (bx,by,bz,bw)
+    let (ax,ay,az,aw) = (a[0],a[1],a[2],a[3]);
+    let (bx,by,bz,bw) = (b[0],b[1],b[2],b[3]);
+    [
+        aw*bx + ax*bw + ay*bz - az*by,
+        aw*by - ax*bz + ay*bw + az*bx,
+        aw*bz + ax*by - ay*bx + az*bw,
+        aw*bw - ax*bx - ay*by - az*bz,
+    ]
+}
+
+/// The inverse rotation of a unit quaternion.
+pub fn conjugate(q: Quat) -> Quat {
+    [-q[0], -q[1], -q[2], q[3]]
+}
+
+pub fn normalize(q: Quat) -> Quat {
+    let len = libm::sqrtf(q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]);
+    [q[0] / len, q[1] / len, q[2] / len, q[3] / len]
+}
+
+pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+    let (sx, sy, sz) = (axis[0], axis[1], axis[2]);
+    let n = libm::sqrtf(sx * sx + sy * sy + sz * sz).max(1e-8);
+    let (x, y, z) = (sx / n, sy / n, sz / n);
+    let half = 0.5 * angle;
+    let s = libm::sinf(half);
+    [x * s, y * s, z * s, libm::cosf(half)]
+}
+
+pub fn rotate(q: Quat, v: Vec3) -> Vec3 {
+    // q * (v, 0) * conj(q)
+    let p = mul(mul(q, [v[0], v[1], v[2], 0.0]), conjugate(q));
+    [p[0], p[1], p[2]]
+}
+
+/// Spherical interpolation along the shorter arc.
+pub fn slerp(a: Quat, b: Quat, t: f32) -> Quat {
+    let mut cos = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    let b = if cos < 0.0 {
+        cos = -cos;
+        b.map(|c| -c)
+    } else {
+        b
+    };
+    if cos > 0.9995 {
+        return normalize([0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t));
+    }
+    let angle = libm::acosf(cos);
+    let sin = libm::sinf(angle);
+    let (wa, wb) = (
+        libm::sinf((1.0 - t) * angle) / sin,
+        libm::sinf(t * angle) / sin,
+    );
+    [0, 1, 2, 3].map(|i| wa * a[i] + wb * b[i])
+}
+
+/// Orientation putting `(lon, lat)` at the center of the view with north
+/// up: a turn about the axis to bring its meridian in front of the camera,
+/// then a tilt to bring it to the center. The identity looks at (90°E, 0°).
+pub fn looking_at(lon: f32, lat: f32) -> Quat {
+    let phi = -(lon + 180.0) / 360.0 * TAU; // as in `sphere::from_uv`
+    let spin = from_axis_angle([0.0, 1.0, 0.0], phi - FRAC_PI_2);
+    let tilt = from_axis_angle([1.0, 0.0, 0.0], lat.to_radians());
+    normalize(mul(tilt, spin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere;
+
+    #[test]
+    fn test_looking_at() {
+        let q = looking_at(90.0, 0.0);
+        assert!((q[3].abs() - 1.0).abs() < 1e-5, "{q:?}");
+
+        for (lon, lat) in [(0.0, 0.0), (-120.0, 35.0), (170.0, -60.0)] {
+            let q = looking_at(lon, lat);
+            let center = rotate(q, sphere::from_lon_lat(lon, lat));
+            assert!((center[2] - 1.0).abs() < 1e-5, "{center:?}");
+            // north up: the pole straight above the center
+            let pole = rotate(q, [0.0, 1.0, 0.0]);
+            assert!(pole[0].abs() < 1e-5 && pole[1] > 0.0, "{pole:?}");
+        }
+    }
+
+    #[test]
+    fn test_slerp() {
+        let a = from_axis_angle([0.0, 1.0, 0.0], 0.0);
+        let b = from_axis_angle([0.0, 1.0, 0.0], 1.0);
+        let half = slerp(a, b, 0.5);
+        let expected = from_axis_angle([0.0, 1.0, 0.0], 0.5);
+        assert!((0..4).all(|i| (half[i] - expected[i]).abs() < 1e-5));
+        // the shorter way round, whatever the sign of b
+        let back = slerp(a, b.map(|c| -c), 0.5);
+        assert!((0..4).all(|i| (back[i].abs() - expected[i].abs()).abs() < 1e-5));
+    }
+}
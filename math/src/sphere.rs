@@ -0,0 +1,94 @@
+//! Points of the unit sphere as the viewer draws it: north on +Y, and the
+//! texture coordinates `(u, v)` of an equirectangular map (u = 0 at 180°W,
+//! v = 0 at the north pole) at `(cos φ sin θ, cos θ, sin φ sin θ)` with
+//! φ = -2πu and θ = πv. Longitudes and latitudes are in degrees.
+//!
+//! The model's grids use geographic axes instead: x through (0°, 0°), y
+//! through (90°E, 0°) and z through the north pole. The same point is
+//! `(-x, z, y)` here, see [`from_geographic`].
+
+use crate::{dot, Vec3};
+use core::f32::consts::{PI, TAU};
+
+pub fn from_uv(u: f32, v: f32) -> Vec3 {
+    let phi = -u * TAU;
+    let theta = v * PI;
+    let sin_t = libm::sinf(theta);
+    [
+        libm::cosf(phi) * sin_t,
+        libm::cosf(theta),
+        libm::sinf(phi) * sin_t,
+    ]
+}
+
+/// Texture coordinates of a point, not necessarily of unit length; u in
+/// [0, 1).
+pub fn to_uv(p: Vec3) -> [f32; 2] {
+    let n = libm::sqrtf(dot(p, p));
+    let theta = libm::acosf((p[1] / n).clamp(-1.0, 1.0));
+    let phi = libm::atan2f(p[2], p[0]);
+    let u = -phi / TAU; // in [-1/2, 1/2]
+    let u = if u < 0.0 { u + 1.0 } else { u };
+    [u % 1.0, theta / PI]
+}
+
+pub fn from_lon_lat(lon: f32, lat: f32) -> Vec3 {
+    from_uv((lon + 180.0) / 360.0, (90.0 - lat) / 180.0)
+}
+
+/// Longitude in [-180, 180) and latitude of a point.
+pub fn to_lon_lat(p: Vec3) -> (f32, f32) {
+    let [u, v] = to_uv(p);
+    (u * 360.0 - 180.0, 90.0 - v * 180.0)
+}
+
+/// The point of a vector in the model's geographic axes.
+pub fn from_geographic(g: Vec3) -> Vec3 {
+    [-g[0], g[2], g[1]]
+}
+
+/// Inverse of [`from_geographic`].
+pub fn to_geographic(p: Vec3) -> Vec3 {
+    [-p[0], p[2], p[1]]
+}
+
+/// Angle (radians) between two unit vectors.
+pub fn angle(a: Vec3, b: Vec3) -> f32 {
+    libm::acosf(dot(a, b).clamp(-1.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5)
+    }
+
+    #[test]
+    fn test_lon_lat_round_trip() {
+        for (lon, lat) in [(0.0, 0.0), (-179.5, 12.0), (90.0, -45.0), (135.0, 89.0)] {
+            let (lon2, lat2) = to_lon_lat(from_lon_lat(lon, lat));
+            assert!((lon - lon2).abs() < 1e-3 && (lat - lat2).abs() < 1e-3);
+        }
+        assert!(close(from_lon_lat(0.0, 90.0), [0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn test_geographic_axes() {
+        for (lon, lat) in [(0.0f32, 0.0f32), (90.0, 0.0), (-60.0, 30.0), (170.0, -75.0)] {
+            let (lam, phi) = (lon.to_radians(), lat.to_radians());
+            let (cos_phi, sin_phi) = (libm::cosf(phi), libm::sinf(phi));
+            let g = [
+                cos_phi * libm::cosf(lam),
+                cos_phi * libm::sinf(lam),
+                sin_phi,
+            ];
+            assert!(
+                close(from_geographic(g), from_lon_lat(lon, lat)),
+                "{lon} {lat}"
+            );
+            assert!(close(to_geographic(from_geographic(g)), g));
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use crate::font::{draw_text, text_height, text_width};
+use image::{Rgb, RgbImage};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Piecewise-linear color ramp over a value domain.
+///
+/// Named maps are defined over [0, 1] and are usually [`rescaled`] to the data;
+/// maps loaded from a GMT `.cpt` file keep the file's z values.
+///
+/// [`rescaled`]: Colormap::rescaled
+#[derive(Debug, Clone)]
+pub struct Colormap {
+    segments: Vec<Segment>,
+    /// Color below the domain (GMT `B`); the first color if `None`.
+    pub background: Option<Rgb<u8>>,
+    /// Color above the domain (GMT `F`); the last color if `None`.
+    pub foreground: Option<Rgb<u8>>,
+    /// Color for NaN (GMT `N`).
+    pub nodata: Rgb<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    lo: f32,
+    hi: f32,
+    lo_color: Rgb<u8>,
+    hi_color: Rgb<u8>,
+}
+
+impl Colormap {
+    /// Continuous map through `stops` (value, color), sorted by value.
+    pub fn from_stops(stops: &[(f32, Rgb<u8>)]) -> Self {
+        assert!(stops.len() >= 2, "a colormap needs at least two stops");
+        let segments = stops
+            .windows(2)
+            .map(|w| Segment {
+                lo: w[0].0,
+                hi: w[1].0,
+                lo_color: w[0].1,
+                hi_color: w[1].1,
+            })
+            .collect();
+        Self {
+            segments,
+            background: None,
+            foreground: None,
+            nodata: Rgb([0, 0, 0]),
+        }
+    }
+
+    /// Perceptually uniform, colorblind-friendly (matplotlib's viridis).
+    pub fn viridis() -> Self {
+        Self::from_hex(&[
+            0x440154, 0x482878, 0x3e4989, 0x31688e, 0x26828e, 0x1f9e89, 0x35b779, 0x6dcd59,
+            0xb4de2c, 0xfde725,
+        ])
+    }
+
+    /// Perceptually uniform and readable with color vision deficiency.
+    pub fn cividis() -> Self {
+        Self::from_hex(&[
+            0x00224e, 0x123570, 0x3b496c, 0x575d6d, 0x707173, 0x8a8678, 0xa59c74, 0xc3b369,
+            0xe1cc55, 0xfee838,
+        ])
+    }
+
+    /// The red (young) to purple (old) rainbow customary for ocean floor age
+    /// grids.
+    pub fn age_rainbow() -> Self {
+        Self::from_stops(&[
+            (0.0, Rgb([230, 0, 0])),
+            (0.1, Rgb([255, 128, 0])),
+            (0.2, Rgb([255, 255, 0])),
+            (0.35, Rgb([0, 200, 0])),
+            (0.5, Rgb([0, 200, 200])),
+            (0.65, Rgb([0, 90, 255])),
+            (0.8, Rgb([90, 0, 200])),
+            (1.0, Rgb([160, 0, 160])),
+        ])
+    }
+
+    /// Colors evenly spaced over [0, 1].
+    fn from_hex(colors: &[u32]) -> Self {
+        let n = (colors.len() - 1) as f32;
+        let stops: Vec<(f32, Rgb<u8>)> = colors
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                let rgb = Rgb([(c >> 16) as u8, (c >> 8) as u8, c as u8]);
+                (i as f32 / n, rgb)
+            })
+            .collect();
+        Self::from_stops(&stops)
+    }
+
+    pub fn load_cpt(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        Ok(Self::parse_cpt(&text).map_err(|e| format!("{}: {e}", path.display()))?)
+    }
+
+    /// Parse a GMT color palette table (RGB color model only).
+    ///
+    /// Colors can be written as `r g b`, `r/g/b`, `#rrggbb` or a single gray
+    /// level. Annotation flags and `;` labels are ignored.
+    pub fn parse_cpt(text: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut background = None;
+        let mut foreground = None;
+        let mut nodata = Rgb([0, 0, 0]);
+
+        for (n, line) in text.lines().enumerate() {
+            let err = |msg: &str| format!("line {}: {msg}", n + 1);
+            let line = line.split(';').next().unwrap().trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                let comment = comment.replace(' ', "");
+                if let Some(model) = comment.strip_prefix("COLOR_MODEL=") {
+                    if !model.eq_ignore_ascii_case("rgb") && !model.eq_ignore_ascii_case("+rgb") {
+                        return Err(err(&format!("unsupported color model {model}")));
+                    }
+                }
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [] => {}
+                ["B", color @ ..] => background = Some(parse_cpt_color(color).map_err(err)?),
+                ["F", color @ ..] => foreground = Some(parse_cpt_color(color).map_err(err)?),
+                ["N", color @ ..] => nodata = parse_cpt_color(color).map_err(err)?,
+                _ => {
+                    // z0 color z1 color, where each color is 1 or 3 tokens
+                    let tokens = match tokens.last() {
+                        Some(&("L" | "U" | "B")) => &tokens[..tokens.len() - 1],
+                        _ => &tokens[..],
+                    };
+                    let width = match tokens.len() {
+                        8 => 3,
+                        4 => 1,
+                        _ => return Err(err("expected `z0 color z1 color`")),
+                    };
+                    let z = |t: &str| t.parse::<f32>().map_err(|_| err("invalid z value"));
+                    segments.push(Segment {
+                        lo: z(tokens[0])?,
+                        lo_color: parse_cpt_color(&tokens[1..1 + width]).map_err(err)?,
+                        hi: z(tokens[1 + width])?,
+                        hi_color: parse_cpt_color(&tokens[2 + width..]).map_err(err)?,
+                    });
+                }
+            }
+        }
+
+        if segments.is_empty() {
+            return Err("no color slices found".into());
+        }
+        segments.sort_by(|a, b| a.lo.total_cmp(&b.lo));
+        Ok(Self {
+            segments,
+            background,
+            foreground,
+            nodata,
+        })
+    }
+
+    /// Lowest and highest value of the ramp.
+    pub fn domain(&self) -> (f32, f32) {
+        (self.segments[0].lo, self.segments.last().unwrap().hi)
+    }
+
+    /// The same ramp stretched linearly over [min, max].
+    pub fn rescaled(&self, min: f32, max: f32) -> Self {
+        let (d0, d1) = self.domain();
+        let span = if d1 > d0 { d1 - d0 } else { 1.0 };
+        let f = |z: f32| min + (z - d0) / span * (max - min);
+        let mut out = self.clone();
+        for s in &mut out.segments {
+            s.lo = f(s.lo);
+            s.hi = f(s.hi);
+        }
+        out
+    }
+
+    /// A discrete map with one flat color per interval between consecutive
+    /// `edges`, taken from the middle of the interval (e.g. 10 Myr age bands).
+    pub fn stepped(&self, edges: &[f32]) -> Self {
+        assert!(
+            edges.len() >= 2,
+            "a stepped colormap needs at least two edges"
+        );
+        let segments = edges
+            .windows(2)
+            .map(|w| {
+                let c = self.color(0.5 * (w[0] + w[1]));
+                Segment {
+                    lo: w[0],
+                    hi: w[1],
+                    lo_color: c,
+                    hi_color: c,
+                }
+            })
+            .collect();
+        Self {
+            segments,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_nodata(mut self, nodata: Rgb<u8>) -> Self {
+        self.nodata = nodata;
+        self
+    }
+
+    pub fn color(&self, v: f32) -> Rgb<u8> {
+        if v.is_nan() {
+            return self.nodata;
+        }
+        let first = self.segments[0];
+        let last = *self.segments.last().unwrap();
+        if v < first.lo {
+            return self.background.unwrap_or(first.lo_color);
+        }
+        if v > last.hi {
+            return self.foreground.unwrap_or(last.hi_color);
+        }
+        let k = self
+            .segments
+            .partition_point(|s| s.hi < v)
+            .min(self.segments.len() - 1);
+        let s = self.segments[k];
+        let t = if s.hi > s.lo {
+            ((v - s.lo) / (s.hi - s.lo)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgb([
+            mix(s.lo_color[0], s.hi_color[0]),
+            mix(s.lo_color[1], s.hi_color[1]),
+            mix(s.lo_color[2], s.hi_color[2]),
+        ])
+    }
+
+    /// The ramp sampled at `width` evenly spaced values over its domain, as a
+    /// one-pixel-high image (e.g. a lookup texture for the viewer).
+    pub fn strip(&self, width: u32) -> RgbImage {
+        let (d0, d1) = self.domain();
+        RgbImage::from_fn(width, 1, |x, _| {
+            self.color(d0 + (d1 - d0) * (x as f32 + 0.5) / width as f32)
+        })
+    }
+
+    /// Values worth labelling on a legend: the slice boundaries of a stepped
+    /// map, otherwise `n` evenly spaced values.
+    pub fn ticks(&self, n: usize) -> Vec<f32> {
+        let stepped = self.segments.iter().all(|s| s.lo_color == s.hi_color);
+        if stepped && self.segments.len() < 2 * n {
+            let mut ticks: Vec<f32> = self.segments.iter().map(|s| s.lo).collect();
+            ticks.push(self.domain().1);
+            return ticks;
+        }
+        let (d0, d1) = self.domain();
+        (0..n)
+            .map(|i| d0 + (d1 - d0) * i as f32 / (n - 1).max(1) as f32)
+            .collect()
+    }
+}
+
+fn parse_cpt_color(tokens: &[&str]) -> Result<Rgb<u8>, &'static str> {
+    let channel = |t: &str| t.parse::<f32>().map(|c| c.round().clamp(0.0, 255.0) as u8);
+    let invalid = "invalid color";
+    match tokens {
+        [r, g, b] => Ok(Rgb([
+            channel(r).map_err(|_| invalid)?,
+            channel(g).map_err(|_| invalid)?,
+            channel(b).map_err(|_| invalid)?,
+        ])),
+        [hex] if hex.starts_with('#') && hex.len() == 7 => {
+            let c = u32::from_str_radix(&hex[1..], 16).map_err(|_| invalid)?;
+            Ok(Rgb([(c >> 16) as u8, (c >> 8) as u8, c as u8]))
+        }
+        [rgb] if rgb.contains('/') => match rgb.split('/').collect::<Vec<_>>().as_slice() {
+            [r, g, b] => parse_cpt_color(&[r, g, b]),
+            _ => Err(invalid),
+        },
+        [gray] => {
+            let g = channel(gray).map_err(|_| invalid)?;
+            Ok(Rgb([g, g, g]))
+        }
+        _ => Err(invalid),
+    }
+}
+
+/// Draw a horizontal colorbar for `cmap` with its top-left corner at (x, y),
+/// with `title` above it and tick labels below, over a dark backdrop.
+pub fn draw_colorbar(
+    img: &mut RgbImage,
+    cmap: &Colormap,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    title: &str,
+) {
+    let scale = (height / 12).max(1);
+    let pad = 2 * scale;
+    let line = text_height(scale) + pad;
+    let (d0, d1) = cmap.domain();
+    let ticks = cmap.ticks(5);
+    let labels: Vec<String> = ticks.iter().map(|&t| tick_label(t, d1 - d0)).collect();
+    let half_label = labels
+        .iter()
+        .map(|l| text_width(l, scale) / 2)
+        .max()
+        .unwrap_or(0);
+
+    // backdrop
+    let box_w = width + 2 * (pad + half_label);
+    let box_h = line + height + pad + line;
+    let (iw, ih) = img.dimensions();
+    for by in y..(y + box_h).min(ih) {
+        for bx in x..(x + box_w).min(iw) {
+            img.put_pixel(bx, by, Rgb([0, 0, 0]));
+        }
+    }
+
+    let white = Rgb([255, 255, 255]);
+    let bar_x = x + pad + half_label;
+    draw_text(img, bar_x as i32, (y + pad) as i32, title, scale, white);
+
+    let bar_y = y + line;
+    for bx in 0..width {
+        let v = d0 + (d1 - d0) * (bx as f32 + 0.5) / width as f32;
+        let c = cmap.color(v);
+        for by in bar_y..(bar_y + height).min(ih) {
+            if bar_x + bx < iw {
+                img.put_pixel(bar_x + bx, by, c);
+            }
+        }
+    }
+
+    for (t, label) in ticks.iter().zip(&labels) {
+        let tx = bar_x as f32 + (t - d0) / (d1 - d0) * width as f32;
+        let lx = tx as i32 - text_width(label, scale) as i32 / 2;
+        draw_text(img, lx, (bar_y + height + pad) as i32, label, scale, white);
+    }
+}
+
+fn tick_label(v: f32, span: f32) -> String {
+    if span >= 10.0 {
+        format!("{v:.0}")
+    } else if span >= 1.0 {
+        format!("{v:.1}")
+    } else {
+        format!("{v:.2}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_maps_endpoints() {
+        let v = Colormap::viridis();
+        assert_eq!(v.color(0.0), Rgb([0x44, 0x01, 0x54]));
+        assert_eq!(v.color(1.0), Rgb([0xfd, 0xe7, 0x25]));
+        assert_eq!(v.color(f32::NAN), Rgb([0, 0, 0]));
+
+        let ages = Colormap::age_rainbow().rescaled(0.0, 200.0);
+        assert_eq!(ages.domain(), (0.0, 200.0));
+        assert_eq!(ages.color(-5.0), ages.color(0.0));
+        assert_eq!(ages.color(250.0), ages.color(200.0));
+    }
+
+    #[test]
+    fn test_parse_cpt() {
+        let cpt = "# COLOR_MODEL = RGB\n\
+                   0 255 0 0 10 0 255 0 ; young\n\
+                   10 0/255/0 20 #0000ff U\n\
+                   B 10 10 10\n\
+                   F 250/250/250\n\
+                   N 128\n";
+        let cmap = Colormap::parse_cpt(cpt).unwrap();
+        assert_eq!(cmap.domain(), (0.0, 20.0));
+        assert_eq!(cmap.color(0.0), Rgb([255, 0, 0]));
+        assert_eq!(cmap.color(5.0), Rgb([128, 128, 0]));
+        assert_eq!(cmap.color(20.0), Rgb([0, 0, 255]));
+        assert_eq!(cmap.color(-1.0), Rgb([10, 10, 10]));
+        assert_eq!(cmap.color(21.0), Rgb([250, 250, 250]));
+        assert_eq!(cmap.color(f32::NAN), Rgb([128, 128, 128]));
+
+        assert!(Colormap::parse_cpt("# COLOR_MODEL = HSV\n0 0 1 1 1 0 1 1").is_err());
+        assert!(Colormap::parse_cpt("0 1 2").is_err());
+    }
+
+    #[test]
+    fn test_stepped() {
+        let cmap = Colormap::viridis()
+            .rescaled(0.0, 30.0)
+            .stepped(&[0.0, 10.0, 20.0, 30.0]);
+        assert_eq!(cmap.color(1.0), cmap.color(9.0));
+        assert_ne!(cmap.color(9.0), cmap.color(11.0));
+        assert_eq!(cmap.ticks(5), vec![0.0, 10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn test_draw_colorbar() {
+        let mut img = RgbImage::new(400, 200);
+        let cmap = Colormap::cividis().rescaled(0.0, 280.0);
+        draw_colorbar(&mut img, &cmap, (10, 10), (300, 24), "Age (Ma)");
+        // a smooth ramp of many distinct colors, starting with the low end of the map
+        let mut colors: Vec<_> = img.pixels().map(|p| p.0).collect();
+        colors.sort();
+        colors.dedup();
+        assert!(colors.len() > 100, "only {} colors drawn", colors.len());
+        assert!(colors.contains(&cmap.color(0.5 / 300.0 * 280.0).0));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::geometry::GeoGrid;
+use image::{GrayImage, Luma, RgbImage};
+use rayon::prelude::*;
+
+/// How a layer's colors combine with the colors below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The layer replaces what's below, weighted by opacity and mask (alpha).
+    Normal,
+    /// Darkens: white is neutral. Good for shading by relief.
+    Multiply,
+    /// Lightens: black is neutral.
+    Screen,
+    /// Multiply in the shadows, screen in the highlights of the layer below;
+    /// mid-gray is neutral.
+    Overlay,
+}
+
+/// One image stacked by [`composite`].
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub image: RgbImage,
+    pub mode: BlendMode,
+    /// 0 (invisible) to 1.
+    pub opacity: f32,
+    /// Where the layer applies, 0 (not at all) to 255 (fully); everywhere if `None`.
+    pub mask: Option<GrayImage>,
+}
+
+impl Layer {
+    pub fn new(image: RgbImage, mode: BlendMode) -> Self {
+        Self {
+            image,
+            mode,
+            opacity: 1.0,
+            mask: None,
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_mask(mut self, mask: GrayImage) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+}
+
+/// Stack `layers` over `base`, bottom to top. Layers and masks must have the
+/// same dimensions as `base`.
+pub fn composite(mut base: RgbImage, layers: &[Layer]) -> RgbImage {
+    let (w, h) = base.dimensions();
+    for layer in layers {
+        assert_eq!(layer.image.dimensions(), (w, h), "layer size mismatch");
+        if let Some(mask) = &layer.mask {
+            assert_eq!(mask.dimensions(), (w, h), "mask size mismatch");
+        }
+        let over = layer.image.as_raw();
+        let mask = layer.mask.as_ref().map(|m| m.as_raw());
+
+        base.par_chunks_mut(3).enumerate().for_each(|(i, px)| {
+            let coverage = mask.map_or(1.0, |m| m[i] as f32 / 255.0) * layer.opacity;
+            if coverage <= 0.0 {
+                return;
+            }
+            for c in 0..3 {
+                let a = px[c] as f32 / 255.0;
+                let b = over[3 * i + c] as f32 / 255.0;
+                let blended = blend(layer.mode, a, b);
+                px[c] = ((a + (blended - a) * coverage) * 255.0).round() as u8;
+            }
+        });
+    }
+    base
+}
+
+#[inline]
+fn blend(mode: BlendMode, a: f32, b: f32) -> f32 {
+    match mode {
+        BlendMode::Normal => b,
+        BlendMode::Multiply => a * b,
+        BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+        BlendMode::Overlay => {
+            if a < 0.5 {
+                2.0 * a * b
+            } else {
+                1.0 - 2.0 * (1.0 - a) * (1.0 - b)
+            }
+        }
+    }
+}
+
+/// Mask of the grid cells where `keep` holds, rows from +90° down (e.g. only
+/// oceanic cells: `|age| !age.is_nan()`).
+pub fn field_mask<F>(grid: &GeoGrid, values: &[f32], keep: F) -> GrayImage
+where
+    F: Fn(f32) -> bool + Sync,
+{
+    let raw: Vec<u8> = values
+        .par_iter()
+        .map(|&v| if keep(v) { 255 } else { 0 })
+        .collect();
+    GrayImage::from_raw(grid.nx as u32, grid.ny as u32, raw).unwrap()
+}
+
+/// Gray image as RGB, for use as a layer.
+pub fn gray_to_rgb(img: &GrayImage) -> RgbImage {
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let Luma([l]) = *img.get_pixel(x, y);
+        image::Rgb([l, l, l])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn solid(c: [u8; 3]) -> RgbImage {
+        RgbImage::from_pixel(2, 1, Rgb(c))
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let base = solid([200, 100, 0]);
+
+        let multiply = composite(
+            base.clone(),
+            &[Layer::new(solid([255; 3]), BlendMode::Multiply)],
+        );
+        assert_eq!(multiply, base, "white is neutral for multiply");
+
+        let screen = composite(
+            base.clone(),
+            &[Layer::new(solid([0; 3]), BlendMode::Screen)],
+        );
+        assert_eq!(screen, base, "black is neutral for screen");
+
+        let overlay = composite(
+            base.clone(),
+            &[Layer::new(solid([128; 3]), BlendMode::Overlay)],
+        );
+        for (a, b) in overlay.as_raw().iter().zip(base.as_raw()) {
+            assert!(a.abs_diff(*b) <= 1, "mid-gray is neutral for overlay");
+        }
+    }
+
+    #[test]
+    fn test_opacity_rounds() {
+        let half = Layer::new(solid([255; 3]), BlendMode::Normal).with_opacity(0.5);
+        let out = composite(solid([0; 3]), &[half]);
+        assert_eq!(out.get_pixel(0, 0), &Rgb([128, 128, 128]));
+    }
+
+    #[test]
+    fn test_mask_limits_layer() {
+        let mask = GrayImage::from_raw(2, 1, vec![255, 0]).unwrap();
+        let layer = Layer::new(solid([0; 3]), BlendMode::Normal).with_mask(mask);
+        let out = composite(solid([90; 3]), &[layer]);
+        assert_eq!(out.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(out.get_pixel(1, 0), &Rgb([90, 90, 90]));
+    }
+}
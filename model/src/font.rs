@@ -0,0 +1,133 @@
+use image::{ImageBuffer, Pixel, Rgb, RgbImage};
+
+const GLYPH_W: u32 = 5;
+const GLYPH_H: u32 = 7;
+
+/// Width in pixels of `text` drawn at `scale` with [`draw_text`].
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let n = text.chars().count() as u32;
+    (n * (GLYPH_W + 1)).saturating_sub(1) * scale
+}
+
+/// Height in pixels of one line of text drawn at `scale`.
+pub fn text_height(scale: u32) -> u32 {
+    GLYPH_H * scale
+}
+
+/// Draw `text` with its top-left corner at (x, y) using a built-in 5×7 bitmap
+/// font, each font pixel `scale` image pixels wide. Works on any image type,
+/// e.g. a `GrayImage` mask. Lowercase letters are drawn as uppercase;
+/// characters outside the font are drawn as `?`. Pixels falling outside the
+/// image are skipped.
+pub fn draw_text<P: Pixel>(
+    img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    x: i32,
+    y: i32,
+    text: &str,
+    scale: u32,
+    color: P,
+) {
+    let (w, h) = img.dimensions();
+    let s = scale as i32;
+    for (k, ch) in text.chars().enumerate() {
+        let x0 = x + k as i32 * (GLYPH_W as i32 + 1) * s;
+        for (row, bits) in glyph(ch).iter().enumerate() {
+            for col in 0..GLYPH_W as i32 {
+                if bits & (1 << (GLYPH_W as i32 - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..s {
+                    for dx in 0..s {
+                        let px = x0 + col * s + dx;
+                        let py = y + row as i32 * s + dy;
+                        if px >= 0 && py >= 0 && (px as u32) < w && (py as u32) < h {
+                            img.put_pixel(px as u32, py as u32, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draw `text` over a filled box with `pad` pixels of margin, for legibility on
+/// busy maps.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_label(
+    img: &mut RgbImage,
+    x: i32,
+    y: i32,
+    text: &str,
+    scale: u32,
+    color: Rgb<u8>,
+    background: Rgb<u8>,
+    pad: u32,
+) {
+    let (w, h) = img.dimensions();
+    let bw = text_width(text, scale) + 2 * pad;
+    let bh = text_height(scale) + 2 * pad;
+    for by in 0..bh as i32 {
+        for bx in 0..bw as i32 {
+            let (px, py) = (x + bx, y + by);
+            if px >= 0 && py >= 0 && (px as u32) < w && (py as u32) < h {
+                img.put_pixel(px as u32, py as u32, background);
+            }
+        }
+    }
+    draw_text(img, x + pad as i32, y + pad as i32, text, scale, color);
+}
+
+#[rustfmt::skip]
+fn glyph(ch: char) -> [u8; GLYPH_H as usize] {
+    match ch.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '°' => [0x0C, 0x12, 0x12, 0x0C, 0x00, 0x00, 0x00],
+        _   => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,51 @@
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::Path;
+
+pub use small_world_math::geojson::{parse_geojson, Geometry, LonLat};
+
+/// Read every geometry of a GeoJSON file (a `FeatureCollection`, `Feature` or
+/// bare geometry). Shapefiles can be converted first, e.g. with
+/// `ogr2ogr -f GeoJSON coastlines.geojson coastlines.shp`.
+pub fn load_geojson(path: &Path) -> Result<Vec<Geometry>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(parse_geojson(&text).map_err(|e| format!("{}: {e}", path.display()))?)
+}
+
+/// A `FeatureCollection` with a `LineString` feature per line, coordinates
+/// rounded to 0.001°.
+pub fn lines_to_geojson(lines: &[Vec<LonLat>]) -> String {
+    let round = |v: f32| (v as f64 * 1000.0).round() / 1000.0;
+    let features: Vec<Value> = lines
+        .iter()
+        .map(|line| {
+            let coordinates: Vec<[f64; 2]> = line
+                .iter()
+                .map(|&(lon, lat)| [round(lon), round(lat)])
+                .collect();
+            json!({
+                "type": "Feature",
+                "properties": {},
+                "geometry": {"type": "LineString", "coordinates": coordinates},
+            })
+        })
+        .collect();
+    json!({"type": "FeatureCollection", "features": features}).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_round_trip() {
+        let lines = vec![vec![(-10.5, 20.25), (0.0001, 21.0)], vec![(179.0, -3.0)]];
+        assert_eq!(
+            parse_geojson(&lines_to_geojson(&lines)).unwrap(),
+            vec![
+                Geometry::LineString(vec![(-10.5, 20.25), (0.0, 21.0)]),
+                Geometry::LineString(vec![(179.0, -3.0)]),
+            ]
+        );
+    }
+}
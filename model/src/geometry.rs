@@ -34,6 +34,19 @@ pub fn lon_of(i: usize, nx: usize) -> f32 {
     d2r(-180.0 + 360.0 * u)
 }
 
+// inverse of `lat_of`: row of the cell containing φ (radians), clamped to the grid
+#[inline]
+pub fn row_of(phi: f32, ny: usize) -> usize {
+    let v = (90.0 - phi.to_degrees()) / 180.0;
+    ((v * ny as f32).floor().max(0.0) as usize).min(ny - 1)
+}
+// inverse of `lon_of`: column of the cell containing λ (radians), wrapping at the dateline
+#[inline]
+pub fn col_of(lam: f32, nx: usize) -> usize {
+    let u = (lam.to_degrees() + 180.0) / 360.0;
+    wrap_i((u * nx as f32).floor() as isize, nx)
+}
+
 /// Great-circle central angle (radians) via haversine (stable for small angles).
 #[inline]
 fn central_angle(phi1: f32, lam1: f32, phi2: f32, lam2: f32) -> f32 {
@@ -78,7 +91,8 @@ pub fn neighbors_within(grid: &GeoGrid, center_idx: usize, d_meters: f32) -> Vec
         let i_pad_loc = ((delta / cos_loc).min(std::f32::consts::PI) / dlam_pix).ceil() as isize;
 
         let i_start = i0 as isize - i_pad.min(i_pad_loc);
-        let i_end = i0 as isize + i_pad.min(i_pad_loc);
+        // never visit a column twice when the window wraps all the way around
+        let i_end = (i0 as isize + i_pad.min(i_pad_loc)).min(i_start + nx as isize - 1);
 
         for ii in i_start..=i_end {
             let i = wrap_i(ii, nx);
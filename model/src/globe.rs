@@ -0,0 +1,211 @@
+use crate::geojson::LonLat;
+use crate::geometry::{col_of, row_of};
+use image::{Rgb, RgbImage};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use rayon::prelude::*;
+use small_world_math::{quat, sphere};
+
+/// How [`render_globe`] projects the globe onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraProjection {
+    Perspective,
+    /// Parallel rays: the globe from infinitely far, framed as the
+    /// perspective view would be at the globe's center.
+    Orthographic,
+}
+
+/// Camera of [`render_globe`], set up as in the viewer's `Globe`: the camera
+/// sits on +Z looking down -Z with Y up, and the globe's model space is that
+/// of [`small_world_math::sphere`].
+#[derive(Debug, Clone, Copy)]
+pub struct GlobeCamera {
+    /// Rotation from the globe's model space to the camera's; the viewer's
+    /// `(x, y, z, w)` is `Quaternion::new(w, x, y, z)`. The identity looks
+    /// at (90°E, 0°) with north up.
+    pub orient: UnitQuaternion<f32>,
+    /// Distance of the camera from the globe's center, in present-day radii.
+    pub dist: f32,
+    /// Radius of the globe relative to the present day's.
+    pub radius: f32,
+    /// Vertical field of view, degrees.
+    pub fovy: f32,
+    pub projection: CameraProjection,
+    pub background: Rgb<u8>,
+    /// Samples per pixel along each axis, to smooth the limb.
+    pub supersample: u32,
+}
+
+impl GlobeCamera {
+    /// Looking down on `center` (degrees) with north up, from `dist` radii,
+    /// with the viewer's field of view.
+    pub fn looking_at((lon, lat): LonLat, dist: f32) -> Self {
+        let [x, y, z, w] = quat::looking_at(lon, lat);
+        Self {
+            orient: UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+            dist,
+            radius: 1.0,
+            fovy: 60.0,
+            projection: CameraProjection::Perspective,
+            background: Rgb([0, 0, 0]),
+            supersample: 2,
+        }
+    }
+
+    /// The globe turned `degrees` about its axis, west to east, as the
+    /// viewer's auto-rotation turns it: the view moves west.
+    pub fn rotated(mut self, degrees: f32) -> Self {
+        let turn = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), degrees.to_radians());
+        self.orient *= turn;
+        self
+    }
+
+    /// The point at the center of the view, degrees.
+    pub fn center(&self) -> LonLat {
+        to_lon_lat(self.orient.inverse_transform_vector(&Vector3::z()))
+    }
+
+    // First point of the unit globe (model space) hit by the ray through
+    // `(x, y)` in normalized device coordinates.
+    fn hit(&self, x: f32, y: f32, aspect: f32) -> Option<Vector3<f32>> {
+        let f = (self.fovy.to_radians() / 2.0).tan();
+        let (origin, dir) = match self.projection {
+            CameraProjection::Perspective => (
+                Vector3::zeros(),
+                Vector3::new(x * f * aspect, y * f, -1.0).normalize(),
+            ),
+            CameraProjection::Orthographic => (
+                Vector3::new(x * f * aspect * self.dist, y * f * self.dist, 0.0),
+                -Vector3::z(),
+            ),
+        };
+        let center = Vector3::new(0.0, 0.0, -self.dist);
+        let oc = origin - center;
+        let b = oc.dot(&dir);
+        let disc = b * b - (oc.norm_squared() - self.radius * self.radius);
+        if disc < 0.0 {
+            return None;
+        }
+        let t = -b - disc.sqrt();
+        if t < 0.0 {
+            return None;
+        }
+        let p = (origin + t * dir - center) / self.radius;
+        Some(self.orient.inverse_transform_vector(&p))
+    }
+}
+
+/// Render the globe covered with a north-up equirectangular image, as seen by
+/// `camera`, e.g. for frames of a rotating globe without a browser. Pixels
+/// take the nearest cell of `img`, averaged over the camera's samples.
+pub fn render_globe(img: &RgbImage, camera: &GlobeCamera, width: u32, height: u32) -> RgbImage {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let src = img.as_raw();
+    let n = camera.supersample.max(1);
+    let aspect = width as f32 / height.max(1) as f32;
+    let raw: Vec<u8> = (0..width as usize * height as usize)
+        .into_par_iter()
+        .flat_map_iter(|k| {
+            let (i, j) = ((k % width as usize) as f32, (k / width as usize) as f32);
+            let mut sum = [0u32; 3];
+            for s in 0..n * n {
+                let dx = ((s % n) as f32 + 0.5) / n as f32;
+                let dy = ((s / n) as f32 + 0.5) / n as f32;
+                let x = 2.0 * (i + dx) / width as f32 - 1.0;
+                let y = 1.0 - 2.0 * (j + dy) / height as f32;
+                let px = camera.hit(x, y, aspect).map_or(camera.background.0, |p| {
+                    let (lon, lat) = to_lon_lat(p);
+                    let c = 3 * (row_of(lat.to_radians(), h) * w + col_of(lon.to_radians(), w));
+                    [src[c], src[c + 1], src[c + 2]]
+                });
+                for (total, v) in sum.iter_mut().zip(px) {
+                    *total += v as u32;
+                }
+            }
+            sum.map(|total| ((total + n * n / 2) / (n * n)) as u8)
+                .into_iter()
+        })
+        .collect();
+    RgbImage::from_raw(width, height, raw).unwrap()
+}
+
+fn to_lon_lat(p: Vector3<f32>) -> LonLat {
+    sphere::to_lon_lat([p.x, p.y, p.z])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_matches_viewer_conventions() {
+        // the identity looks at 90°E, as in the viewer
+        let camera = GlobeCamera::looking_at((90.0, 0.0), 3.0);
+        assert!(camera.orient.angle() < 1e-5);
+
+        let camera = GlobeCamera::looking_at((-30.0, 40.0), 3.0);
+        let (lon, lat) = camera.center();
+        assert!((lon + 30.0).abs() < 1e-3 && (lat - 40.0).abs() < 1e-3);
+        // north up: the pole is straight above the center
+        let pole = camera.orient * Vector3::y();
+        assert!(pole.x.abs() < 1e-5 && pole.y > 0.0);
+
+        let (lon, _) = camera.rotated(10.0).center();
+        assert!((lon + 40.0).abs() < 1e-3, "{lon}");
+    }
+
+    #[test]
+    fn test_rays_match_viewer_projection() {
+        // where the viewer's model-view-projection puts a lon/lat on the
+        // screen, the renderer's ray finds the same lon/lat
+        use small_world_math::mat4;
+        let mut camera = GlobeCamera::looking_at((-40.0, 20.0), 2.2);
+        camera.radius = 0.8;
+        let aspect = 1.5;
+        let q = camera.orient.coords;
+        let model = mat4::mul(
+            &mat4::mul(
+                &mat4::translate_z(-camera.dist),
+                &mat4::from_quat([q.x, q.y, q.z, q.w]),
+            ),
+            &mat4::scale(camera.radius),
+        );
+        let mvp = mat4::mul(
+            &mat4::perspective(camera.fovy.to_radians(), aspect, 0.01, 100.0),
+            &model,
+        );
+        for (lon, lat) in [(-40.0, 20.0), (-30.0, 35.0), (-60.0, 5.0), (-45.0, -10.0)] {
+            let [x, y] = mat4::project(&mvp, sphere::from_lon_lat(lon, lat)).unwrap();
+            let hit = camera.hit(x, y, aspect).expect("on the globe");
+            let (lon2, lat2) = to_lon_lat(hit);
+            assert!(
+                (lon - lon2).abs() < 0.01 && (lat - lat2).abs() < 0.01,
+                "{lon} {lat} -> {lon2} {lat2}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_globe() {
+        // east and west hemispheres in two colors
+        let img = RgbImage::from_fn(36, 18, |x, _| {
+            if x < 18 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let mut camera = GlobeCamera::looking_at((0.0, 0.0), 3.0);
+        camera.background = Rgb([0, 255, 0]);
+        for projection in [
+            CameraProjection::Perspective,
+            CameraProjection::Orthographic,
+        ] {
+            camera.projection = projection;
+            let out = render_globe(&img, &camera, 64, 48);
+            assert_eq!(out.get_pixel(0, 0), &Rgb([0, 255, 0]), "{projection:?}");
+            // west on the left, east on the right
+            assert_eq!(out.get_pixel(24, 24), &Rgb([255, 0, 0]), "{projection:?}");
+            assert_eq!(out.get_pixel(40, 24), &Rgb([0, 0, 255]), "{projection:?}");
+        }
+    }
+}
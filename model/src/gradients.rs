@@ -7,7 +7,12 @@ use rayon::prelude::*;
 use std::error::Error;
 use std::path::Path;
 
-pub fn convert_nc_to_gradient_map(nc_path: &Path) -> Result<RgbImage, Box<dyn Error>> {
+/// Color each cell by the bearing of the age gradient, fit over the cells
+/// within `neighbor_radius` meters of it.
+pub fn convert_nc_to_gradient_map(
+    nc_path: &Path,
+    neighbor_radius: f32,
+) -> Result<RgbImage, Box<dyn Error>> {
     let earth_radius = 6_371_008.8; // meters
     let age_var_name = "z";
     let mut reader = FileReader::open(nc_path)?;
@@ -58,8 +63,11 @@ pub fn convert_nc_to_gradient_map(nc_path: &Path) -> Result<RgbImage, Box<dyn Er
                 // Distance between meridians at 80°N:         322 meters
                 // Distance between meridians at 85°N:         162 meters
                 // Distance between meridians at 89°N:          32 meters
-                let neighbors = neighbors_within(&grid, i, 2620.0);
-                let g = gradient_tangent(&grid, i, &neighbors, &age_data).unwrap();
+                let neighbors = neighbors_within(&grid, i, neighbor_radius);
+                // too few neighbors to fit, e.g. a radius under the cell size
+                let Some(g) = gradient_tangent(&grid, i, &neighbors, &age_data) else {
+                    return (Rgb([0, 0, 0]), area_lookup[y]);
+                };
                 let (_, bearing) = gradient_magnitude_bearing(g);
                 let (r, g, b) = gradient_to_rgb(0.0001, bearing, 0.0001);
                 (Rgb([r, g, b]), area_lookup[y])
@@ -87,6 +95,14 @@ pub fn convert_nc_to_gradient_map(nc_path: &Path) -> Result<RgbImage, Box<dyn Er
     Ok(img)
 }
 
+/// The smallest neighbor radius (meters) of [`convert_nc_to_gradient_map`]
+/// that reaches the adjacent cells of every cell on `grid`: the larger of the
+/// spacing between parallels and between meridians at the equator.
+pub fn min_neighbor_radius(grid: &GeoGrid) -> f32 {
+    use std::f32::consts::PI;
+    (PI * grid.radius / grid.ny as f32).max(2.0 * PI * grid.radius / grid.nx as f32)
+}
+
 /// Compute the local tangent-plane gradient (east,north) in scalar units per meter.
 ///
 /// Returns `None` if fewer than 3 valid samples or if the fit matrix is singular.
@@ -174,6 +190,24 @@ pub fn gradient_tangent(
     Some(Vector2::new(a, b))
 }
 
+/// Tangent-plane gradient (east, north) per meter of every cell, fitted over
+/// the valid cells within `neighbor_radius` meters. NaN where the cell has no
+/// value or too few valid neighbors.
+pub fn gradient_field(grid: &GeoGrid, values: &[f32], neighbor_radius: f32) -> Vec<Vector2<f32>> {
+    let nan = Vector2::new(f32::NAN, f32::NAN);
+    (0..values.len())
+        .into_par_iter()
+        .map(|i| {
+            if values[i].is_nan() {
+                return nan;
+            }
+            let mut neighbors = neighbors_within(grid, i, neighbor_radius);
+            neighbors.retain(|&k| !values[k].is_nan());
+            gradient_tangent(grid, i, &neighbors, values).unwrap_or(nan)
+        })
+        .collect()
+}
+
 /// Convert tangent gradient (east,north) into magnitude and bearing.
 /// Returns (magnitude, bearing_radians)
 pub fn gradient_magnitude_bearing(g: Vector2<f32>) -> (f32, f32) {
@@ -219,6 +253,23 @@ mod gradient_tests {
 
     use std::f32::consts::PI;
 
+    #[test]
+    fn test_min_neighbor_radius() {
+        let grid = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 6_371_000.0,
+        };
+        let r = min_neighbor_radius(&grid);
+        for i in 0..grid.nx * grid.ny {
+            let neighbors = neighbors_within(&grid, i, r * 1.001);
+            assert!(neighbors.len() >= 3, "{i}: {neighbors:?}");
+        }
+        // under it, cells at the equator only have themselves
+        let equator = (grid.ny / 2) * grid.nx;
+        assert_eq!(neighbors_within(&grid, equator, r * 0.5), [equator]);
+    }
+
     #[test]
     fn test_gradient_flat_plane() {
         let grid = GeoGrid {
@@ -1,3 +1,5 @@
+use crate::colormap::{draw_colorbar, Colormap};
+use crate::composite::{composite, BlendMode, Layer};
 use image::{ImageReader, Rgb, RgbImage};
 use netcdf3::FileReader;
 use rayon::prelude::*;
@@ -6,7 +8,41 @@ use std::fs;
 use std::path::Path;
 use webp::Encoder;
 
-pub fn convert_nc_to_png(nc_path: &Path) -> Result<RgbImage, Box<dyn Error>> {
+/// Values the colormap of [`ColorOptions`] is stretched over.
+#[derive(Debug, Clone, Copy)]
+pub enum ValueRange {
+    /// The minimum and maximum of the data.
+    Data,
+    Fixed(f32, f32),
+    /// The colormap's own values, e.g. the z values of a `.cpt` file.
+    Colormap,
+}
+
+/// How [`convert_nc_to_png`] turns values into colors.
+#[derive(Debug, Clone)]
+pub struct ColorOptions {
+    pub colormap: Colormap,
+    pub range: ValueRange,
+    /// Title of a colorbar drawn in the bottom-left corner, if any.
+    pub legend: Option<String>,
+}
+
+impl Default for ColorOptions {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::age_rainbow(),
+            range: ValueRange::Data,
+            legend: None,
+        }
+    }
+}
+
+/// The grid of a NetCDF file in colors, south up like the viewer's single
+/// textures; flip it vertically for a north-up map.
+pub fn convert_nc_to_png(
+    nc_path: &Path,
+    options: &ColorOptions,
+) -> Result<RgbImage, Box<dyn Error>> {
     let var_name = "z";
 
     // Open + read metadata
@@ -22,7 +58,37 @@ pub fn convert_nc_to_png(nc_path: &Path) -> Result<RgbImage, Box<dyn Error>> {
     println!("Grid: {} x {}", nx, ny);
 
     let data: Vec<f32> = reader.read_var_f32(var_name)?;
+    Ok(color_grid(&data, (nx, ny), options))
+}
+
+/// Color a row-major grid of `nx`×`ny` values, row 0 at 90°N as
+/// `read_age_grid` reads them, into a south-up image.
+fn color_grid(data: &[f32], (nx, ny): (usize, usize), options: &ColorOptions) -> RgbImage {
+    let cmap = stretched_colormap(data, options);
+
+    // Compute all pixels in parallel
+    let pixels: Vec<Rgb<u8>> = data.par_iter().map(|&v| cmap.color(v)).collect();
+
+    // Convert to image
+    let mut img = RgbImage::new(nx as u32, ny as u32);
+    for (i, px) in pixels.into_iter().enumerate() {
+        let x = (i % nx) as u32;
+        let y = (i / nx) as u32;
+        img.put_pixel(x, (ny as u32 - 1) - y, px);
+    }
+
+    // A bar `h` high, `h` from the left edge and 4·h from the bottom, left
+    // out of grids too small to leave a margin of `h` above it
+    let (w, h) = ((nx / 4) as u32, (ny / 40).max(8) as u32);
+    let y = (ny as u32).checked_sub(4 * h).filter(|&y| y >= h);
+    if let (Some(title), Some(y)) = (&options.legend, y) {
+        draw_colorbar(&mut img, &cmap, (h, y), (w, h), title);
+    }
+    img
+}
 
+/// The colormap of `options`, stretched over its range of `data`.
+fn stretched_colormap(data: &[f32], options: &ColorOptions) -> Colormap {
     // Find min and max in parallel (ignoring NaNs)
     let (min, max) = data
         .par_iter()
@@ -33,32 +99,13 @@ pub fn convert_nc_to_png(nc_path: &Path) -> Result<RgbImage, Box<dyn Error>> {
         )
         .reduce(|| (f32::MAX, f32::MIN), |a, b| (a.0.min(b.0), a.1.max(b.1)));
 
-    let span = if max > min { max - min } else { 1.0 };
-
-    // Compute all pixels in parallel
-    let pixels: Vec<Rgb<u8>> = data
-        .par_iter()
-        .map(|&v| {
-            if v.is_nan() {
-                Rgb([0, 0, 0])
-            } else {
-                let t = ((v - min) / span).clamp(0.0, 1.0);
-                let r = (255.0 * t) as u8;
-                let g = (255.0 * (1.0 - ((t - 0.5).abs() * 2.0).clamp(0.0, 1.0))) as u8;
-                let b = (255.0 * (1.0 - t)) as u8;
-                Rgb([r, g, b])
-            }
-        })
-        .collect();
-
-    // Convert to image
-    let mut img = RgbImage::new(nx as u32, ny as u32);
-    for (i, px) in pixels.into_iter().enumerate() {
-        let x = (i % nx) as u32;
-        let y = (i / nx) as u32;
-        img.put_pixel(x, (ny as u32 - 1) - y, px);
+    match options.range {
+        // without any data, keep the colormap's own domain
+        ValueRange::Data if min > max => options.colormap.clone(),
+        ValueRange::Data => options.colormap.rescaled(min, max),
+        ValueRange::Fixed(min, max) => options.colormap.rescaled(min, max),
+        ValueRange::Colormap => options.colormap.clone(),
     }
-    Ok(img)
 }
 
 pub fn load_png(png_path: &Path) -> Result<RgbImage, Box<dyn Error>> {
@@ -66,28 +113,14 @@ pub fn load_png(png_path: &Path) -> Result<RgbImage, Box<dyn Error>> {
     Ok(img)
 }
 
-pub fn combine_images(
-    mut img1: RgbImage,
-    img2: RgbImage,
-    k: f64,
-) -> Result<RgbImage, Box<dyn Error>> {
-    // Ensure same dimensions or handle resizing
-    let (width, height) = img1.dimensions();
-    let (ow, oh) = img2.dimensions();
-
-    for y in 0..height.min(oh) {
-        for x in 0..width.min(ow) {
-            let base_px = img1.get_pixel_mut(x, y);
-            let over_px = img2.get_pixel(x, y);
-
-            // Simple averaging blend
-            for i in 0..3 {
-                base_px.0[i] =
-                    (base_px.0[i] as f64 * k) as u8 + (over_px.0[i] as f64 * (1.0 - k)) as u8;
-            }
-        }
-    }
-    Ok(img1)
+/// Blend `img2` over `img1` as `k·img1 + (1 − k)·img2`, where they overlap.
+pub fn combine_images(img1: RgbImage, img2: RgbImage, k: f64) -> Result<RgbImage, Box<dyn Error>> {
+    // Outside of img2, blending img1 with itself leaves it unchanged
+    let mut over = img1.clone();
+    image::imageops::replace(&mut over, &img2, 0, 0);
+
+    let layer = Layer::new(over, BlendMode::Normal).with_opacity(1.0 - k as f32);
+    Ok(composite(img1, &[layer]))
 }
 
 pub fn save_webp_lossy(img: &RgbImage, quality: f32, path: &Path) -> std::io::Result<()> {
@@ -97,3 +130,41 @@ pub fn save_webp_lossy(img: &RgbImage, quality: f32, path: &Path) -> std::io::Re
     let webp = enc.encode(quality); // 0.0–100.0
     fs::write(path, &*webp)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_range_without_data() {
+        let options = ColorOptions::default();
+        let domain = options.colormap.domain();
+        // instead of a rescaling to (f32::MAX, f32::MIN)
+        assert_eq!(
+            stretched_colormap(&[f32::NAN; 6], &options).domain(),
+            domain
+        );
+        assert_eq!(
+            stretched_colormap(&[f32::NAN, 2.0, 5.0], &options).domain(),
+            (2.0, 5.0)
+        );
+
+        let img = color_grid(&[f32::NAN; 6], (3, 2), &options);
+        assert_eq!(img.dimensions(), (3, 2));
+    }
+
+    #[test]
+    fn test_legend_only_where_it_fits() {
+        let options = ColorOptions {
+            legend: Some("Age (Myr)".into()),
+            ..Default::default()
+        };
+        for ny in [1, 8, 31, 32, 39] {
+            let img = color_grid(&vec![50.0; 8 * ny], (8, ny), &options);
+            assert_eq!(img.dimensions(), (8, ny as u32));
+        }
+        let plain = color_grid(&vec![50.0; 64 * 40], (64, 40), &ColorOptions::default());
+        let legend = color_grid(&vec![50.0; 64 * 40], (64, 40), &options);
+        assert_ne!(plain, legend);
+    }
+}
@@ -1,6 +1,20 @@
+pub mod colormap;
+pub mod composite;
+pub mod font;
+pub mod geojson;
 pub mod geometry;
+pub mod globe;
 pub mod gradients;
 pub mod image;
 pub mod map_helpers;
+pub mod metrics;
+pub mod overlay;
 pub mod partition;
+pub mod projection;
+pub mod reconstruct;
+pub mod relief;
+pub mod resample;
+pub mod scalar;
+pub mod streamlines;
+pub mod tiles;
 pub mod video;
@@ -1,5 +1,34 @@
+use crate::geometry::GeoGrid;
+use netcdf3::{DataSet, FileReader};
 use num_traits::Float;
 use rayon::prelude::*;
+use std::error::Error;
+use std::path::Path;
+
+/// Read the age variable (`z`) of a NetCDF-3 age grid, rows from +90° down.
+pub fn read_age_grid(nc_path: &Path, radius: f32) -> Result<(GeoGrid, Vec<f32>), Box<dyn Error>> {
+    let mut reader = FileReader::open(nc_path)?;
+    let grid = age_grid_shape(reader.data_set(), radius)?;
+
+    println!("Grid: {:?}", &grid);
+    let ages = reader.read_var_f32(AGE_VAR)?;
+    Ok((grid, ages))
+}
+
+/// The grid of [`read_age_grid`], from the file's header alone.
+pub fn read_age_grid_shape(nc_path: &Path, radius: f32) -> Result<GeoGrid, Box<dyn Error>> {
+    let reader = FileReader::open(nc_path)?;
+    age_grid_shape(reader.data_set(), radius)
+}
+
+const AGE_VAR: &str = "z";
+
+fn age_grid_shape(ds: &DataSet, radius: f32) -> Result<GeoGrid, Box<dyn Error>> {
+    let age_var = ds.get_var(AGE_VAR).ok_or("age variable not found")?;
+    let ny = ds.dim_size(&age_var.dim_names()[0]).unwrap();
+    let nx = ds.dim_size(&age_var.dim_names()[1]).unwrap();
+    Ok(GeoGrid { nx, ny, radius })
+}
 
 /// Returns a vector of pixel areas (m²) for each latitude row (y index)
 /// in an equirectangular map of size nx × ny.
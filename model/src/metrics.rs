@@ -0,0 +1,216 @@
+use crate::geometry::GeoGrid;
+use crate::map_helpers::{area_of_sphere, pixel_area_lookup};
+use crate::partition::get_neighbours;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// How well a set of reconstructed partitions fits together on a globe.
+///
+/// All areas are in m², accumulated with the same per-row spherical cell areas
+/// as [`pixel_area_lookup`].
+#[derive(Debug, Serialize)]
+pub struct FitMetrics {
+    pub radius: f32,
+    pub sphere_area: f64,
+    pub covered_area: f64,
+    pub gap_area: f64,
+    pub gap_fraction: f64,
+    pub gap_count: usize,
+    pub largest_gap_area: f64,
+    pub overlap_area: f64,
+    pub overlap_fraction: f64,
+    pub boundaries: Vec<BoundaryMisfit>,
+}
+
+/// Gap and overlap attributed to the boundary between two partitions.
+#[derive(Debug, Default, Serialize)]
+pub struct BoundaryMisfit {
+    pub partitions: (usize, usize),
+    pub gap_area: f64,
+    pub overlap_area: f64,
+}
+
+impl FitMetrics {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn save_json(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+/// Measure gaps and overlaps of reconstructed partitions on `grid`.
+///
+/// `patches` holds, for each partition, the linear indices of the cells it
+/// covers on the reconstructed grid (the same layout `partition_crust` returns).
+///
+/// - A cell covered by no partition is a gap. Connected gap cells (wrapping in
+///   longitude) form one gap region.
+/// - Every extra partition landing on an already covered cell adds that cell's
+///   area to the overlap, and to the misfit of the boundary between the two.
+/// - A gap region touching several partitions has its area split evenly among
+///   every pair of them. Gaps touching a single partition are holes, and only
+///   count towards the totals.
+pub fn fit_metrics(grid: &GeoGrid, patches: &[Vec<usize>]) -> FitMetrics {
+    let GeoGrid { nx, ny, radius } = *grid;
+    let (_, area_lookup) = pixel_area_lookup(nx, ny, radius);
+    let cell_area = |i: usize| area_lookup[i / nx] as f64;
+
+    let uncovered = usize::MAX;
+    let mut owner = vec![uncovered; nx * ny];
+    let mut boundaries: BTreeMap<(usize, usize), BoundaryMisfit> = BTreeMap::new();
+
+    let mut covered_area = 0.0;
+    let mut overlap_area = 0.0;
+    for (id, patch) in patches.iter().enumerate() {
+        for &i in patch {
+            let a = cell_area(i);
+            if owner[i] == uncovered {
+                owner[i] = id;
+                covered_area += a;
+            } else if owner[i] != id {
+                overlap_area += a;
+                boundary(&mut boundaries, owner[i], id).overlap_area += a;
+            }
+        }
+    }
+
+    let mut visited = vec![false; nx * ny];
+    let mut gap_area = 0.0;
+    let mut gap_count = 0;
+    let mut largest_gap_area: f64 = 0.0;
+    for start in 0..nx * ny {
+        if visited[start] || owner[start] != uncovered {
+            continue;
+        }
+
+        // flood fill one gap region, collecting the partitions around it
+        let mut region_area = 0.0;
+        let mut rim: Vec<usize> = Vec::new();
+        let mut stack = vec![start];
+        visited[start] = true;
+        while let Some(ci) = stack.pop() {
+            region_area += cell_area(ci);
+            for (x, y) in get_neighbours((ci % nx, ci / nx), (nx, ny)) {
+                let ni = y * nx + x;
+                if owner[ni] != uncovered {
+                    rim.push(owner[ni]);
+                } else if !visited[ni] {
+                    visited[ni] = true;
+                    stack.push(ni);
+                }
+            }
+        }
+
+        gap_count += 1;
+        gap_area += region_area;
+        largest_gap_area = largest_gap_area.max(region_area);
+
+        rim.sort_unstable();
+        rim.dedup();
+        let pairs = rim.len() * rim.len().saturating_sub(1) / 2;
+        for (k, &a) in rim.iter().enumerate() {
+            for &b in &rim[k + 1..] {
+                boundary(&mut boundaries, a, b).gap_area += region_area / pairs as f64;
+            }
+        }
+    }
+
+    let sphere_area = area_of_sphere(radius) as f64;
+    FitMetrics {
+        radius,
+        sphere_area,
+        covered_area,
+        gap_area,
+        gap_fraction: gap_area / sphere_area,
+        gap_count,
+        largest_gap_area,
+        overlap_area,
+        overlap_fraction: overlap_area / sphere_area,
+        boundaries: boundaries.into_values().collect(),
+    }
+}
+
+fn boundary(
+    boundaries: &mut BTreeMap<(usize, usize), BoundaryMisfit>,
+    a: usize,
+    b: usize,
+) -> &mut BoundaryMisfit {
+    let key = (a.min(b), a.max(b));
+    boundaries.entry(key).or_insert_with(|| BoundaryMisfit {
+        partitions: key,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(grid: &GeoGrid, i: usize) -> Vec<usize> {
+        (0..grid.ny).map(|j| j * grid.nx + i).collect()
+    }
+
+    #[test]
+    fn test_gap_between_two_partitions() {
+        let grid = GeoGrid {
+            nx: 36,
+            ny: 18,
+            radius: 1_000.0,
+        };
+        // partition 0 covers columns 0..17, partition 1 covers 18..34; column 35 is a gap
+        let left: Vec<usize> = (0..17).flat_map(|i| column(&grid, i)).collect();
+        let right: Vec<usize> = (18..35).flat_map(|i| column(&grid, i)).collect();
+
+        let m = fit_metrics(&grid, &[left, right]);
+
+        // columns 17 and 35 are two separate gaps, each a full meridian strip
+        let strip: f64 = pixel_area_lookup(grid.nx, grid.ny, grid.radius)
+            .1
+            .iter()
+            .map(|&a| a as f64)
+            .sum();
+        assert_eq!(m.gap_count, 2);
+        assert!((m.gap_area - 2.0 * strip).abs() < 1e-6 * strip, "{m:?}");
+        assert!((m.largest_gap_area - strip).abs() < 1e-6 * strip);
+        assert_eq!(m.overlap_area, 0.0);
+
+        assert_eq!(m.boundaries.len(), 1);
+        let b = &m.boundaries[0];
+        assert_eq!(b.partitions, (0, 1));
+        assert!((b.gap_area - m.gap_area).abs() < 1e-6 * m.gap_area);
+    }
+
+    #[test]
+    fn test_overlap_and_hole() {
+        let grid = GeoGrid {
+            nx: 36,
+            ny: 18,
+            radius: 1_000.0,
+        };
+        let equator = grid.ny / 2 * grid.nx;
+        // partition 0 covers everything but one cell; partition 1 overlaps two cells
+        let all: Vec<usize> = (0..grid.nx * grid.ny).filter(|&i| i != equator).collect();
+        let overlap = vec![equator + 10, equator + 11];
+
+        let m = fit_metrics(&grid, &[all, overlap]);
+
+        let cell = pixel_area_lookup(grid.nx, grid.ny, grid.radius).1[grid.ny / 2] as f64;
+        assert_eq!(m.gap_count, 1);
+        assert!((m.gap_area - cell).abs() < 1e-6 * cell);
+        assert!((m.overlap_area - 2.0 * cell).abs() < 1e-6 * cell);
+
+        // the hole touches only partition 0, so the boundary only carries overlap
+        assert_eq!(m.boundaries.len(), 1);
+        assert_eq!(m.boundaries[0].gap_area, 0.0);
+        assert!((m.boundaries[0].overlap_area - m.overlap_area).abs() < 1e-9);
+
+        let json = m.to_json().unwrap();
+        assert!(json.contains("\"largest_gap_area\""));
+    }
+}
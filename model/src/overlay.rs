@@ -0,0 +1,262 @@
+use crate::composite::{BlendMode, Layer};
+use crate::font::{draw_text, text_height};
+use crate::geojson::{Geometry, LonLat};
+use crate::geometry::{col_of, row_of, GeoGrid};
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use rayon::prelude::*;
+
+/// Antialiased line art for an equirectangular image (rows from +90° down,
+/// columns from -180°), collected as a coverage mask and then stacked on a map
+/// with [`Overlay::layer`]. Use one overlay per color.
+///
+/// Lines wrap around the dateline: a segment from 170°E to 170°W is the short
+/// one across it, not the long one across the whole map.
+pub struct Overlay {
+    mask: GrayImage,
+}
+
+impl Overlay {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            mask: GrayImage::new(width, height),
+        }
+    }
+
+    /// Meridians and parallels every `step` degrees, `width` pixels wide.
+    pub fn graticule(&mut self, step: f32, width: f32) -> &mut Self {
+        let n = (360.0 / step).round() as i32;
+        for k in 0..n {
+            let lon = -180.0 + k as f32 * step;
+            self.line(&[(lon, 90.0), (lon, -90.0)], width);
+        }
+        let n = (90.0 / step).floor() as i32;
+        for k in -n..=n {
+            let lat = k as f32 * step;
+            if lat.abs() < 90.0 {
+                // three pieces, so each is the short way round
+                self.line(
+                    &[(-180.0, lat), (-60.0, lat), (60.0, lat), (180.0, lat)],
+                    width,
+                );
+            }
+        }
+        self
+    }
+
+    /// Degree labels of the graticule: parallels along the left edge and
+    /// meridians along the equator.
+    pub fn graticule_labels(&mut self, step: f32, scale: u32) -> &mut Self {
+        let n = (90.0 / step).floor() as i32;
+        for k in -n..=n {
+            let lat = k as f32 * step;
+            if lat.abs() < 90.0 {
+                self.label((-180.0, lat), &degrees(lat, 'N', 'S'), scale);
+            }
+        }
+        let n = (360.0 / step).round() as i32;
+        // the left edge already has the equator's label
+        for k in 1..n {
+            let lon = -180.0 + k as f32 * step;
+            self.label((lon, 0.0), &degrees(lon, 'E', 'W'), scale);
+        }
+        self
+    }
+
+    /// Polyline through `points`, `width` pixels wide.
+    pub fn line(&mut self, points: &[LonLat], width: f32) -> &mut Self {
+        for pair in points.windows(2) {
+            let (x0, y0) = self.to_px(pair[0]);
+            let (mut x1, y1) = self.to_px(pair[1]);
+            // go the short way round
+            let w = self.mask.width() as f32;
+            if x1 - x0 > w / 2.0 {
+                x1 -= w;
+            } else if x0 - x1 > w / 2.0 {
+                x1 += w;
+            }
+            self.segment((x0, y0), (x1, y1), width / 2.0);
+        }
+        self
+    }
+
+    /// Dot of `radius` pixels.
+    pub fn point(&mut self, at: LonLat, radius: f32) -> &mut Self {
+        let p = self.to_px(at);
+        self.segment(p, p, radius);
+        self
+    }
+
+    /// Outlines of lines and polygons `width` pixels wide; points as dots of
+    /// the same width.
+    pub fn geometry(&mut self, geometry: &Geometry, width: f32) -> &mut Self {
+        match geometry {
+            Geometry::Point(p) => self.point(*p, width),
+            Geometry::LineString(points) => self.line(points, width),
+            Geometry::Polygon(rings) => {
+                for ring in rings {
+                    self.line(ring, width);
+                }
+                self
+            }
+        }
+    }
+
+    pub fn geometries(&mut self, geometries: &[Geometry], width: f32) -> &mut Self {
+        for g in geometries {
+            self.geometry(g, width);
+        }
+        self
+    }
+
+    /// Text with its left edge at `at`, vertically centered on it.
+    pub fn label(&mut self, at: LonLat, text: &str, scale: u32) -> &mut Self {
+        let (x, y) = self.to_px(at);
+        let (x, y) = (
+            x.round() as i32 + 2,
+            y.round() as i32 - text_height(scale) as i32 / 2,
+        );
+        draw_text(&mut self.mask, x, y, text, scale, Luma([255]));
+        self
+    }
+
+    /// Isochrons: lines between cells whose `values` (e.g. ages, in the same
+    /// layout as the map) fall in different multiples of `interval`. NaN cells
+    /// have no isochrons.
+    pub fn isochrons(&mut self, grid: &GeoGrid, values: &[f32], interval: f32) -> &mut Self {
+        let (w, h) = self.mask.dimensions();
+        let band = |x: u32, y: u32| {
+            let lam = (x as f32 + 0.5) / w as f32 * std::f32::consts::TAU - std::f32::consts::PI;
+            let phi =
+                std::f32::consts::FRAC_PI_2 - (y as f32 + 0.5) / h as f32 * std::f32::consts::PI;
+            let v = values[row_of(phi, grid.ny) * grid.nx + col_of(lam, grid.nx)];
+            (v / interval).floor()
+        };
+        self.mask
+            .par_chunks_mut(w as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let y = y as u32;
+                for (x, px) in row.iter_mut().enumerate() {
+                    let x = x as u32;
+                    let b = band(x, y);
+                    let east = band((x + 1) % w, y);
+                    let south = if y + 1 < h { band(x, y + 1) } else { b };
+                    let differs = |c: f32| !b.is_nan() && !c.is_nan() && b != c;
+                    if differs(east) || differs(south) {
+                        *px = 255;
+                    }
+                }
+            });
+        self
+    }
+
+    pub fn mask(&self) -> &GrayImage {
+        &self.mask
+    }
+
+    /// The overlay as a layer of solid `color` for
+    /// [`composite`](crate::composite::composite).
+    pub fn layer(&self, color: Rgb<u8>, opacity: f32) -> Layer {
+        let (w, h) = self.mask.dimensions();
+        Layer::new(RgbImage::from_pixel(w, h, color), BlendMode::Normal)
+            .with_mask(self.mask.clone())
+            .with_opacity(opacity)
+    }
+
+    /// Continuous pixel coordinates of a lon/lat, cell centers at +0.5.
+    fn to_px(&self, (lon, lat): LonLat) -> (f32, f32) {
+        let (w, h) = self.mask.dimensions();
+        let x = (lon + 180.0).rem_euclid(360.0) / 360.0 * w as f32;
+        let y = (90.0 - lat) / 180.0 * h as f32;
+        (x, y)
+    }
+
+    /// Antialiased capsule of radius `r` around a segment, wrapping columns.
+    fn segment(&mut self, (x0, y0): (f32, f32), (x1, y1): (f32, f32), r: f32) {
+        let (w, h) = self.mask.dimensions();
+        let pad = r + 1.0;
+        let y_min = (y0.min(y1) - pad).floor().max(0.0) as i64;
+        let y_max = (y0.max(y1) + pad).ceil().min(h as f32) as i64;
+        let x_min = (x0.min(x1) - pad).floor() as i64;
+        let x_max = (x0.max(x1) + pad).ceil() as i64;
+
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let len2 = dx * dx + dy * dy;
+        for y in y_min..y_max {
+            for x in x_min..x_max {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let t = if len2 > 0.0 {
+                    (((px - x0) * dx + (py - y0) * dy) / len2).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let d = ((px - x0 - t * dx).powi(2) + (py - y0 - t * dy).powi(2)).sqrt();
+                let coverage = (r + 0.5 - d).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    let Luma([m]) = self
+                        .mask
+                        .get_pixel_mut(x.rem_euclid(w as i64) as u32, y as u32);
+                    *m = (*m).max((coverage * 255.0).round() as u8);
+                }
+            }
+        }
+    }
+}
+
+fn degrees(v: f32, positive: char, negative: char) -> String {
+    let v = v.round();
+    if v > 0.0 {
+        format!("{v}{positive}")
+    } else if v < 0.0 {
+        format!("{}{negative}", -v)
+    } else {
+        "0".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_wraps_at_dateline() {
+        let mut overlay = Overlay::new(360, 180);
+        // through the centers of row 89
+        overlay.line(&[(170.0, 0.5), (-170.0, 0.5)], 1.0);
+        let m = overlay.mask();
+        assert_eq!(m.get_pixel(355, 89).0[0], 255);
+        assert_eq!(m.get_pixel(5, 89).0[0], 255);
+        assert_eq!(m.get_pixel(180, 89).0[0], 0, "took the long way round");
+    }
+
+    #[test]
+    fn test_isochrons() {
+        let grid = GeoGrid {
+            nx: 36,
+            ny: 18,
+            radius: 1.0,
+        };
+        // age grows by 1 per column from the west edge; NaN in the south
+        let values: Vec<f32> = (0..grid.nx * grid.ny)
+            .map(|k| {
+                if k / grid.nx < 9 {
+                    (k % grid.nx) as f32
+                } else {
+                    f32::NAN
+                }
+            })
+            .collect();
+        let mut overlay = Overlay::new(72, 36);
+        overlay.isochrons(&grid, &values, 10.0);
+        let m = overlay.mask();
+        // the 10 Myr isochron lies between columns 9 and 10, i.e. pixels 19 and 20
+        assert_eq!(m.get_pixel(19, 5).0[0], 255);
+        assert_eq!(m.get_pixel(20, 5).0[0], 0);
+        assert_eq!(m.get_pixel(10, 5).0[0], 0);
+        assert_eq!(
+            m.get_pixel(19, 30).0[0],
+            0,
+            "no isochrons where there is no data"
+        );
+    }
+}
@@ -112,7 +112,10 @@ pub fn partition_crust(
     patches
 }
 
-fn get_neighbours((cx, cy): (usize, usize), (nx, ny): (usize, usize)) -> Vec<(usize, usize)> {
+pub(crate) fn get_neighbours(
+    (cx, cy): (usize, usize),
+    (nx, ny): (usize, usize),
+) -> Vec<(usize, usize)> {
     // wrap longitudinally
     let left = ((cx + nx - 1) % nx, cy);
     let right = ((cx + 1) % nx, cy);
@@ -0,0 +1,437 @@
+use crate::geojson::LonLat;
+use crate::geometry::{col_of, row_of, GeoGrid};
+use image::{Rgb, RgbImage};
+use rayon::prelude::*;
+use std::f64::consts::{FRAC_PI_2, PI, SQRT_2};
+
+/// Map projections for still renders. Centers and meridians are in degrees.
+///
+/// Maps are warped by inverse mapping: every output pixel is unprojected to a
+/// lon/lat and takes the value of the equirectangular cell it falls in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// The globe seen from far away, one hemisphere around `center`.
+    Orthographic { center: LonLat },
+    /// Equal-area ellipse of the whole globe.
+    Mollweide { central_meridian: f32 },
+    /// Compromise pseudo-cylindrical of the whole globe.
+    Robinson { central_meridian: f32 },
+    /// Equal-area azimuthal view of everything within `extent` degrees of
+    /// `center` (up to 180, the whole globe).
+    LambertAzimuthalEqualArea { center: LonLat, extent: f32 },
+    /// Conformal view of a pole down to `extent` degrees away from it, with
+    /// `central_meridian` pointing down from the north pole or up from the
+    /// south pole.
+    PolarStereographic {
+        north: bool,
+        central_meridian: f32,
+        extent: f32,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Azimuthal {
+    Orthographic,
+    EqualArea,
+    Stereographic,
+}
+
+impl Azimuthal {
+    /// Distance from the map center of a point `c` radians away from it.
+    fn rho(self, c: f64) -> f64 {
+        match self {
+            Azimuthal::Orthographic => c.sin(),
+            Azimuthal::EqualArea => 2.0 * (c / 2.0).sin(),
+            Azimuthal::Stereographic => 2.0 * (c / 2.0).tan(),
+        }
+    }
+
+    fn c(self, rho: f64) -> Option<f64> {
+        match self {
+            Azimuthal::Orthographic if rho <= 1.0 => Some(rho.asin()),
+            Azimuthal::EqualArea if rho <= 2.0 => Some(2.0 * (rho / 2.0).asin()),
+            Azimuthal::Stereographic => Some(2.0 * (rho / 2.0).atan()),
+            _ => None,
+        }
+    }
+}
+
+impl Projection {
+    /// North polar stereographic down to 50°N, Greenwich at the bottom.
+    pub fn arctic() -> Self {
+        Projection::PolarStereographic {
+            north: true,
+            central_meridian: 0.0,
+            extent: 40.0,
+        }
+    }
+
+    /// South polar stereographic up to 50°S, Greenwich at the top.
+    pub fn antarctic() -> Self {
+        Projection::PolarStereographic {
+            north: false,
+            central_meridian: 0.0,
+            extent: 40.0,
+        }
+    }
+
+    /// Map coordinates of a lon/lat in degrees, `None` where it isn't shown
+    /// (e.g. the far side of an orthographic globe).
+    pub fn project(&self, (lon, lat): LonLat) -> Option<(f64, f64)> {
+        let (lam, phi) = ((lon as f64).to_radians(), (lat as f64).to_radians());
+        match self.azimuthal() {
+            Some((kind, (lam0, phi0), extent)) => {
+                let dlam = lam - lam0;
+                // direction away from the center, of length sin(c)
+                let xi = phi.cos() * dlam.sin();
+                let eta = phi0.cos() * phi.sin() - phi0.sin() * phi.cos() * dlam.cos();
+                let cos_c = phi0.sin() * phi.sin() + phi0.cos() * phi.cos() * dlam.cos();
+                let c = xi.hypot(eta).atan2(cos_c);
+                if c > extent + 1e-9 {
+                    return None;
+                }
+                let k = if c < 1e-12 {
+                    1.0
+                } else {
+                    kind.rho(c) / c.sin()
+                };
+                Some((k * xi, k * eta))
+            }
+            None => {
+                let dlam = wrap(lam - self.central_meridian());
+                match self {
+                    Projection::Mollweide { .. } => {
+                        let theta = mollweide_theta(phi);
+                        Some((2.0 * SQRT_2 / PI * dlam * theta.cos(), SQRT_2 * theta.sin()))
+                    }
+                    _ => {
+                        let (x, y) = robinson_xy(phi.abs());
+                        Some((0.8487 * x * dlam, 1.3523 * y * phi.signum()))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lon/lat in degrees of map coordinates, `None` outside of the map.
+    pub fn unproject(&self, (x, y): (f64, f64)) -> Option<LonLat> {
+        let (lam, phi) = match self.azimuthal() {
+            Some((kind, (lam0, phi0), _)) => {
+                let rho = x.hypot(y);
+                let c = kind.c(rho)?;
+                if rho < 1e-12 {
+                    (lam0, phi0)
+                } else {
+                    let (sin_c, cos_c) = c.sin_cos();
+                    let phi = (cos_c * phi0.sin() + y * sin_c * phi0.cos() / rho).clamp(-1.0, 1.0);
+                    let lam =
+                        lam0 + (x * sin_c).atan2(rho * cos_c * phi0.cos() - y * sin_c * phi0.sin());
+                    (lam, phi.asin())
+                }
+            }
+            None => {
+                let (dlam, phi) = match self {
+                    Projection::Mollweide { .. } => {
+                        let theta = (y / SQRT_2).clamp(-1.0, 1.0).asin();
+                        let phi = ((2.0 * theta + (2.0 * theta).sin()) / PI).clamp(-1.0, 1.0);
+                        (PI * x / (2.0 * SQRT_2 * theta.cos()), phi.asin())
+                    }
+                    _ => {
+                        let phi = robinson_phi((y / 1.3523).abs())? * y.signum();
+                        (x / (0.8487 * robinson_xy(phi.abs()).0), phi)
+                    }
+                };
+                if dlam.is_nan() || dlam.abs() > PI + 1e-9 {
+                    return None;
+                }
+                (self.central_meridian() + dlam, phi)
+            }
+        };
+        Some((
+            wrap(lam).to_degrees() as f32,
+            phi.to_degrees().clamp(-90.0, 90.0) as f32,
+        ))
+    }
+
+    /// Map coordinates of the frame: x min, x max, y min, y max.
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        match self.azimuthal() {
+            Some((kind, _, extent)) => {
+                let r = kind.rho(extent);
+                (-r, r, -r, r)
+            }
+            None => match self {
+                Projection::Mollweide { .. } => (-2.0 * SQRT_2, 2.0 * SQRT_2, -SQRT_2, SQRT_2),
+                _ => (-0.8487 * PI, 0.8487 * PI, -1.3523, 1.3523),
+            },
+        }
+    }
+
+    /// Height of a map `width` pixels wide.
+    pub fn height_for(&self, width: u32) -> u32 {
+        let (x0, x1, y0, y1) = self.bounds();
+        ((width as f64 * (y1 - y0) / (x1 - x0)).round() as u32).max(1)
+    }
+
+    /// Lon/lat at the center of every pixel of a `width`-wide map, rows from the
+    /// top; `None` outside of the map.
+    pub fn inverse_map(&self, width: u32) -> Vec<Option<LonLat>> {
+        let height = self.height_for(width);
+        let (x0, x1, y0, y1) = self.bounds();
+        (0..width as usize * height as usize)
+            .into_par_iter()
+            .map(|k| {
+                let (i, j) = (k % width as usize, k / width as usize);
+                let x = x0 + (i as f64 + 0.5) / width as f64 * (x1 - x0);
+                let y = y1 - (j as f64 + 0.5) / height as f64 * (y1 - y0);
+                self.unproject((x, y))
+            })
+            .collect()
+    }
+
+    // kind, center (radians) and extent (radians) of azimuthal projections
+    fn azimuthal(&self) -> Option<(Azimuthal, (f64, f64), f64)> {
+        let rad = |(lon, lat): LonLat| ((lon as f64).to_radians(), (lat as f64).to_radians());
+        match *self {
+            Projection::Orthographic { center } => {
+                Some((Azimuthal::Orthographic, rad(center), FRAC_PI_2))
+            }
+            Projection::LambertAzimuthalEqualArea { center, extent } => Some((
+                Azimuthal::EqualArea,
+                rad(center),
+                (extent as f64).clamp(1.0, 180.0).to_radians(),
+            )),
+            Projection::PolarStereographic {
+                north,
+                central_meridian,
+                extent,
+            } => {
+                // seen from below, the south pole's central meridian points up
+                let lat = if north { 90.0 } else { -90.0 };
+                Some((
+                    Azimuthal::Stereographic,
+                    rad((central_meridian, lat)),
+                    (extent as f64).clamp(1.0, 170.0).to_radians(),
+                ))
+            }
+            Projection::Mollweide { .. } | Projection::Robinson { .. } => None,
+        }
+    }
+
+    fn central_meridian(&self) -> f64 {
+        match *self {
+            Projection::Mollweide { central_meridian }
+            | Projection::Robinson { central_meridian } => (central_meridian as f64).to_radians(),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Warp an equirectangular field on `grid` into a `width`-wide map. Cells
+/// outside of the map are NaN. Returns the values and the map height.
+pub fn warp_field(
+    grid: &GeoGrid,
+    values: &[f32],
+    projection: &Projection,
+    width: u32,
+) -> (Vec<f32>, u32) {
+    let values = projection
+        .inverse_map(width)
+        .into_par_iter()
+        .map(|ll| ll.map_or(f32::NAN, |ll| values[cell_of(ll, grid.nx, grid.ny)]))
+        .collect();
+    (values, projection.height_for(width))
+}
+
+/// Warp a north-up equirectangular image into a `width`-wide map, filling the
+/// outside of the map with `background`.
+pub fn warp_image(
+    img: &RgbImage,
+    projection: &Projection,
+    width: u32,
+    background: Rgb<u8>,
+) -> RgbImage {
+    let (w, h) = img.dimensions();
+    let src = img.as_raw();
+    let raw: Vec<u8> = projection
+        .inverse_map(width)
+        .into_par_iter()
+        .flat_map_iter(|ll| {
+            let px = ll.map_or(background.0, |ll| {
+                let k = 3 * cell_of(ll, w as usize, h as usize);
+                [src[k], src[k + 1], src[k + 2]]
+            });
+            px.into_iter()
+        })
+        .collect();
+    RgbImage::from_raw(width, projection.height_for(width), raw).unwrap()
+}
+
+fn cell_of((lon, lat): LonLat, nx: usize, ny: usize) -> usize {
+    row_of(lat.to_radians(), ny) * nx + col_of(lon.to_radians(), nx)
+}
+
+// wrap to [-π, π)
+fn wrap(a: f64) -> f64 {
+    (a + PI).rem_euclid(2.0 * PI) - PI
+}
+
+// auxiliary angle θ of Mollweide: 2θ + sin 2θ = π sin φ
+fn mollweide_theta(phi: f64) -> f64 {
+    if phi.abs() > FRAC_PI_2 - 1e-9 {
+        return phi;
+    }
+    let target = PI * phi.sin();
+    let mut theta = phi;
+    for _ in 0..20 {
+        let f = 2.0 * theta + (2.0 * theta).sin() - target;
+        theta -= f / (2.0 + 2.0 * (2.0 * theta).cos());
+        if f.abs() < 1e-12 {
+            break;
+        }
+    }
+    theta
+}
+
+// Robinson's table: length of the parallel and distance from the equator, every 5°
+const ROBINSON: [(f64, f64); 19] = [
+    (1.0000, 0.0000),
+    (0.9986, 0.0620),
+    (0.9954, 0.1240),
+    (0.9900, 0.1860),
+    (0.9822, 0.2480),
+    (0.9730, 0.3100),
+    (0.9600, 0.3720),
+    (0.9427, 0.4340),
+    (0.9216, 0.4958),
+    (0.8962, 0.5571),
+    (0.8679, 0.6176),
+    (0.8350, 0.6769),
+    (0.7986, 0.7346),
+    (0.7597, 0.7903),
+    (0.7186, 0.8435),
+    (0.6732, 0.8936),
+    (0.6213, 0.9394),
+    (0.5722, 0.9761),
+    (0.5322, 1.0000),
+];
+
+// table values at latitude φ ≥ 0 (radians), linearly interpolated
+fn robinson_xy(phi: f64) -> (f64, f64) {
+    let t = (phi.to_degrees() / 5.0).clamp(0.0, 18.0);
+    let k = (t.floor() as usize).min(17);
+    let f = t - k as f64;
+    let ((x0, y0), (x1, y1)) = (ROBINSON[k], ROBINSON[k + 1]);
+    (x0 + f * (x1 - x0), y0 + f * (y1 - y0))
+}
+
+// inverse of `robinson_xy(φ).1`
+fn robinson_phi(y: f64) -> Option<f64> {
+    if y > 1.0 + 1e-9 {
+        return None;
+    }
+    let k = ROBINSON
+        .iter()
+        .rposition(|&(_, yk)| yk <= y)
+        .unwrap()
+        .min(17);
+    let (y0, y1) = (ROBINSON[k].1, ROBINSON[k + 1].1);
+    let f = ((y - y0) / (y1 - y0)).clamp(0.0, 1.0);
+    Some((5.0 * (k as f64 + f)).to_radians())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Vec<Projection> {
+        vec![
+            Projection::Orthographic {
+                center: (-30.0, 60.0),
+            },
+            Projection::Mollweide {
+                central_meridian: 150.0,
+            },
+            Projection::Robinson {
+                central_meridian: -90.0,
+            },
+            Projection::LambertAzimuthalEqualArea {
+                center: (20.0, -45.0),
+                extent: 180.0,
+            },
+            Projection::arctic(),
+            Projection::antarctic(),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for p in all() {
+            let mut shown = 0;
+            for lat in (-85..=85).step_by(10) {
+                for lon in (-175..=175).step_by(10) {
+                    let ll = (lon as f32, lat as f32);
+                    let Some(xy) = p.project(ll) else {
+                        continue;
+                    };
+                    shown += 1;
+                    let (x0, x1, y0, y1) = p.bounds();
+                    assert!(xy.0 >= x0 - 1e-6 && xy.0 <= x1 + 1e-6, "{p:?} {ll:?}");
+                    assert!(xy.1 >= y0 - 1e-6 && xy.1 <= y1 + 1e-6, "{p:?} {ll:?}");
+                    let back = p.unproject(xy).unwrap();
+                    let dlon = (back.0 - ll.0 + 540.0).rem_euclid(360.0) - 180.0;
+                    assert!(dlon.abs() < 1e-3, "{p:?} {ll:?} -> {back:?}");
+                    assert!((back.1 - ll.1).abs() < 1e-3, "{p:?} {ll:?} -> {back:?}");
+                }
+            }
+            assert!(shown > 50, "{p:?} shows {shown} points");
+        }
+    }
+
+    #[test]
+    fn test_orientation() {
+        // north is up and east is right on the world maps
+        for p in [
+            Projection::Mollweide {
+                central_meridian: 0.0,
+            },
+            Projection::Robinson {
+                central_meridian: 0.0,
+            },
+        ] {
+            let (x, y) = p.project((10.0, 20.0)).unwrap();
+            assert!(x > 0.0 && y > 0.0, "{p:?}");
+        }
+        // the central meridian points down from the north pole
+        let (x, y) = Projection::arctic().project((0.0, 70.0)).unwrap();
+        assert!(x.abs() < 1e-9 && y < 0.0);
+        // and up from the south pole
+        let (x, y) = Projection::antarctic().project((0.0, -70.0)).unwrap();
+        assert!(x.abs() < 1e-9 && y > 0.0);
+    }
+
+    #[test]
+    fn test_warp_field() {
+        let grid = GeoGrid {
+            nx: 360,
+            ny: 180,
+            radius: 1.0,
+        };
+        // latitude, in degrees
+        let values: Vec<f32> = (0..grid.nx * grid.ny)
+            .map(|k| 89.5 - (k / grid.nx) as f32)
+            .collect();
+        let p = Projection::Mollweide {
+            central_meridian: 0.0,
+        };
+        let (warped, height) = warp_field(&grid, &values, &p, 400);
+        assert_eq!(height, 200);
+        assert_eq!(warped.len(), 400 * 200);
+        // the ellipse covers π/4 of its frame
+        let inside = warped.iter().filter(|v| !v.is_nan()).count() as f32;
+        assert!((inside / warped.len() as f32 - std::f32::consts::FRAC_PI_4).abs() < 0.01);
+        assert!(warped[0].is_nan());
+        assert!(warped[400 * 2 + 200] > 80.0, "top is north");
+        assert!(warped[400 * 199 + 200] < -80.0, "bottom is south");
+    }
+}
@@ -0,0 +1,235 @@
+use crate::colormap::Colormap;
+use crate::geometry::{col_of, lat_of, lon_of, neighbors_within, row_of, GeoGrid};
+use crate::map_helpers::pixel_area_lookup;
+use crate::partition::{label_partitions, partition_crust};
+use image::{Rgb, RgbImage};
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+/// The globe at some age in the past, after removing all younger oceanic crust.
+pub struct Reconstruction {
+    /// Millions of years before present.
+    pub age: f32,
+    /// Grid of the smaller globe. Cells are about the same size as on the
+    /// present-day grid, so there are fewer of them.
+    pub grid: GeoGrid,
+    /// Age of the crust at `age` Ma for each cell of `grid`; NaN for
+    /// continental crust and for gaps.
+    pub ages: Vec<f32>,
+    /// Cells of `grid` covered by each surviving partition.
+    pub patches: Vec<Vec<usize>>,
+}
+
+/// Radius (meters) of a globe whose surface is exactly the crust that is at
+/// least `age` Myr old today (continental crust always survives).
+pub fn radius_at(grid: &GeoGrid, ages: &[f32], age: f32) -> f32 {
+    let (_, area_lookup) = pixel_area_lookup(grid.nx, grid.ny, grid.radius);
+    let total: f64 = area_lookup.iter().map(|&a| a as f64).sum::<f64>() * grid.nx as f64;
+    let surviving: f64 = ages
+        .par_iter()
+        .enumerate()
+        .filter(|(_, a)| a.is_nan() || **a >= age)
+        .map(|(i, _)| area_lookup[i / grid.nx] as f64)
+        .sum();
+    // ratio of the discrete areas, so that nothing removed means exactly R₀
+    grid.radius * (surviving / total).sqrt() as f32
+}
+
+/// Remove all oceanic crust younger than `age` and place what is left on a
+/// globe with the surviving area.
+///
+/// This is a first, naive remapping: each surviving partition stays centred on
+/// its present-day centroid and keeps its size in meters, by stretching great
+/// circle distances from the centroid by `R₀ / R`. Partitions are not moved
+/// towards each other, so the fit can be judged with `metrics::fit_metrics`.
+/// Parts of a partition that would reach past the antipode of its centroid are
+/// dropped.
+pub fn reconstruct(grid: &GeoGrid, ages: &[f32], age: f32) -> Reconstruction {
+    let src_patches = partition_crust(ages, (grid.nx, grid.ny), age);
+    let labels = label_partitions(&src_patches, ages.len());
+    let radius = radius_at(grid, ages, age);
+    let stretch = grid.radius / radius;
+
+    let dst = GeoGrid {
+        nx: ((grid.nx as f32 / stretch).round() as usize).max(2),
+        ny: ((grid.ny as f32 / stretch).round() as usize).max(1),
+        radius,
+    };
+
+    let placed: Vec<(Vec<usize>, Vec<f32>)> = src_patches
+        .par_iter()
+        .enumerate()
+        .map(|(id, patch)| {
+            let (c, reach) = centroid_and_reach(grid, patch);
+            let reach = (reach * stretch).min(std::f32::consts::PI);
+            let c_idx = row_of(c.z.asin(), dst.ny) * dst.nx + col_of(c.y.atan2(c.x), dst.nx);
+            // pad by one cell so the rim of the patch isn't clipped
+            let pad = std::f32::consts::PI / dst.ny as f32;
+
+            let mut cells = Vec::new();
+            let mut values = Vec::new();
+            for q_idx in neighbors_within(&dst, c_idx, (reach + pad) * dst.radius) {
+                let q = unit(
+                    lat_of(q_idx / dst.nx, dst.ny),
+                    lon_of(q_idx % dst.nx, dst.nx),
+                );
+                let p = towards(&c, &q, c.dot(&q).clamp(-1.0, 1.0).acos() / stretch);
+                let s = row_of(p.z.asin(), grid.ny) * grid.nx + col_of(p.y.atan2(p.x), grid.nx);
+                if labels[s] == id {
+                    cells.push(q_idx);
+                    values.push(ages[s] - age);
+                }
+            }
+            (cells, values)
+        })
+        .collect();
+
+    let mut rec_ages = vec![f32::NAN; dst.nx * dst.ny];
+    let mut patches = Vec::with_capacity(placed.len());
+    for (cells, values) in placed {
+        for (&i, &v) in cells.iter().zip(&values) {
+            rec_ages[i] = v;
+        }
+        patches.push(cells);
+    }
+
+    Reconstruction {
+        age,
+        grid: dst,
+        ages: rec_ages,
+        patches,
+    }
+}
+
+/// Color a reconstruction on its own grid, north up: oceanic crust by its age
+/// through `cmap`, continental crust gray and gaps black.
+pub fn render_reconstruction(rec: &Reconstruction, cmap: &Colormap) -> RgbImage {
+    let GeoGrid { nx, ny, .. } = rec.grid;
+    let mut covered = vec![false; nx * ny];
+    for &i in rec.patches.iter().flatten() {
+        covered[i] = true;
+    }
+
+    let mut img = RgbImage::new(nx as u32, ny as u32);
+    for (i, px) in img.pixels_mut().enumerate() {
+        let age = rec.ages[i];
+        *px = if !covered[i] {
+            Rgb([0, 0, 0])
+        } else if age.is_nan() {
+            Rgb([128, 128, 128])
+        } else {
+            cmap.color(age)
+        };
+    }
+    img
+}
+
+#[inline]
+fn unit(phi: f32, lam: f32) -> Vector3<f32> {
+    Vector3::new(phi.cos() * lam.cos(), phi.cos() * lam.sin(), phi.sin())
+}
+
+/// Point at angle `theta` from `c` along the great circle through `q`.
+fn towards(c: &Vector3<f32>, q: &Vector3<f32>, theta: f32) -> Vector3<f32> {
+    let t = q - c * c.dot(q);
+    match t.try_normalize(1e-9) {
+        Some(t) => c * theta.cos() + t * theta.sin(),
+        None => *c,
+    }
+}
+
+/// Area-weighted centroid direction of a patch and the largest angle (radians)
+/// from it to any cell of the patch.
+fn centroid_and_reach(grid: &GeoGrid, patch: &[usize]) -> (Vector3<f32>, f32) {
+    let point = |i: usize| unit(lat_of(i / grid.nx, grid.ny), lon_of(i % grid.nx, grid.nx));
+    let sum: Vector3<f32> = patch
+        .iter()
+        .map(|&i| point(i) * lat_of(i / grid.nx, grid.ny).cos())
+        .sum();
+    // a patch covering the whole globe has no meaningful centroid; any will do
+    let c = sum.try_normalize(1e-6).unwrap_or_else(|| point(patch[0]));
+    let reach = patch
+        .iter()
+        .map(|&i| c.dot(&point(i)).clamp(-1.0, 1.0).acos())
+        .fold(0.0, f32::max);
+    (c, reach)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_helpers::area_of_sphere;
+
+    #[test]
+    fn test_radius_keeps_surviving_area() {
+        let grid = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 6_371_000.0,
+        };
+        // young crust (5 Myr) in the east half, old crust (50 Myr) in the west
+        let ages: Vec<f32> = (0..grid.nx * grid.ny)
+            .map(|i| if i % grid.nx < grid.nx / 2 { 50.0 } else { 5.0 })
+            .collect();
+
+        assert!((radius_at(&grid, &ages, 0.0) - grid.radius).abs() / grid.radius < 1e-3);
+
+        let r = radius_at(&grid, &ages, 10.0);
+        let ratio = area_of_sphere(r) / area_of_sphere(grid.radius);
+        assert!((ratio - 0.5).abs() < 1e-3, "area ratio {ratio}");
+    }
+
+    #[test]
+    fn test_reconstruct_at_present_is_identity() {
+        let grid = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 6_371_000.0,
+        };
+        let ages: Vec<f32> = (0..grid.nx * grid.ny)
+            .map(|i| {
+                if (i / grid.nx).is_multiple_of(3) {
+                    f32::NAN
+                } else {
+                    20.0
+                }
+            })
+            .collect();
+
+        let rec = reconstruct(&grid, &ages, 0.0);
+
+        assert_eq!((rec.grid.nx, rec.grid.ny), (grid.nx, grid.ny));
+        let covered: usize = rec.patches.iter().map(|p| p.len()).sum();
+        assert_eq!(covered, grid.nx * grid.ny);
+        for (a, b) in ages.iter().zip(&rec.ages) {
+            assert!(a == b || (a.is_nan() && b.is_nan()));
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_shrinks_grid() {
+        let grid = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 6_371_000.0,
+        };
+        // a band of young crust around the equator separates two polar caps
+        let ages: Vec<f32> = (0..grid.nx * grid.ny)
+            .map(|i| {
+                let lat = lat_of(i / grid.nx, grid.ny).to_degrees();
+                if lat.abs() < 20.0 {
+                    5.0
+                } else {
+                    f32::NAN
+                }
+            })
+            .collect();
+
+        let rec = reconstruct(&grid, &ages, 10.0);
+
+        assert_eq!(rec.patches.len(), 2);
+        assert!(rec.grid.radius < grid.radius);
+        assert!(rec.grid.nx < grid.nx && rec.grid.ny < grid.ny);
+        assert!(rec.patches.iter().all(|p| !p.is_empty()));
+    }
+}
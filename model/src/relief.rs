@@ -0,0 +1,135 @@
+use crate::geometry::GeoGrid;
+use crate::gradients::gradient_field;
+use image::{GrayImage, RgbImage};
+use nalgebra::{Vector2, Vector3};
+use rayon::prelude::*;
+
+/// Ocean depth (meters) of crust of a given age (Myr), from the half-space
+/// cooling model of Parsons & Sclater (1977). Good enough for shading.
+pub fn depth_from_age(age: f32) -> f32 {
+    2500.0 + 350.0 * age.max(0.0).sqrt()
+}
+
+/// Light source for [`hillshade`].
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    /// Radians clockwise from north (the conventional 315° is from the north-west).
+    pub azimuth: f32,
+    /// Radians above the horizon.
+    pub altitude: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            azimuth: 315f32.to_radians(),
+            altitude: 45f32.to_radians(),
+        }
+    }
+}
+
+/// Shaded relief of a scalar field, rows from +90° down: 255 where the surface
+/// faces the light, 0 where it faces away.
+///
+/// Slopes are the same tangent-plane gradients as the gradient map, fitted over
+/// the valid cells within `neighbor_radius` meters. `z_factor` converts field
+/// units to meters of height (e.g. `-1.0` for depths). NaN cells and cells
+/// without enough valid neighbors are shaded as flat.
+pub fn hillshade(
+    grid: &GeoGrid,
+    values: &[f32],
+    neighbor_radius: f32,
+    z_factor: f32,
+    light: Light,
+) -> GrayImage {
+    let l = Vector3::new(
+        light.azimuth.sin() * light.altitude.cos(),
+        light.azimuth.cos() * light.altitude.cos(),
+        light.altitude.sin(),
+    );
+    let raw: Vec<u8> = normals(grid, values, neighbor_radius, z_factor)
+        .into_par_iter()
+        .map(|n| (n.dot(&l).max(0.0) * 255.0).round() as u8)
+        .collect();
+    GrayImage::from_raw(grid.nx as u32, grid.ny as u32, raw).unwrap()
+}
+
+/// Surface normals of a scalar field as an RGB image, rows from +90° down:
+/// the east, north and up components mapped from [-1, 1] to [0, 255], for
+/// lighting the viewer's globe. Slopes as in [`hillshade`]; flat is
+/// (128, 128, 255).
+pub fn normal_map(grid: &GeoGrid, values: &[f32], neighbor_radius: f32, z_factor: f32) -> RgbImage {
+    let to_byte = |c: f32| ((c * 0.5 + 0.5) * 255.0).round() as u8;
+    let raw: Vec<u8> = normals(grid, values, neighbor_radius, z_factor)
+        .into_par_iter()
+        .flat_map_iter(|n| [to_byte(n.x), to_byte(n.y), to_byte(n.z)])
+        .collect();
+    RgbImage::from_raw(grid.nx as u32, grid.ny as u32, raw).unwrap()
+}
+
+// Unit normal of every cell in (east, north, up); up where there is no slope.
+fn normals(
+    grid: &GeoGrid,
+    values: &[f32],
+    neighbor_radius: f32,
+    z_factor: f32,
+) -> Vec<Vector3<f32>> {
+    gradient_field(grid, values, neighbor_radius)
+        .into_par_iter()
+        .map(|g| {
+            let g = if g.x.is_finite() && g.y.is_finite() {
+                g * z_factor
+            } else {
+                Vector2::zeros()
+            };
+            // surface normal of z = gx·east + gy·north
+            Vector3::new(-g.x, -g.y, 1.0).normalize()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::lon_of;
+
+    #[test]
+    fn test_slope_facing_light_is_brighter() {
+        let grid = GeoGrid {
+            nx: 360,
+            ny: 180,
+            radius: 6_371_000.0,
+        };
+        // height rises towards the east by 10 m per km
+        let values: Vec<f32> = (0..grid.nx * grid.ny)
+            .map(|i| lon_of(i % grid.nx, grid.nx) * grid.radius * 0.01)
+            .collect();
+        let center = (grid.ny / 2) * grid.nx + grid.nx / 2;
+        let radius = 300_000.0;
+
+        let from_east = Light {
+            azimuth: 90f32.to_radians(),
+            altitude: 30f32.to_radians(),
+        };
+        let from_west = Light {
+            azimuth: 270f32.to_radians(),
+            ..from_east
+        };
+        let lit = hillshade(&grid, &values, radius, 1.0, from_west);
+        let dark = hillshade(&grid, &values, radius, 1.0, from_east);
+        let flat = hillshade(&grid, &vec![1.0; values.len()], radius, 1.0, from_east);
+
+        let px = |img: &GrayImage| img.as_raw()[center];
+        assert!(px(&lit) > px(&flat), "{} vs {}", px(&lit), px(&flat));
+        assert!(px(&dark) < px(&flat), "{} vs {}", px(&dark), px(&flat));
+
+        // the slope faces west
+        let normals = normal_map(&grid, &values, radius, 1.0);
+        let [east, north, up] = normals.as_raw()[3 * center..3 * center + 3] else {
+            unreachable!()
+        };
+        assert!(east < 127, "{east}");
+        assert!(north.abs_diff(128) <= 1, "{north}");
+        assert!(up > 250, "{up}");
+    }
+}
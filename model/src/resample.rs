@@ -0,0 +1,310 @@
+use crate::geometry::{col_of, lat_of, lon_of, row_of, GeoGrid};
+use image::{Rgb, RgbImage};
+use rayon::prelude::*;
+
+/// How values are combined when remapping a field from one grid to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resampling {
+    /// Area-weighted mean of the overlapping source cells (continuous fields).
+    Conservative,
+    /// Value covering the largest area of the target cell (categorical fields).
+    Mode,
+    /// Source cell under the target cell center.
+    Nearest,
+}
+
+/// Remap a scalar field from `src` to `dst`.
+///
+/// Both grids are global equirectangular grids, so cells overlap by angle and
+/// the radius cancels out of the area weights: a mean stays a mean on the
+/// shrunk globe. Use [`resample_density`] for per-area quantities whose total
+/// must survive a change of radius.
+///
+/// NaN marks missing data. It is left out of the weighted mean and only
+/// produced where no valid source cell overlaps the target cell.
+pub fn resample(src: &GeoGrid, values: &[f32], dst: &GeoGrid, method: Resampling) -> Vec<f32> {
+    assert_eq!(values.len(), src.nx * src.ny);
+    match method {
+        Resampling::Conservative => conservative(src, dst, |i| [values[i]])
+            .into_iter()
+            .map(|[v]| v)
+            .collect(),
+        Resampling::Mode => resample_mode(src, values, dst),
+        Resampling::Nearest => resample_nearest(src, values, dst),
+    }
+}
+
+/// Remap a per-area quantity (e.g. mass per m²) so that its integral over the
+/// sphere is unchanged when `dst` has a different radius than `src`.
+pub fn resample_density(src: &GeoGrid, values: &[f32], dst: &GeoGrid) -> Vec<f32> {
+    let scale = (src.radius / dst.radius).powi(2);
+    resample(src, values, dst, Resampling::Conservative)
+        .into_par_iter()
+        .map(|v| v * scale)
+        .collect()
+}
+
+/// Remap categorical data (e.g. partition labels) by the label covering the
+/// largest area of each target cell.
+pub fn resample_mode<T>(src: &GeoGrid, labels: &[T], dst: &GeoGrid) -> Vec<T>
+where
+    T: Copy + PartialEq + Send + Sync,
+{
+    assert_eq!(labels.len(), src.nx * src.ny);
+    let rows = overlaps(src.ny, dst.ny, lat_edge_sin);
+    let cols = overlaps(src.nx, dst.nx, lon_edge);
+
+    let mut out = Vec::with_capacity(dst.nx * dst.ny);
+    (0..dst.nx * dst.ny)
+        .into_par_iter()
+        .map(|k| {
+            let (j, i) = (k / dst.nx, k % dst.nx);
+            let mut tally: Vec<(T, f64)> = Vec::new();
+            for &(sj, wy) in &rows[j] {
+                for &(si, wx) in &cols[i] {
+                    let label = labels[sj * src.nx + si];
+                    match tally.iter_mut().find(|(l, _)| *l == label) {
+                        Some((_, w)) => *w += wy * wx,
+                        None => tally.push((label, wy * wx)),
+                    }
+                }
+            }
+            tally
+                .into_iter()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(l, _)| l)
+                .unwrap()
+        })
+        .collect_into_vec(&mut out);
+    out
+}
+
+/// Remap any per-cell data by taking the source cell under each target center.
+pub fn resample_nearest<T>(src: &GeoGrid, values: &[T], dst: &GeoGrid) -> Vec<T>
+where
+    T: Copy + Send + Sync,
+{
+    assert_eq!(values.len(), src.nx * src.ny);
+    let cols: Vec<usize> = (0..dst.nx)
+        .map(|i| col_of(lon_of(i, dst.nx), src.nx))
+        .collect();
+
+    let mut out = Vec::with_capacity(dst.nx * dst.ny);
+    (0..dst.nx * dst.ny)
+        .into_par_iter()
+        .map(|k| {
+            let sj = row_of(lat_of(k / dst.nx, dst.ny), src.ny);
+            values[sj * src.nx + cols[k % dst.nx]]
+        })
+        .collect_into_vec(&mut out);
+    out
+}
+
+/// Resize an equirectangular image with area-weighted averaging, so that rows
+/// near the poles don't count as much as rows at the equator.
+pub fn resample_rgb(img: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let (w, h) = img.dimensions();
+    // only the cell shapes matter here, not the radius
+    let src = GeoGrid {
+        nx: w as usize,
+        ny: h as usize,
+        radius: 1.0,
+    };
+    let dst = GeoGrid {
+        nx: width as usize,
+        ny: height as usize,
+        radius: 1.0,
+    };
+    let pixels = conservative(&src, &dst, |i| {
+        let Rgb([r, g, b]) = *img.get_pixel((i % src.nx) as u32, (i / src.nx) as u32);
+        [r as f32, g as f32, b as f32]
+    });
+
+    let mut out = RgbImage::new(width, height);
+    for (px, [r, g, b]) in out.pixels_mut().zip(pixels) {
+        *px = Rgb([r.round() as u8, g.round() as u8, b.round() as u8]);
+    }
+    out
+}
+
+/// Area-weighted mean of `C` channels, skipping NaN per channel.
+fn conservative<const C: usize, F>(src: &GeoGrid, dst: &GeoGrid, sample: F) -> Vec<[f32; C]>
+where
+    F: Fn(usize) -> [f32; C] + Sync,
+{
+    let rows = overlaps(src.ny, dst.ny, lat_edge_sin);
+    let cols = overlaps(src.nx, dst.nx, lon_edge);
+
+    let mut out = Vec::with_capacity(dst.nx * dst.ny);
+    (0..dst.nx * dst.ny)
+        .into_par_iter()
+        .map(|k| {
+            let (j, i) = (k / dst.nx, k % dst.nx);
+            let mut sum = [0.0f64; C];
+            let mut wsum = [0.0f64; C];
+            for &(sj, wy) in &rows[j] {
+                for &(si, wx) in &cols[i] {
+                    let v = sample(sj * src.nx + si);
+                    for c in 0..C {
+                        if !v[c].is_nan() {
+                            sum[c] += wy * wx * v[c] as f64;
+                            wsum[c] += wy * wx;
+                        }
+                    }
+                }
+            }
+            let mut px = [f32::NAN; C];
+            for c in 0..C {
+                if wsum[c] > 0.0 {
+                    px[c] = (sum[c] / wsum[c]) as f32;
+                }
+            }
+            px
+        })
+        .collect_into_vec(&mut out);
+    out
+}
+
+/// For each of `n_dst` cells along one axis, the overlapping source cells and
+/// the overlap measure between them. `edge(k, n)` is the coordinate of the
+/// leading edge of cell `k` of `n`, increasing with `k`.
+fn overlaps(n_src: usize, n_dst: usize, edge: fn(usize, usize) -> f64) -> Vec<Vec<(usize, f64)>> {
+    (0..n_dst)
+        .map(|d| {
+            let (d0, d1) = (edge(d, n_dst), edge(d + 1, n_dst));
+            // first source cell that can overlap, from the fractional position
+            let s_first = d * n_src / n_dst;
+            (s_first..n_src)
+                .map(|s| (s, edge(s, n_src), edge(s + 1, n_src)))
+                .take_while(|&(_, s0, _)| s0 < d1)
+                .filter_map(|(s, s0, s1)| {
+                    let w = s1.min(d1) - s0.max(d0);
+                    (w > 0.0).then_some((s, w))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Rows run from +90° down, so use -sin(φ) to keep the edges increasing; the
+// difference between two edges is then the band's area on the unit sphere per
+// radian of longitude.
+fn lat_edge_sin(j: usize, ny: usize) -> f64 {
+    let phi = std::f64::consts::FRAC_PI_2 - std::f64::consts::PI * j as f64 / ny as f64;
+    -phi.sin()
+}
+
+fn lon_edge(i: usize, nx: usize) -> f64 {
+    std::f64::consts::TAU * i as f64 / nx as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_helpers::pixel_area_lookup;
+
+    fn integral(grid: &GeoGrid, values: &[f32]) -> f64 {
+        let (_, area) = pixel_area_lookup(grid.nx, grid.ny, grid.radius);
+        values
+            .iter()
+            .enumerate()
+            .map(|(k, &v)| v as f64 * area[k / grid.nx] as f64)
+            .sum()
+    }
+
+    #[test]
+    fn test_conservative_preserves_mean() {
+        let src = GeoGrid {
+            nx: 90,
+            ny: 45,
+            radius: 6_371_000.0,
+        };
+        let dst = GeoGrid {
+            nx: 36,
+            ny: 18,
+            radius: 6_371_000.0,
+        };
+        // field varying with latitude, where a naive average would be biased to the poles
+        let values: Vec<f32> = (0..src.nx * src.ny)
+            .map(|k| lat_of(k / src.nx, src.ny).cos() * 100.0)
+            .collect();
+
+        let out = resample(&src, &values, &dst, Resampling::Conservative);
+
+        let before = integral(&src, &values);
+        let after = integral(&dst, &out);
+        assert!(
+            (before - after).abs() / before < 0.01,
+            "integral changed: {before} → {after}"
+        );
+    }
+
+    #[test]
+    fn test_density_preserves_total_on_smaller_globe() {
+        let src = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 6_371_000.0,
+        };
+        let dst = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 3_000_000.0,
+        };
+        let values = vec![2.0; src.nx * src.ny];
+
+        let out = resample_density(&src, &values, &dst);
+
+        let before = integral(&src, &values);
+        let after = integral(&dst, &out);
+        assert!((before - after).abs() / before < 1e-4);
+    }
+
+    #[test]
+    fn test_conservative_skips_nodata() {
+        let src = GeoGrid {
+            nx: 4,
+            ny: 2,
+            radius: 1.0,
+        };
+        let dst = GeoGrid {
+            nx: 2,
+            ny: 1,
+            radius: 1.0,
+        };
+        let nan = f32::NAN;
+        let values = vec![1.0, 3.0, nan, nan, nan, 5.0, nan, nan];
+
+        let out = resample(&src, &values, &dst, Resampling::Conservative);
+
+        // both source rows have the same area, so the left cell is the mean of 1, 3 and 5
+        assert!((out[0] - 3.0).abs() < 1e-6, "{out:?}");
+        assert!(out[1].is_nan());
+    }
+
+    #[test]
+    fn test_mode_and_nearest_keep_labels() {
+        let src = GeoGrid {
+            nx: 8,
+            ny: 4,
+            radius: 1.0,
+        };
+        let dst = GeoGrid {
+            nx: 4,
+            ny: 2,
+            radius: 1.0,
+        };
+        // west half labelled 1, east half 2, with a stray 3 in one cell
+        let mut labels: Vec<usize> = (0..src.nx * src.ny)
+            .map(|k| if k % src.nx < src.nx / 2 { 1 } else { 2 })
+            .collect();
+        labels[0] = 3;
+
+        let mode = resample_mode(&src, &labels, &dst);
+        assert_eq!(mode, vec![1, 1, 2, 2, 1, 1, 2, 2]);
+
+        let nearest = resample_nearest(&src, &labels, &dst);
+        assert!(nearest.iter().all(|l| [1, 2, 3].contains(l)));
+        assert_eq!(nearest[3], 2);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::map_helpers::par_min_max;
+use image::{ImageFormat, RgbaImage};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// A scalar field (north-up, like `GeoGrid` cells) to export at 16 bits.
+///
+/// Values are quantized linearly over [min, max] to 1..=65535; 0 is reserved
+/// for NaN (no data). Values outside of the range are clamped.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub unit: String,
+    pub min: f32,
+    pub max: f32,
+    pub values: Vec<f32>,
+}
+
+impl Field {
+    /// A field spanning the range of its data.
+    pub fn new(name: &str, unit: &str, values: Vec<f32>) -> Self {
+        let (min, max) = par_min_max(&values);
+        let (min, max) = if min <= max { (min, max) } else { (0.0, 0.0) };
+        Self {
+            name: name.to_string(),
+            unit: unit.to_string(),
+            min,
+            max,
+            values,
+        }
+    }
+
+    /// Quantize over [min, max] instead, e.g. to leave out outliers.
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn encode(&self, v: f32) -> u16 {
+        if v.is_nan() {
+            return 0;
+        }
+        let span = self.max - self.min;
+        let t = if span > 0.0 {
+            ((v - self.min) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        1 + (t * 65534.0).round() as u16
+    }
+
+    pub fn decode(&self, q: u16) -> f32 {
+        decode(q, self.min, self.max)
+    }
+}
+
+/// Inverse of [`Field::encode`] for a field quantized over [min, max].
+pub fn decode(q: u16, min: f32, max: f32) -> f32 {
+    if q == 0 {
+        return f32::NAN;
+    }
+    min + (q - 1) as f32 / 65534.0 * (max - min)
+}
+
+/// The value below which a fraction `q` of the non-NaN `values` fall.
+pub fn quantile(values: &[f32], q: f32) -> f32 {
+    let mut sorted: Vec<f32> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    if sorted.is_empty() {
+        return f32::NAN;
+    }
+    sorted.sort_unstable_by(f32::total_cmp);
+    let k = ((sorted.len() - 1) as f32 * q.clamp(0.0, 1.0)).round() as usize;
+    sorted[k]
+}
+
+/// Pack one or two fields into an RGBA image: the first one big-endian in red
+/// and green, the second in blue and alpha (zero if there is none).
+pub fn pack_fields(width: u32, height: u32, rg: &Field, ba: Option<&Field>) -> RgbaImage {
+    let n = (width * height) as usize;
+    assert_eq!(rg.values.len(), n);
+    let mut raw = Vec::with_capacity(4 * n);
+    for i in 0..n {
+        let a = rg.encode(rg.values[i]).to_be_bytes();
+        let b = ba.map_or(0, |f| f.encode(f.values[i])).to_be_bytes();
+        raw.extend_from_slice(&[a[0], a[1], b[0], b[1]]);
+    }
+    RgbaImage::from_raw(width, height, raw).unwrap()
+}
+
+/// The quantized values of one of the two fields of a packed image
+/// (`second` for blue and alpha).
+pub fn unpack_field(img: &RgbaImage, second: bool) -> Vec<u16> {
+    let k = if second { 2 } else { 0 };
+    img.pixels()
+        .map(|px| u16::from_be_bytes([px[k], px[k + 1]]))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LosslessFormat {
+    Png,
+    WebP,
+}
+
+impl LosslessFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LosslessFormat::Png => "png",
+            LosslessFormat::WebP => "webp",
+        }
+    }
+}
+
+pub fn save_lossless(
+    img: &RgbaImage,
+    format: LosslessFormat,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        LosslessFormat::Png => img.save_with_format(path, ImageFormat::Png)?,
+        LosslessFormat::WebP => {
+            let (w, h) = img.dimensions();
+            let mut config = webp::WebPConfig::new().map_err(|_| "invalid WebP config")?;
+            config.lossless = 1;
+            // keep the color of transparent pixels: alpha is data here
+            config.exact = 1;
+            let webp = webp::Encoder::from_rgba(img.as_raw(), w, h)
+                .encode_advanced(&config)
+                .map_err(|e| format!("WebP encoding failed: {e:?}"))?;
+            fs::write(path, &*webp)?;
+        }
+    }
+    Ok(())
+}
+
+/// How to decode a field of an exported image.
+#[derive(Debug, Serialize)]
+pub struct FieldInfo {
+    pub name: String,
+    pub unit: String,
+    /// `"rg"` or `"ba"`: the two channels holding the big-endian 16-bit value.
+    pub channels: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackedImage {
+    pub file: String,
+    pub fields: Vec<FieldInfo>,
+}
+
+/// Description of exported scalar fields, saved as `metadata.json`.
+#[derive(Debug, Serialize)]
+pub struct ScalarMetadata {
+    pub width: u32,
+    pub height: u32,
+    /// How to get a value from its 16-bit code `q`.
+    pub encoding: String,
+    pub images: Vec<PackedImage>,
+}
+
+/// Save each `(name, first, second)` pair of fields as a lossless
+/// `out_dir/{name}.{png,webp}` and describe them all in
+/// `out_dir/metadata.json`. Images are north-up, `width`×`height`
+/// equirectangular, and must be decoded without premultiplied alpha or color
+/// conversion.
+pub fn export_scalars(
+    out_dir: &Path,
+    width: u32,
+    height: u32,
+    images: &[(&str, &Field, Option<&Field>)],
+    format: LosslessFormat,
+) -> Result<ScalarMetadata, Box<dyn Error>> {
+    fs::create_dir_all(out_dir)?;
+    let info = |f: &Field, channels: &str| FieldInfo {
+        name: f.name.clone(),
+        unit: f.unit.clone(),
+        channels: channels.to_string(),
+        min: f.min,
+        max: f.max,
+    };
+    let mut packed = vec![];
+    for &(name, rg, ba) in images {
+        let file = format!("{name}.{}", format.extension());
+        save_lossless(
+            &pack_fields(width, height, rg, ba),
+            format,
+            &out_dir.join(&file),
+        )?;
+        let mut fields = vec![info(rg, "rg")];
+        fields.extend(ba.map(|f| info(f, "ba")));
+        packed.push(PackedImage { file, fields });
+    }
+
+    let metadata = ScalarMetadata {
+        width,
+        height,
+        encoding: "q = 0: no data, else min + (q - 1) / 65534 * (max - min)".to_string(),
+        images: packed,
+    };
+    fs::write(
+        out_dir.join("metadata.json"),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let age = Field::new("age", "Myr", vec![0.0, 1.5, f32::NAN, 280.0]);
+        let east = Field::new("east", "Myr/km", vec![-0.3, 0.0, 0.3, 9.0]).with_range(-0.3, 0.3);
+        assert_eq!((age.min, age.max), (0.0, 280.0));
+
+        let dir = std::env::temp_dir().join(format!("small_world_scalars_{}", std::process::id()));
+        for format in [LosslessFormat::Png, LosslessFormat::WebP] {
+            let meta =
+                export_scalars(&dir, 2, 2, &[("fields", &age, Some(&east))], format).unwrap();
+            assert_eq!(meta.images[0].fields[1].channels, "ba");
+
+            let path = dir.join(&meta.images[0].file);
+            let img = image::open(&path).unwrap().to_rgba8();
+            let ages: Vec<f32> = unpack_field(&img, false)
+                .into_iter()
+                .map(|q| age.decode(q))
+                .collect();
+            let easts: Vec<f32> = unpack_field(&img, true)
+                .into_iter()
+                .map(|q| east.decode(q))
+                .collect();
+
+            for (a, b) in ages.iter().zip(&age.values) {
+                assert!(
+                    a.is_nan() && b.is_nan() || (a - b).abs() < 280.0 / 65534.0,
+                    "{format:?}"
+                );
+            }
+            assert!((easts[0] + 0.3).abs() < 1e-5);
+            assert!(easts[1].abs() < 1e-5);
+            assert!((easts[3] - 0.3).abs() < 1e-5, "clamped to the range");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_quantile() {
+        let values = [5.0, f32::NAN, 1.0, 3.0, 2.0, 4.0];
+        assert_eq!(quantile(&values, 0.0), 1.0);
+        assert_eq!(quantile(&values, 0.5), 3.0);
+        assert_eq!(quantile(&values, 1.0), 5.0);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::geojson::LonLat;
+use crate::geometry::{col_of, row_of, GeoGrid};
+use nalgebra::{Vector2, Vector3};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// How to trace streamlines; lengths are in meters.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamlineOptions {
+    /// Lines are seeded this far apart and stop when they come closer than
+    /// about this to another line.
+    pub separation: f32,
+    /// Integration step.
+    pub step: f32,
+    pub max_length: f32,
+    /// Shorter lines are dropped.
+    pub min_length: f32,
+    /// Lines stop where the gradient is weaker than this (value units per
+    /// meter), e.g. on flat crust where its direction is noise.
+    pub min_gradient: f32,
+}
+
+impl Default for StreamlineOptions {
+    fn default() -> Self {
+        Self {
+            separation: 300_000.0,
+            step: 20_000.0,
+            max_length: 5_000_000.0,
+            min_length: 200_000.0,
+            min_gradient: 0.0,
+        }
+    }
+}
+
+/// Evenly spaced lines following a gradient field (see
+/// [`crate::gradients::gradient_field`]), each running uphill: for crust
+/// ages, from the ridge towards older crust. Lines stop at NaN cells, where
+/// the gradient is too weak, or where it turns back (a ridge or trough).
+pub fn streamlines(
+    grid: &GeoGrid,
+    gradients: &[Vector2<f32>],
+    options: &StreamlineOptions,
+) -> Vec<Vec<LonLat>> {
+    assert_eq!(gradients.len(), grid.nx * grid.ny);
+    let sep = options.separation / grid.radius; // radians
+    let mut occupancy = Occupancy::new(sep);
+    let mut lines = vec![];
+
+    let rows = (PI / sep).floor().max(1.0) as usize;
+    for r in 0..rows {
+        let lat = FRAC_PI_2 - (r as f32 + 0.5) * sep;
+        let cols = ((TAU * lat.cos() / sep).round() as usize).max(1);
+        for c in 0..cols {
+            let lon = -PI + (c as f32 + 0.5) * TAU / cols as f32;
+            let seed = to_vec(lat, lon);
+            if occupancy.taken(seed) {
+                continue;
+            }
+            let forward = trace(grid, gradients, options, &occupancy, seed, 1.0);
+            let backward = trace(grid, gradients, options, &occupancy, seed, -1.0);
+            let steps = forward.len() + backward.len();
+            if (steps as f32) * options.step < options.min_length {
+                continue;
+            }
+            let line: Vec<Vector3<f32>> = backward
+                .into_iter()
+                .rev()
+                .chain(std::iter::once(seed))
+                .chain(forward)
+                .collect();
+            for &p in &line {
+                occupancy.take(p);
+            }
+            lines.push(line.into_iter().map(to_lon_lat).collect());
+        }
+    }
+    lines
+}
+
+// Points after `start` along `sign` × the gradient, until a stop condition.
+fn trace(
+    grid: &GeoGrid,
+    gradients: &[Vector2<f32>],
+    options: &StreamlineOptions,
+    occupancy: &Occupancy,
+    start: Vector3<f32>,
+    sign: f32,
+) -> Vec<Vector3<f32>> {
+    let step = options.step / grid.radius;
+    let max_steps = (options.max_length / options.step) as usize;
+    let direction = |p: Vector3<f32>| -> Option<Vector3<f32>> {
+        let (lat, lon) = (p.z.clamp(-1.0, 1.0).asin(), p.y.atan2(p.x));
+        let g = gradients[row_of(lat, grid.ny) * grid.nx + col_of(lon, grid.nx)];
+        let norm = g.norm();
+        if norm.is_nan() || norm <= options.min_gradient || norm == 0.0 {
+            return None;
+        }
+        let east = Vector3::new(-lon.sin(), lon.cos(), 0.0);
+        let north = p.cross(&east);
+        Some(sign * (g.x * east + g.y * north) / norm)
+    };
+
+    let mut out = vec![];
+    let mut p = start;
+    let mut last: Option<Vector3<f32>> = None;
+    for _ in 0..max_steps {
+        // midpoint method
+        let Some(d1) = direction(p) else { break };
+        let Some(d2) = direction(advance(p, d1, step / 2.0)) else {
+            break;
+        };
+        if last.is_some_and(|l| l.dot(&d2) < 0.0) {
+            break;
+        }
+        let next = advance(p, d2, step);
+        if occupancy.taken(next) {
+            break;
+        }
+        out.push(next);
+        last = Some(d2);
+        p = next;
+    }
+    out
+}
+
+// Move `angle` radians from `p` along the tangent direction `d`.
+fn advance(p: Vector3<f32>, d: Vector3<f32>, angle: f32) -> Vector3<f32> {
+    (p * angle.cos() + d * angle.sin()).normalize()
+}
+
+fn to_vec(lat: f32, lon: f32) -> Vector3<f32> {
+    Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin())
+}
+
+fn to_lon_lat(p: Vector3<f32>) -> LonLat {
+    (
+        p.y.atan2(p.x).to_degrees(),
+        p.z.clamp(-1.0, 1.0).asin().to_degrees(),
+    )
+}
+
+// Equirectangular cells about `sep` radians tall, marked along the lines.
+struct Occupancy {
+    nx: usize,
+    ny: usize,
+    cells: Vec<bool>,
+}
+
+impl Occupancy {
+    fn new(sep: f32) -> Self {
+        let ny = (PI / sep).ceil().max(1.0) as usize;
+        Self {
+            nx: 2 * ny,
+            ny,
+            cells: vec![false; 2 * ny * ny],
+        }
+    }
+
+    fn index(&self, p: Vector3<f32>) -> usize {
+        let (lat, lon) = (p.z.clamp(-1.0, 1.0).asin(), p.y.atan2(p.x));
+        row_of(lat, self.ny) * self.nx + col_of(lon, self.nx)
+    }
+
+    fn taken(&self, p: Vector3<f32>) -> bool {
+        self.cells[self.index(p)]
+    }
+
+    fn take(&mut self, p: Vector3<f32>) {
+        let i = self.index(p);
+        self.cells[i] = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streamlines_follow_gradient() {
+        let grid = GeoGrid {
+            nx: 72,
+            ny: 36,
+            radius: 6_371_000.0,
+        };
+        // older to the east between 60°S and 60°N, no data elsewhere
+        let gradients: Vec<Vector2<f32>> = (0..grid.nx * grid.ny)
+            .map(|i| {
+                let lat = crate::geometry::lat_of(i / grid.nx, grid.ny).to_degrees();
+                if lat.abs() < 60.0 {
+                    Vector2::new(1e-5, 0.0)
+                } else {
+                    Vector2::new(f32::NAN, f32::NAN)
+                }
+            })
+            .collect();
+        let options = StreamlineOptions {
+            separation: 1_000_000.0,
+            step: 50_000.0,
+            max_length: 2_000_000.0,
+            min_length: 500_000.0,
+            min_gradient: 0.0,
+        };
+        let lines = streamlines(&grid, &gradients, &options);
+        assert!(!lines.is_empty());
+        for line in &lines {
+            assert!(line.len() >= 10);
+            for pair in line.windows(2) {
+                let ((lon0, lat0), (lon1, lat1)) = (pair[0], pair[1]);
+                let dlon = (lon1 - lon0 + 540.0).rem_euclid(360.0) - 180.0;
+                assert!(dlon > 0.0, "{pair:?} should run east");
+                assert!(
+                    (lat1 - lat0).abs() < 0.1,
+                    "{pair:?} should keep its latitude"
+                );
+                assert!(lat0.abs() < 61.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::image::save_webp_lossy;
+use crate::resample::resample_rgb;
+use image::RgbImage;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Description of an exported tile pyramid, saved as `manifest.json` next to
+/// the tiles.
+///
+/// Tiles form an equirectangular quadtree: level `z` has `2^(z+1)` columns and
+/// `2^z` rows of `tile_size`² tiles, stored north-up at `{z}/{x}/{y}.webp` with
+/// `x = 0` starting at -180° and `y = 0` at +90°.
+#[derive(Debug, Serialize)]
+pub struct TileManifest {
+    pub tile_size: u32,
+    pub max_level: u32,
+    pub format: String,
+}
+
+/// Number of levels needed to show an image `width` pixels wide at full
+/// resolution, minus one (i.e. the deepest level).
+pub fn max_level_for(width: u32, tile_size: u32) -> u32 {
+    let mut z = 0;
+    while tile_size << (z + 1) < width {
+        z += 1;
+    }
+    z
+}
+
+/// Cut a north-up equirectangular image into a pyramid of lossy WebP tiles
+/// under `out_dir`, from level 0 (the whole globe in two tiles) down to
+/// `max_level`. Each level is an area-weighted downsampling of the one below.
+pub fn export_tiles(
+    img: &RgbImage,
+    out_dir: &Path,
+    tile_size: u32,
+    max_level: u32,
+    quality: f32,
+) -> Result<TileManifest, Box<dyn Error>> {
+    let mut level = img.clone();
+    for z in (0..=max_level).rev() {
+        let (cols, rows) = (2u32 << z, 1u32 << z);
+        level = resample_rgb(&level, cols * tile_size, rows * tile_size);
+
+        (0..cols * rows)
+            .into_par_iter()
+            .try_for_each(|k| -> std::io::Result<()> {
+                let (x, y) = (k % cols, k / cols);
+                let tile = image::imageops::crop_imm(
+                    &level,
+                    x * tile_size,
+                    y * tile_size,
+                    tile_size,
+                    tile_size,
+                )
+                .to_image();
+                let dir = out_dir.join(z.to_string()).join(x.to_string());
+                fs::create_dir_all(&dir)?;
+                save_webp_lossy(&tile, quality, &dir.join(format!("{y}.webp")))
+            })?;
+        println!("Level {z}: {cols}×{rows} tiles");
+    }
+
+    let manifest = TileManifest {
+        tile_size,
+        max_level,
+        format: "webp".to_string(),
+    };
+    fs::write(
+        out_dir.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_export_tiles() {
+        assert_eq!(max_level_for(8192, 256), 4);
+        assert_eq!(max_level_for(8000, 256), 4);
+        assert_eq!(max_level_for(512, 256), 0);
+
+        // west half red, east half blue
+        let img = RgbImage::from_fn(64, 32, |x, _| {
+            if x < 32 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("small_world_tiles_{}", std::process::id()));
+        let manifest = export_tiles(&img, &dir, 8, 2, 100.0).unwrap();
+        assert_eq!(manifest.max_level, 2);
+
+        for (z, cols, rows) in [(0, 2, 1), (1, 4, 2), (2, 8, 4)] {
+            for x in 0..cols {
+                for y in 0..rows {
+                    let path = dir.join(format!("{z}/{x}/{y}.webp"));
+                    let tile = image::open(&path).unwrap().to_rgb8();
+                    assert_eq!(tile.dimensions(), (8, 8));
+                    let Rgb([r, _, b]) = *tile.get_pixel(4, 4);
+                    if x < cols / 2 {
+                        assert!(r > b, "{path:?} should be red");
+                    } else {
+                        assert!(b > r, "{path:?} should be blue");
+                    }
+                }
+            }
+        }
+        assert!(dir.join("manifest.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
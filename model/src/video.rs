@@ -1,70 +1,645 @@
-use std::io::Write;
-use std::process::{Command, Stdio};
+use image::{DynamicImage, ImageFormat};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-pub fn make_video<F>(
+/// Output format of [`make_video`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Av1,
+    Vp9,
+    H264,
+    /// One image per frame (`frame_00000.png`, …) in the output directory,
+    /// for when ffmpeg isn't available or frames are post-processed elsewhere.
+    ImageSequence(FrameFormat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    Png,
+    /// Lossless WebP.
+    WebP,
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoOptions {
+    pub fps: u32,
+    pub codec: Codec,
+    /// Constant rate factor (lower is better). Ignored when `bitrate` is set.
+    pub crf: u32,
+    /// Target bitrate in ffmpeg notation, e.g. `"4M"`.
+    pub bitrate: Option<String>,
+    /// Encoder speed/quality trade-off: `-cpu-used` for AV1 and VP9 (e.g.
+    /// `"4"`), `-preset` for H.264 (e.g. `"slow"`).
+    pub preset: Option<String>,
+    /// Keep the frames' alpha channel. Only VP9 and image sequences support it.
+    pub alpha: bool,
+    /// Replace an existing output instead of failing.
+    pub overwrite: bool,
+    /// First frame to render, to resume an interrupted image sequence.
+    pub start_frame: u32,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        Self {
+            fps: 30,
+            codec: Codec::Av1,
+            crf: 30,
+            bitrate: None,
+            preset: None,
+            alpha: false,
+            overwrite: true,
+            start_frame: 0,
+        }
+    }
+}
+
+/// Encode `frame_count` frames of `width`×`height` from `next_frame`.
+///
+/// Frames are converted to RGB, or RGBA when `options.alpha` is set. If
+/// ffmpeg fails, the returned error carries its exit status and stderr.
+pub fn make_video<F, I>(
     width: u32,
     height: u32,
-    fps: u32,
-    duration_sec: u32,
+    frame_count: u32,
     out_path: &str,
+    options: &VideoOptions,
     mut next_frame: F,
 ) -> std::io::Result<()>
 where
-    F: FnMut(u32) -> image::RgbImage,
+    F: FnMut(u32) -> I,
+    I: Into<DynamicImage>,
 {
-    let args = ffmpeg_args(width, height, fps, Codec::Av1, "30", out_path);
+    check_options(options)?;
+    if let Codec::ImageSequence(format) = options.codec {
+        for frame_idx in options.start_frame..frame_count {
+            save_frame(
+                Path::new(out_path),
+                frame_idx,
+                format,
+                options,
+                next_frame(frame_idx),
+            )?;
+        }
+        return Ok(());
+    }
 
-    if std::fs::exists(out_path)? {
-        std::fs::remove_file(out_path)?;
+    let mut ffmpeg = Ffmpeg::spawn(&ffmpeg_args(width, height, options, out_path))?;
+    let mut written = Ok(());
+    for frame_idx in 0..frame_count {
+        written = ffmpeg.write_frame(next_frame(frame_idx).into(), options.alpha);
+        if written.is_err() {
+            break; // ffmpeg is gone; its stderr says why
+        }
     }
+    ffmpeg.finish(written)
+}
+
+/// Frames written so far by [`make_video_parallel`].
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// Index of the frame just written.
+    pub frame_idx: u32,
+    /// Frames written (and still to write) in this run.
+    pub done: u32,
+    pub total: u32,
+    pub elapsed: Duration,
+    /// Estimated time left, from the average time per frame so far.
+    pub eta: Duration,
+}
 
-    let mut ffmpeg = Command::new("ffmpeg")
-        .args(&args)
-        .stdin(Stdio::piped())
-        .spawn()?;
+/// Prints one line per frame, e.g. `frame 12/181 (6.6%) 00:01:23, ETA 00:20:00`.
+pub fn print_progress(p: Progress) {
+    println!(
+        "frame {}/{} ({:.1}%) {}, ETA {}",
+        p.frame_idx + 1,
+        p.frame_idx + 1 + p.total - p.done,
+        100.0 * p.done as f32 / p.total.max(1) as f32,
+        hms(p.elapsed),
+        hms(p.eta)
+    );
+}
+
+/// Like [`make_video`], but renders frames in parallel on the rayon pool.
+///
+/// Frame `i` isn't started before frame `i - lookahead` is written, so at most
+/// `lookahead` frames are held at once (rendering, finished or being written)
+/// however long the video is, and a slow frame only holds back the frames
+/// `lookahead` after it. Frames are still written in order, and `on_progress`
+/// is called after each one.
+///
+/// To resume after an interruption, render to an image sequence, restart with
+/// `options.start_frame` set to [`first_missing_frame`], then encode the frames
+/// with [`encode_image_sequence`]. A container cut off mid-write can't be
+/// appended to, so `start_frame` is only accepted for image sequences.
+#[allow(clippy::too_many_arguments)]
+pub fn make_video_parallel<F, I, P>(
+    width: u32,
+    height: u32,
+    frame_count: u32,
+    out_path: &str,
+    options: &VideoOptions,
+    lookahead: usize,
+    next_frame: F,
+    mut on_progress: P,
+) -> std::io::Result<()>
+where
+    F: Fn(u32) -> I + Sync,
+    I: Into<DynamicImage> + Send,
+    P: FnMut(Progress) + Send,
+{
+    check_options(options)?;
+    let lookahead = lookahead.max(1);
+    let start = options.start_frame.min(frame_count);
+    let total = frame_count - start;
 
-    let stdin = ffmpeg.stdin.as_mut().unwrap();
+    let mut ffmpeg = match options.codec {
+        Codec::ImageSequence(_) => None,
+        _ => Some(Ffmpeg::spawn(&ffmpeg_args(
+            width, height, options, out_path,
+        ))?),
+    };
 
-    for frame_idx in 0..(duration_sec * fps) {
-        let img = next_frame(frame_idx);
-        stdin.write_all(&img)?;
+    let started = Instant::now();
+    // the next frame to write, and whether writing (or a render) failed
+    let next = AtomicU32::new(start);
+    let window = (Mutex::new((start, false)), Condvar::new());
+    let (tx, rx) = channel::<(u32, DynamicImage)>();
+    let written = std::thread::scope(|scope| {
+        let writer = scope.spawn(|| -> std::io::Result<()> {
+            let mut pending = BTreeMap::new();
+            let mut write = |frame_idx: u32, img: DynamicImage| -> std::io::Result<()> {
+                match (&mut ffmpeg, options.codec) {
+                    (Some(ffmpeg), _) => ffmpeg.write_frame(img, options.alpha),
+                    (None, Codec::ImageSequence(format)) => {
+                        save_frame(Path::new(out_path), frame_idx, format, options, img)
+                    }
+                    (None, _) => unreachable!(),
+                }
+            };
+            let mut done = 0;
+            let result = rx.into_iter().try_for_each(|(frame_idx, img)| {
+                pending.insert(frame_idx, img);
+                while let Some(img) = pending.remove(&(start + done)) {
+                    let frame_idx = start + done;
+                    write(frame_idx, img)?;
+                    done += 1;
+                    let elapsed = started.elapsed();
+                    on_progress(Progress {
+                        frame_idx,
+                        done,
+                        total,
+                        elapsed,
+                        eta: elapsed.mul_f64((total - done) as f64 / done as f64),
+                    });
+                    window.0.lock().unwrap().0 = frame_idx + 1;
+                    window.1.notify_all();
+                }
+                Ok(())
+            });
+            if result.is_err() {
+                window.0.lock().unwrap().1 = true;
+                window.1.notify_all();
+            }
+            result
+        });
+
+        let workers = rayon::current_num_threads().min(lookahead);
+        (0..workers)
+            .into_par_iter()
+            .for_each_with(tx, |tx, _| loop {
+                let _abort = AbortOnPanic(&window);
+                let frame_idx = next.fetch_add(1, Ordering::Relaxed);
+                if frame_idx >= frame_count {
+                    break;
+                }
+                let (_, failed) = *window
+                    .1
+                    .wait_while(window.0.lock().unwrap(), |&mut (to_write, failed)| {
+                        !failed && frame_idx >= to_write + lookahead as u32
+                    })
+                    .unwrap();
+                if failed || tx.send((frame_idx, next_frame(frame_idx).into())).is_err() {
+                    break; // the writer failed
+                }
+            });
+        writer.join().unwrap()
+    });
+
+    match ffmpeg {
+        Some(ffmpeg) => ffmpeg.finish(written),
+        None => written,
     }
+}
 
-    let _ = stdin;
-    ffmpeg.wait()?;
-    Ok(())
+// Marks the window of `make_video_parallel` failed if a frame's render panics,
+// so the workers waiting on that frame stop and the panic reaches the caller.
+struct AbortOnPanic<'a>(&'a (Mutex<(u32, bool)>, Condvar));
+
+impl Drop for AbortOnPanic<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            if let Ok(mut window) = self.0 .0.lock() {
+                window.1 = true;
+            }
+            self.0 .1.notify_all();
+        }
+    }
 }
 
-enum Codec {
-    Av1,
+/// Index of the first frame of an image sequence that hasn't been written yet.
+pub fn first_missing_frame(dir: &Path, format: FrameFormat, frame_count: u32) -> u32 {
+    (0..frame_count)
+        .find(|&i| !frame_path(dir, i, format).exists())
+        .unwrap_or(frame_count)
+}
+
+/// Encode a finished image sequence in `dir` (as written with
+/// `Codec::ImageSequence(format)`) into a video with `options.codec`.
+pub fn encode_image_sequence(
+    dir: &Path,
+    format: FrameFormat,
+    out_path: &str,
+    options: &VideoOptions,
+) -> std::io::Result<()> {
+    check_options(options)?;
+    let pattern = dir.join(match format {
+        FrameFormat::Png => "frame_%05d.png",
+        FrameFormat::WebP => "frame_%05d.webp",
+    });
+    let fps = options.fps.to_string();
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    args.push(if options.overwrite { "-y" } else { "-n" }.into());
+    args.extend(["-framerate".into(), fps, "-i".into()]);
+    args.push(pattern.to_string_lossy().into_owned());
+    args.extend(encoder_args(options, out_path));
+
+    let ffmpeg = Ffmpeg::spawn(&args)?;
+    ffmpeg.finish(Ok(()))
+}
+
+fn check_options(options: &VideoOptions) -> std::io::Result<()> {
+    let invalid = |msg: String| Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    match options.codec {
+        Codec::ImageSequence(_) => Ok(()),
+        Codec::Vp9 | Codec::Av1 | Codec::H264 if options.start_frame > 0 => invalid(format!(
+            "{:?} can't resume at frame {}, render an image sequence instead",
+            options.codec, options.start_frame
+        )),
+        Codec::Av1 | Codec::H264 if options.alpha => invalid(format!(
+            "{:?} can't encode alpha, use VP9 or an image sequence",
+            options.codec
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A running ffmpeg reading raw frames from stdin.
+struct Ffmpeg {
+    child: Child,
+    stdin: ChildStdin,
+    stderr: JoinHandle<String>,
+}
+
+impl Ffmpeg {
+    fn spawn(args: &[String]) -> std::io::Result<Self> {
+        let mut child = Command::new("ffmpeg")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // drain stderr on its own thread so ffmpeg never blocks on a full pipe
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr = std::thread::spawn(move || {
+            let mut log = String::new();
+            let _ = stderr.read_to_string(&mut log);
+            log
+        });
+        let stdin = child.stdin.take().unwrap();
+        Ok(Self {
+            child,
+            stdin,
+            stderr,
+        })
+    }
+
+    fn write_frame(&mut self, img: DynamicImage, alpha: bool) -> std::io::Result<()> {
+        self.stdin.write_all(&frame_bytes(img, alpha))
+    }
+
+    /// Close stdin and wait for ffmpeg. Its failure takes precedence over
+    /// `written`, as a broken pipe is usually just the symptom.
+    fn finish(self, written: std::io::Result<()>) -> std::io::Result<()> {
+        let Self {
+            mut child,
+            stdin,
+            stderr,
+        } = self;
+        drop(stdin);
+        let status = child.wait()?;
+        let log = stderr.join().unwrap_or_default();
+        if !status.success() {
+            return Err(std::io::Error::other(format!(
+                "ffmpeg exited with {status}: {}",
+                log.trim()
+            )));
+        }
+        written
+    }
+}
+
+fn hms(d: Duration) -> String {
+    let s = d.as_secs();
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+fn frame_bytes(img: DynamicImage, alpha: bool) -> Vec<u8> {
+    if alpha {
+        img.into_rgba8().into_raw()
+    } else {
+        img.into_rgb8().into_raw()
+    }
+}
+
+fn frame_path(dir: &Path, frame_idx: u32, format: FrameFormat) -> std::path::PathBuf {
+    let ext = match format {
+        FrameFormat::Png => "png",
+        FrameFormat::WebP => "webp",
+    };
+    dir.join(format!("frame_{frame_idx:05}.{ext}"))
+}
+
+fn save_frame<I: Into<DynamicImage>>(
+    dir: &Path,
+    frame_idx: u32,
+    format: FrameFormat,
+    options: &VideoOptions,
+    img: I,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let path = frame_path(dir, frame_idx, format);
+    if !options.overwrite && path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    let img: DynamicImage = img.into();
+    let img = if options.alpha {
+        DynamicImage::ImageRgba8(img.into_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(img.into_rgb8())
+    };
+    let format = match format {
+        FrameFormat::Png => ImageFormat::Png,
+        FrameFormat::WebP => ImageFormat::WebP,
+    };
+    img.save_with_format(&path, format)
+        .map_err(std::io::Error::other)
 }
 
 #[rustfmt::skip]
 fn ffmpeg_args(
     width: u32,
     height: u32,
-    fps: u32,
-    codec: Codec,
-    quality: &str,
+    options: &VideoOptions,
     output: &str,
 ) -> Vec<String> {
 	let size = format!("{}x{}", width, height);
-	let fps = fps.to_string();
-    match codec {
-        Codec::Av1 => vec![
-            "-f", "rawvideo",
-            "-pix_fmt", "rgb24",
-            "-s", &size,
-            "-r", &fps,
-            "-i", "-",
-            "-c:v", "libaom-av1",
-            "-crf", quality,
-            "-b:v", "0",
-            "-cpu-used", "4",
-            output,
-        ],
+	let fps = options.fps.to_string();
+	let in_pix_fmt = if options.alpha { "rgba" } else { "rgb24" };
+	let mut args: Vec<String> = vec![
+		"-hide_banner",
+		"-loglevel", "error",
+		if options.overwrite { "-y" } else { "-n" },
+		"-f", "rawvideo",
+		"-pix_fmt", in_pix_fmt,
+		"-s", &size,
+		"-r", &fps,
+		"-i", "-",
+	]
+	.into_iter()
+	.map(String::from)
+	.collect();
+	args.extend(encoder_args(options, output));
+	args
+}
+
+#[rustfmt::skip]
+fn encoder_args(options: &VideoOptions, output: &str) -> Vec<String> {
+	let crf = options.crf.to_string();
+	let out_pix_fmt = if options.alpha { "yuva420p" } else { "yuv420p" };
+	let mut args = vec![];
+
+	// rate control: a target bitrate, or constant quality
+	let rate = match &options.bitrate {
+		Some(bitrate) => vec!["-b:v", bitrate],
+		None if options.codec == Codec::H264 => vec!["-crf", &crf],
+		None => vec!["-crf", &crf, "-b:v", "0"],
+	};
+
+	match options.codec {
+		Codec::Av1 => {
+			args.extend(["-c:v", "libaom-av1"]);
+			args.extend(rate);
+			args.extend(["-cpu-used", options.preset.as_deref().unwrap_or("4")]);
+		}
+		Codec::Vp9 => {
+			args.extend(["-c:v", "libvpx-vp9"]);
+			args.extend(rate);
+			args.extend(["-row-mt", "1"]);
+			args.extend(["-cpu-used", options.preset.as_deref().unwrap_or("2")]);
+		}
+		Codec::H264 => {
+			args.extend(["-c:v", "libx264"]);
+			args.extend(rate);
+			args.extend(["-preset", options.preset.as_deref().unwrap_or("medium")]);
+		}
+		Codec::ImageSequence(_) => unreachable!("image sequences are written without ffmpeg"),
 	}
-    .into_iter()
-    .map(String::from)
-    .collect()
+	args.extend(["-pix_fmt", out_pix_fmt, output]);
+
+	args.into_iter().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn test_ffmpeg_args_rate_control() {
+        let crf = ffmpeg_args(64, 32, &VideoOptions::default(), "out.webm");
+        assert!(crf.windows(2).any(|w| w == ["-crf", "30"]));
+        assert!(crf.windows(2).any(|w| w == ["-c:v", "libaom-av1"]));
+
+        let options = VideoOptions {
+            codec: Codec::H264,
+            bitrate: Some("4M".into()),
+            ..Default::default()
+        };
+        let bitrate = ffmpeg_args(64, 32, &options, "out.mp4");
+        assert!(bitrate.windows(2).any(|w| w == ["-b:v", "4M"]));
+        assert!(!bitrate.contains(&"-crf".to_string()));
+        assert!(bitrate.windows(2).any(|w| w == ["-preset", "medium"]));
+    }
+
+    #[test]
+    fn test_ffmpeg_args_alpha() {
+        let options = VideoOptions {
+            codec: Codec::Vp9,
+            alpha: true,
+            ..Default::default()
+        };
+        let args = ffmpeg_args(64, 32, &options, "out.webm");
+        assert!(args.windows(2).any(|w| w == ["-pix_fmt", "rgba"]));
+        assert!(args.windows(2).any(|w| w == ["-pix_fmt", "yuva420p"]));
+
+        let options = VideoOptions {
+            alpha: true,
+            ..Default::default()
+        };
+        let err = make_video(8, 8, 1, "unused.webm", &options, |_| RgbaImage::new(8, 8));
+        assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_image_sequence() {
+        let dir = std::env::temp_dir().join("small_world_video_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let options = VideoOptions {
+            codec: Codec::ImageSequence(FrameFormat::Png),
+            alpha: true,
+            ..Default::default()
+        };
+
+        make_video(4, 2, 3, dir.to_str().unwrap(), &options, |i| {
+            RgbaImage::from_pixel(4, 2, Rgba([i as u8, 0, 0, 128]))
+        })
+        .unwrap();
+
+        let last = image::open(frame_path(&dir, 2, FrameFormat::Png)).unwrap();
+        assert_eq!(last.to_rgba8().get_pixel(0, 0), &Rgba([2, 0, 0, 128]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_image_sequence_resumes() {
+        let dir = std::env::temp_dir().join("small_world_parallel_video_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let format = FrameFormat::Png;
+        let mut options = VideoOptions {
+            codec: Codec::ImageSequence(format),
+            ..Default::default()
+        };
+        let render = |i: u32| RgbImage::from_pixel(4, 2, Rgb([i as u8, 0, 0]));
+
+        // first run "interrupted" after 3 frames
+        make_video(4, 2, 3, dir.to_str().unwrap(), &options, render).unwrap();
+        assert_eq!(first_missing_frame(&dir, format, 10), 3);
+
+        options.start_frame = 3;
+        let mut written = vec![];
+        make_video_parallel(4, 2, 10, dir.to_str().unwrap(), &options, 4, render, |p| {
+            written.push(p.frame_idx);
+            assert_eq!(p.total, 7);
+        })
+        .unwrap();
+
+        assert_eq!(written, (3..10).collect::<Vec<_>>());
+        assert_eq!(first_missing_frame(&dir, format, 10), 10);
+        let last = image::open(frame_path(&dir, 9, format)).unwrap();
+        assert_eq!(last.to_rgb8().get_pixel(0, 0), &Rgb([9, 0, 0]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_lookahead() {
+        let dir = std::env::temp_dir().join("small_world_lookahead_video_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let options = VideoOptions {
+            codec: Codec::ImageSequence(FrameFormat::Png),
+            ..Default::default()
+        };
+        let written = AtomicU32::new(0);
+        let render = |i: u32| {
+            // frame i can't start before frame i - 3 is written
+            assert!(
+                i < written.load(Ordering::SeqCst) + 3,
+                "frame {i} started early"
+            );
+            std::thread::sleep(Duration::from_millis((i % 4) as u64 * 5));
+            RgbImage::from_pixel(4, 2, Rgb([i as u8, 0, 0]))
+        };
+        let mut order = vec![];
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            make_video_parallel(4, 2, 20, dir.to_str().unwrap(), &options, 3, render, |p| {
+                order.push(p.frame_idx);
+                written.store(p.done, Ordering::SeqCst);
+            })
+        })
+        .unwrap();
+
+        assert_eq!(order, (0..20).collect::<Vec<_>>());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "frame 5")]
+    fn test_parallel_render_panics() {
+        let dir = std::env::temp_dir().join("small_world_panic_video_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let options = VideoOptions {
+            codec: Codec::ImageSequence(FrameFormat::Png),
+            ..Default::default()
+        };
+        let render = |i: u32| {
+            assert_ne!(i, 5, "frame 5");
+            std::thread::sleep(Duration::from_millis(5));
+            RgbImage::new(4, 2)
+        };
+        // the workers waiting on frame 5 to be written must give up too
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let _ = pool.install(|| {
+            make_video_parallel(4, 2, 20, dir.to_str().unwrap(), &options, 3, render, |_| {})
+        });
+    }
+
+    #[test]
+    fn test_resume_needs_image_sequence() {
+        let options = VideoOptions {
+            start_frame: 5,
+            ..Default::default()
+        };
+        let err = make_video_parallel(
+            8,
+            8,
+            10,
+            "unused.webm",
+            &options,
+            4,
+            |_| RgbImage::new(8, 8),
+            print_progress,
+        );
+        assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
 }
@@ -13,7 +13,10 @@ import { fileURLToPath } from 'node:url';
 import { getConfig } from './config.js';
 
 const __dirname = path.dirname(fileURLToPath(import.meta.url));
-const modelPath = path.join(__dirname, '..', 'model');
+const rootPath = path.join(__dirname, '..');
+const modelPath = path.join(rootPath, 'model');
+const cliPath = path.join(rootPath, 'cli');
+const mathPath = path.join(rootPath, 'math');
 const viewerLibPath = path.join(__dirname, '..', 'viewer-lib');
 const publicPath = path.join(__dirname, '..', 'public');
 
@@ -21,9 +24,10 @@ const publicPath = path.join(__dirname, '..', 'public');
 const rebuildingRust = {};
 
 const rebuildModel = rustRebuilder(
-	modelPath,
-	'cargo run --release --bin nc_to_image',
-	touchMain);
+	cliPath,
+	'cargo run --release -p small-world -- gradient',
+	touchMain,
+	rootPath);
 
 const rebuildViewerLib = rustRebuilder(
 	viewerLibPath,
@@ -44,12 +48,11 @@ const config = {
 };
 
 if (shouldServe) {
-	chokidar.watch(modelPath, {
-		ignored: [path.join(modelPath, 'target')],
-	}).on('change', rebuildModel);
+	chokidar.watch([modelPath, cliPath, mathPath])
+		.on('change', rebuildModel);
 
-	chokidar.watch(viewerLibPath, {
-		ignored: [path.join(viewerLibPath, 'target')],
+	chokidar.watch([viewerLibPath, mathPath], {
+		ignored: [path.join(viewerLibPath, 'pkg')],
 	}).on('change', rebuildViewerLib);
 
 	await rebuildModel();
@@ -108,9 +111,10 @@ function getCopyPlugin() {
  * @param {string} projectPath
  * @param {string} cmdString
  * @param {async () => Promise<void>} andThen
+ * @param {string} [cwd] where to run `cmdString`, `projectPath` by default
  * @returns {() => Promise<void>}
  */
-function rustRebuilder(projectPath, cmdString, andThen) {
+function rustRebuilder(projectPath, cmdString, andThen, cwd = projectPath) {
 	const name = path.basename(projectPath);
 	const [cmd, ...args] = cmdString.split(' ');
 	return () => {
@@ -122,7 +126,7 @@ function rustRebuilder(projectPath, cmdString, andThen) {
 		const build = spawn(cmd, args, {
 			env: { ...process.env, RUST_LOG: 'warn' },
 			stdio: 'inherit',
-			cwd: projectPath,
+			cwd,
 		});
 
 		let resolve = null;
@@ -1,4 +1,4 @@
-import init, { Globe } from '../viewer-lib/pkg/small_world_viewer';
+import init, { CameraMode, CameraView, Globe, Lighting, RenderMode, ScalarField, ScalarImage, SphereMesh } from '../viewer-lib/pkg/small_world_viewer';
 import { buildDate } from './build-date';
 
 const ZOOM_SPEED = 0.02;
@@ -8,6 +8,14 @@ const MIN_DIST = 1.2;
 const TWIST_BASE = 0.001; // radians per render frame
 const TWIST_ACCEL = 0.001;
 const TWIST_MAX = 0.06;
+const MAX_TILE_FETCHES = 6; // in flight at once
+const RADIUS_ANIMATION_MS = 250;
+const SCALE_BAR_MAX_PX = 120;
+const FLIGHT_MS = 1500;
+const RESET_NORTH_MS = 600;
+const AUTO_ROTATE_DEG_PER_S = 6;
+const VIEW_HASH_INTERVAL_MS = 500;
+const CAPTURE_WIDTH = 7680;
 
 type ControlKey = 'q' | 'w' | 'e' | 'a' | 's' | 'd';
 const holdingKey: Record<ControlKey, number> = { q: 0, w: 0, e: 0, a: 0, s: 0, d: 0 };
@@ -22,9 +30,13 @@ async function bootup() {
 	canvas.width = canvas.clientWidth * window.devicePixelRatio;
 	canvas.height = canvas.clientHeight * window.devicePixelRatio;
 
-	const globe = new Globe(canvas);
+	const globe = new Globe(canvas, SphereMesh.Icosphere);
 	setupControls(canvas, globe);
 	enableTouchGestures(canvas, globe);
+	setupTooltip(canvas, globe);
+	setupLighting(globe);
+	setupViewHash(globe);
+	setupCapture(canvas, globe);
 
 	window.addEventListener('resize', () => resizeCanvasToDisplaySize(canvas));
 	resizeCanvasToDisplaySize(canvas);
@@ -37,8 +49,158 @@ async function bootup() {
 		renderOnAnimationFrame(globe, () => globe.set_image_video(video));
 
 	} else {
-		globe.set_image(await loadImage('./age.2020.1.GTS2012.webp'));
-		renderOnAnimationFrame(globe);
+		const fetchTiles = await loadTileManifest('./tiles/manifest.json')
+			.then(manifest => {
+				globe.set_tile_source(manifest.tile_size, manifest.max_level);
+				return () => fetchNeededTiles(globe, './tiles', manifest.format);
+			})
+			.catch(e => {
+				// the single texture exported alongside the tiles
+				console.warn('Tiles unavailable, using a single texture:', e);
+				loadImage('./age.2020.1.GTS2012.webp')
+					.then(img => globe.set_image(img))
+					.catch(e => console.warn('Map texture unavailable:', e));
+				return undefined;
+			});
+		renderOnAnimationFrame(globe, fetchTiles);
+		loadScalars(globe, './scalars').catch(e => console.warn('Scalar fields unavailable:', e));
+		loadVectorLayer(globe, './coastlines.geojson', [1, 1, 1, 0.8], 1.5)
+			.then(id => toggleLayerOnKey(globe, id, 'c'))
+			.catch(e => console.warn('Coastlines unavailable:', e));
+		loadVectorLayer(globe, './streamlines.geojson', [1, 1, 0.6, 0.9], 2)
+			.then(id => {
+				// dashes flow from the ridges towards older crust
+				globe.set_layer_dashes(id, 1.5, 1, 2);
+				toggleLayerOnKey(globe, id, 'f');
+			})
+			.catch(e => console.warn('Streamlines unavailable:', e));
+	}
+}
+
+interface ScalarMetadata {
+	width: number
+	height: number
+	images: { file: string, fields: { name: string, unit: string, channels: string, min: number, max: number }[] }[]
+}
+
+const SCALAR_IMAGES: Record<string, ScalarImage> = {
+	'age_magnitude': ScalarImage.AgeMagnitude,
+	'gradient': ScalarImage.Gradient,
+	'partition': ScalarImage.Partition,
+};
+
+const SCALAR_FIELDS: Record<string, ScalarField> = {
+	'age': ScalarField.Age,
+	'magnitude': ScalarField.Magnitude,
+	'east': ScalarField.East,
+	'north': ScalarField.North,
+	'partition': ScalarField.Partition,
+};
+
+/** Packed 16-bit fields, colorized on the GPU. Keys 0–4 switch what is shown. */
+async function loadScalars(globe: Globe, baseUrl: string) {
+	const resp = await fetch(`${baseUrl}/metadata.json`);
+	if (!resp.ok) throw new Error('Failed to fetch scalar metadata');
+	const metadata: ScalarMetadata = await resp.json();
+
+	let ageRange = [0, 1];
+	let magnitudeRange = [0, 1];
+	for (const image of metadata.images) {
+		for (const field of image.fields) {
+			globe.set_field_range(SCALAR_FIELDS[field.name], field.min, field.max);
+			if (field.name === 'age') ageRange = [field.min, field.max];
+			if (field.name === 'magnitude') magnitudeRange = [field.min, field.max];
+		}
+	}
+	globe.set_colormap(await loadImage(`${baseUrl}/colormaps/age_rainbow.png`));
+	await Promise.all(metadata.images.map(async image => {
+		const bitmap = await loadImage(`${baseUrl}/${image.file}`, { premultiplyAlpha: 'none', colorSpaceConversion: 'none' });
+		globe.set_scalar_image(SCALAR_IMAGES[image.file.replace(/\.\w+$/, '')], bitmap);
+	}));
+
+	window.addEventListener('keydown', e => {
+		if (e.target !== document.body || !/^[0-4]$/.test(e.key)) {
+			return;
+		}
+		const mode = Number(e.key) as RenderMode;
+		const range = mode === RenderMode.Magnitude ? magnitudeRange : ageRange;
+		globe.set_value_range(range[0], range[1]);
+		globe.set_render_mode(mode);
+	});
+
+	setupAgeSlider(globe, ageRange[1]);
+}
+
+/**
+ * Hide crust younger than the slider's age; partitions follow on release.
+ * With "Shrink" checked, the globe also takes the radius of the crust left.
+ */
+function setupAgeSlider(globe: Globe, maxAge: number) {
+	const slider = document.getElementById('age-threshold') as HTMLInputElement | null;
+	const label = document.getElementById('age-threshold-value');
+	const shrink = document.getElementById('shrink') as HTMLInputElement | null;
+	const reference = document.getElementById('reference') as HTMLInputElement | null;
+	if (!slider) return;
+
+	const updateRadius = () => {
+		const radius = shrink?.checked ? globe.surviving_radius(Number(slider.value)) : 1;
+		globe.animate_radius(radius, RADIUS_ANIMATION_MS);
+	};
+	shrink?.addEventListener('change', updateRadius);
+	reference?.addEventListener('change', () => globe.set_reference_visible(reference.checked));
+
+	slider.max = String(Math.ceil(maxAge));
+	document.getElementById('age-control')?.removeAttribute('hidden');
+	slider.addEventListener('input', () => {
+		globe.set_age_threshold(Number(slider.value));
+		updateRadius();
+		if (label) label.textContent = `${slider.value} Myr`;
+	});
+	slider.addEventListener('change', () => {
+		const count = globe.update_partitions();
+		if (label) label.textContent = `${slider.value} Myr, ${count} partitions`;
+	});
+}
+
+/** Lines and points of a GeoJSON file, drawn over the globe. */
+async function loadVectorLayer(globe: Globe, url: string, color: number[], width: number): Promise<number> {
+	const resp = await fetch(url);
+	if (!resp.ok) throw new Error(`Failed to fetch ${url}`);
+	return globe.add_layer(await resp.text(), new Float32Array(color), width);
+}
+
+function toggleLayerOnKey(globe: Globe, id: number, key: string) {
+	let visible = true;
+	window.addEventListener('keydown', e => {
+		if (e.target !== document.body || e.key !== key) return;
+		visible = !visible;
+		globe.set_layer_visible(id, visible);
+	});
+}
+
+interface TileManifest {
+	tile_size: number
+	max_level: number
+	format: string
+}
+
+async function loadTileManifest(url: string): Promise<TileManifest> {
+	const resp = await fetch(url);
+	if (!resp.ok) throw new Error('Failed to fetch tile manifest');
+	return resp.json();
+}
+
+let tileFetches = 0;
+
+function fetchNeededTiles(globe: Globe, baseUrl: string, format: string) {
+	const needed = globe.needed_tiles(MAX_TILE_FETCHES - tileFetches);
+	for (let i = 0; i < needed.length; i += 3) {
+		const [z, x, y] = [needed[i], needed[i + 1], needed[i + 2]];
+		tileFetches++;
+		loadImage(`${baseUrl}/${z}/${x}/${y}.${format}`)
+			.then(image => globe.set_tile(z, x, y, image))
+			.catch(() => globe.tile_failed(z, x, y))
+			.finally(() => { tileFetches--; });
 	}
 }
 
@@ -70,22 +232,155 @@ function renderOnAnimationFrame(globe: Globe, onFrame?: () => void) {
 			holdingKeyAccel('d');
 		}
 		globe.render();
+		updateScaleBar(globe);
 		requestAnimationFrame(frame);
 	}
 	requestAnimationFrame(frame);
 }
 
-async function loadImage(url: string): Promise<ImageBitmap> {
+/** Key l cycles the lighting, with the sun where it is now; key o toggles the atmosphere. */
+function setupLighting(globe: Globe) {
+	const modes = [Lighting.Off, Lighting.Lambert, Lighting.Hemisphere];
+	let mode = 0;
+	let atmosphere = false;
+	globe.set_sun_time(Date.now());
+	setInterval(() => globe.set_sun_time(Date.now()), 60_000);
+	loadImage('./normals.webp', { colorSpaceConversion: 'none' })
+		.then(image => globe.set_normal_map(image))
+		.catch(e => console.warn('Normal map unavailable:', e));
+
+	window.addEventListener('keydown', e => {
+		if (e.target !== document.body) return;
+		if (e.key === 'l') {
+			mode = (mode + 1) % modes.length;
+			globe.set_lighting(modes[mode]);
+		} else if (e.key === 'o') {
+			atmosphere = !atmosphere;
+			globe.set_atmosphere_visible(atmosphere);
+		}
+	});
+}
+
+/**
+ * Keep the camera in the URL hash as `#lat,lon,dist,heading`, so a view can be
+ * bookmarked or shared; the globe flies to a hash edited by hand. Key r
+ * toggles auto-rotation, key m the north-up camera and key n turns north up.
+ */
+function setupViewHash(globe: Globe) {
+	const parse = (hash: string) => {
+		const parts = hash.replace(/^#/, '').split(',').map(Number);
+		return parts.length === 4 && parts.every(Number.isFinite)
+			? new CameraView(parts[0], parts[1], parts[2], parts[3])
+			: null;
+	};
+	const format = (view: CameraView) =>
+		`#${view.lat.toFixed(2)},${view.lon.toFixed(2)},${view.dist.toFixed(3)},${view.heading.toFixed(1)}`;
+
+	const initial = parse(location.hash);
+	if (initial) globe.set_view(initial);
+
+	let written = location.hash;
+	setInterval(() => {
+		const hash = format(globe.get_view());
+		if (hash !== written) {
+			history.replaceState(null, '', hash);
+			written = hash;
+		}
+	}, VIEW_HASH_INTERVAL_MS);
+	window.addEventListener('hashchange', () => {
+		const view = parse(location.hash);
+		if (view) globe.fly_to(view.lat, view.lon, view.dist, FLIGHT_MS);
+	});
+
+	let rotating = false;
+	let northUp = false;
+	window.addEventListener('keydown', e => {
+		if (e.target !== document.body) return;
+		if (e.key === 'r') {
+			rotating = !rotating;
+			globe.set_auto_rotate(rotating ? AUTO_ROTATE_DEG_PER_S : 0);
+		} else if (e.key === 'm') {
+			northUp = !northUp;
+			globe.set_camera_mode(northUp ? CameraMode.NorthUp : CameraMode.Free, RESET_NORTH_MS);
+		} else if (e.key === 'n') {
+			globe.reset_north(RESET_NORTH_MS);
+		}
+	});
+}
+
+/** Key p saves the view as a PNG `CAPTURE_WIDTH` pixels wide, with the canvas's aspect. */
+function setupCapture(canvas: HTMLCanvasElement, globe: Globe) {
+	window.addEventListener('keydown', e => {
+		if (e.target !== document.body || e.key !== 'p') return;
+		const height = Math.round(CAPTURE_WIDTH * canvas.height / canvas.width);
+		try {
+			const png = globe.capture(CAPTURE_WIDTH, height);
+			const link = document.createElement('a');
+			link.href = URL.createObjectURL(new Blob([png], { type: 'image/png' }));
+			link.download = `small-world-${new Date().toISOString().replace(/[:.]/g, '-')}.png`;
+			link.click();
+			URL.revokeObjectURL(link.href);
+		} catch (err) {
+			console.warn('Capture failed:', err);
+		}
+	});
+}
+
+/** Coordinates and values under the mouse, picked at most once a frame. */
+function setupTooltip(canvas: HTMLCanvasElement, globe: Globe) {
+	const tooltip = document.getElementById('tooltip');
+	if (!tooltip) return;
+	let pending: PointerEvent | null = null;
+
+	function update() {
+		const e = pending!;
+		pending = null;
+		const rect = canvas.getBoundingClientRect();
+		const scale = canvas.width / rect.width;
+		const pick = globe.pick((e.clientX - rect.left) * scale, (e.clientY - rect.top) * scale);
+		if (!pick) {
+			tooltip!.hidden = true;
+			return;
+		}
+		const lines = [
+			`${Math.abs(pick.lat).toFixed(2)}° ${pick.lat >= 0 ? 'N' : 'S'}, ${Math.abs(pick.lon).toFixed(2)}° ${pick.lon >= 0 ? 'E' : 'W'}`,
+		];
+		if (pick.age !== undefined) lines.push(`Age: ${pick.age.toFixed(1)} Myr`);
+		if (pick.bearing !== undefined) lines.push(`Older towards: ${pick.bearing.toFixed(0)}°`);
+		pick.free();
+
+		tooltip!.textContent = lines.join('\n');
+		tooltip!.style.left = `${e.clientX + 12}px`;
+		tooltip!.style.top = `${e.clientY + 12}px`;
+		tooltip!.hidden = false;
+	}
+
+	canvas.addEventListener('pointermove', e => {
+		if (e.pointerType !== 'mouse') return;
+		if (!pending) requestAnimationFrame(update);
+		pending = e;
+	});
+	canvas.addEventListener('pointerleave', () => { tooltip.hidden = true; });
+}
+
+/** Longest round length (1, 2 or 5 × 10ⁿ km) that fits the scale bar. */
+function updateScaleBar(globe: Globe) {
+	const bar = document.getElementById('scale-bar');
+	if (!bar) return;
+	const kmPerPx = globe.km_per_pixel() * window.devicePixelRatio;
+	const maxKm = kmPerPx * SCALE_BAR_MAX_PX;
+	const base = Math.pow(10, Math.floor(Math.log10(maxKm)));
+	const km = [5, 2, 1].map(k => k * base).find(k => k <= maxKm) ?? base;
+	bar.style.width = `${km / kmPerPx}px`;
+	bar.textContent = `${km} km`;
+}
+
+async function loadImage(url: string, options?: ImageBitmapOptions): Promise<ImageBitmap> {
 	const resp = await fetch(url);
 	if (!resp.ok) throw new Error('Failed to fetch image');
 
 	const blob = await resp.blob();
-	console.log('blob size:', blob.size, 'type:', blob.type);
-
-	const image = await createImageBitmap(blob);
-	console.log('Image bitmap:', image.width, 'x', image.height);
-
-	return image;
+	return createImageBitmap(blob, options);
 }
 
 async function loadVideo(url: string): Promise<HTMLVideoElement> {
@@ -119,12 +414,11 @@ function setupControls(canvas: HTMLCanvasElement, globe: Globe) {
 
 	let dragging = false;
 	let lastX = 0, lastY = 0;
-	let dist = 2.2;
 
 	canvas.addEventListener('wheel', e => {
 		e.preventDefault();
 		const k = 1.0 - Math.sign(e.deltaY) * ZOOM_SPEED; // zoom step
-		dist = Math.min(MAX_DIST, Math.max(MIN_DIST, dist * k));
+		const dist = Math.min(MAX_DIST, Math.max(MIN_DIST, globe.distance() * k));
 		globe.set_distance(dist);
 	}, { passive: false });
 
@@ -153,6 +447,7 @@ function setupControls(canvas: HTMLCanvasElement, globe: Globe) {
 		dragging = true;
 		lastX = e.clientX;
 		lastY = e.clientY;
+		globe.begin_drag();
 		canvas.setPointerCapture(e.pointerId);
 	});
 
@@ -163,12 +458,13 @@ function setupControls(canvas: HTMLCanvasElement, globe: Globe) {
 		lastX = e.clientX;
 		lastY = e.clientY;
 
-		const rotSpeed = ROT_SPEED * dist / 5;
+		const rotSpeed = ROT_SPEED * globe.distance() / 5;
 		globe.apply_drag(dx, dy, rotSpeed);
 	});
 
 	canvas.addEventListener('pointerup', e => {
 		dragging = false;
+		globe.end_drag();
 		canvas.releasePointerCapture(e.pointerId);
 	});
 }
@@ -188,7 +484,6 @@ function enableTouchGestures(canvas: HTMLCanvasElement, globe: Globe, { minDist
 	let lastX = 0, lastY = 0;
 	let twistLast: number | null = null;
 	let pinchStart = 0, pinchDist = 0;
-	let dist = 2.2; // keep your current dist source if stored elsewhere
 
 	function onDown(e: PointerEvent) {
 		touches.set(e.pointerId, e);
@@ -196,11 +491,12 @@ function enableTouchGestures(canvas: HTMLCanvasElement, globe: Globe, { minDist
 			dragging = true;
 			lastX = e.clientX;
 			lastY = e.clientY;
+			globe.begin_drag();
 		} else if (touches.size === 2) {
 			const [p1, p2] = [...touches.values()];
 			twistLast = vecAngle(p1, p2);
 			pinchStart = pinchLen(p1, p2);
-			pinchDist = dist;
+			pinchDist = globe.distance();
 		}
 		canvas.setPointerCapture(e.pointerId);
 	}
@@ -213,7 +509,7 @@ function enableTouchGestures(canvas: HTMLCanvasElement, globe: Globe, { minDist
 			lastX = e.clientX;
 			lastY = e.clientY;
 
-			const rotSpeed = ROT_SPEED * dist / 5;
+			const rotSpeed = ROT_SPEED * globe.distance() / 5;
 			globe.apply_drag(dx, dy, rotSpeed);
 
 		} else if (touches.size === 2) {
@@ -234,8 +530,7 @@ function enableTouchGestures(canvas: HTMLCanvasElement, globe: Globe, { minDist
 			const len = pinchLen(p1, p2);
 			if (pinchStart) {
 				const factor = pinchStart / len;
-				dist = Math.min(maxDist, Math.max(minDist, pinchDist * factor));
-				globe.set_distance(dist);
+				globe.set_distance(Math.min(maxDist, Math.max(minDist, pinchDist * factor)));
 			}
 		}
 	}
@@ -247,6 +542,9 @@ function enableTouchGestures(canvas: HTMLCanvasElement, globe: Globe, { minDist
 			twistLast = null;
 			pinchStart = 0;
 		}
+		if (touches.size === 0) {
+			globe.end_drag(); // spins on only after a one-finger flick
+		}
 		canvas.releasePointerCapture(e.pointerId);
 	}
 
@@ -1,7 +1,68 @@
 use js_sys::{Float32Array, Uint32Array};
+use small_world_math::{mat4, normalize, quat, sphere};
 use wasm_bindgen::prelude::*;
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext as GL, WebGlProgram, WebGlTexture};
 
+mod lighting;
+mod reference;
+mod scalar;
+mod tiles;
+mod vector;
+
+pub use lighting::Lighting;
+use lighting::{subsolar_point, Lights, SHADE_GLSL};
+use reference::ReferenceSphere;
+use scalar::ScalarLayer;
+pub use scalar::{RenderMode, ScalarField, ScalarImage};
+use tiles::{TileSet, View};
+use vector::VectorLayers;
+
+const FOVY_DEG: f32 = 60.0;
+const ICOSPHERE_LEVEL: u32 = 7; // 327,680 triangles
+const UV_SPHERE_SIZE: (u32, u32) = (512, 1024); // rows, columns
+
+/// Triangles the globe is drawn with. Texture coordinates are computed per
+/// fragment either way, so the poles and the dateline sample correctly.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SphereMesh {
+    /// Rows and columns of latitude and longitude, crowded at the poles.
+    UvSphere = 0,
+    /// A subdivided icosahedron: evenly sized triangles everywhere.
+    Icosphere = 1,
+}
+
+/// GLSL for fragment shaders drawing on the sphere mesh: equirectangular
+/// texture coordinates (v = 0 at the north pole) of a model-space point, and
+/// mipmapped sampling without a seam at the dateline, where u wraps.
+pub(crate) const SPHERE_UV_GLSL: &str = r#"
+        vec2 sphere_uv(vec3 p) {
+            vec3 n = normalize(p);
+            return vec2(
+                fract(-atan(n.z, n.x) / 6.28318530718),
+                acos(clamp(n.y, -1.0, 1.0)) / 3.14159265359);
+        }
+
+        vec4 sample_sphere(sampler2D tex, vec3 p) {
+            vec2 uv = sphere_uv(p);
+            // derivatives of u jump at the dateline; those of u shifted by a
+            // half turn don't there, so use the smaller ones
+            vec2 shifted = vec2(fract(uv.x + 0.5), uv.y);
+            vec2 dx = dFdx(uv), dy = dFdy(uv);
+            vec2 dx2 = dFdx(shifted), dy2 = dFdy(shifted);
+            if (abs(dx2.x) + abs(dy2.x) < abs(dx.x) + abs(dy.x)) {
+                dx.x = dx2.x;
+                dy.x = dy2.x;
+            }
+            return textureGrad(tex, uv, dx, dy);
+        }
+"#;
+const EARTH_RADIUS_KM: f32 = 6371.0; // present-day radius, 1 in world units
+const DRAG_REST_MS: f64 = 80.0; // no inertia if the pointer rested this long
+const MAX_CAPTURE_PIECE: u32 = 4096; // pixels, per side of a capture's framebuffer
+const MAX_NORTH_UP_LAT: f32 = 85.0; // degrees, where north-up drags stop
+const SPIN_DAMPING_MS: f32 = 600.0; // time constant of the drag inertia
+
 #[wasm_bindgen]
 pub struct Globe {
     gl: GL,
@@ -10,14 +71,101 @@ pub struct Globe {
     sphere_ibo: web_sys::WebGlBuffer,
     index_count: i32,
     tex: WebGlTexture,
+    tiles: TileSet,
+    scalars: ScalarLayer,
+    reference: ReferenceSphere,
+    vectors: VectorLayers,
+    lights: Lights,
     dist: f32,        // globe distance
     orient: [f32; 4], // quaternion (x, y, z, w), identity = (0, 0, 0, 1)
+    radius: f32,      // relative to the present day
+    radius_anim: Option<RadiusAnimation>,
+    flight: Option<Flight>,
+    spin: [f32; 2],   // drag inertia: yaw and pitch, radians per millisecond
+    dragging: bool,   // between `begin_drag` and `end_drag`
+    last_drag: f64,   // `Date.now()` of the last drag while dragging
+    auto_rotate: f32, // radians per millisecond about the globe's axis
+    last_frame: f64,  // `Date.now()` of the last render, 0 before the first
+    camera_mode: CameraMode,
+}
+
+/// What is under a point of the canvas, see [`Globe::pick`].
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Pick {
+    /// Degrees, north positive.
+    pub lat: f32,
+    /// Degrees east, in [-180, 180).
+    pub lon: f32,
+    /// Crust age (Myr), if the scalar fields are loaded and there is data.
+    pub age: Option<f32>,
+    /// Age gradient magnitude (Myr/km).
+    pub magnitude: Option<f32>,
+    /// Direction towards older crust, degrees clockwise from north.
+    pub bearing: Option<f32>,
+}
+
+/// How dragging turns the globe, see [`Globe::set_camera_mode`].
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    /// A trackball: any orientation, north possibly down.
+    Free = 0,
+    /// Sideways drags turn the globe about its axis and vertical ones move
+    /// the view towards a pole, stopping short of it; north stays up.
+    NorthUp = 1,
+}
+
+/// Where the camera looks from, to bookmark or share a view: see
+/// [`Globe::get_view`] and [`Globe::set_view`].
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraView {
+    /// Degrees, north positive, of the point at the center of the view.
+    pub lat: f32,
+    /// Degrees east, in [-180, 180).
+    pub lon: f32,
+    /// Distance of the camera from the globe's center, in present-day radii.
+    pub dist: f32,
+    /// Bearing of the top of the screen, degrees clockwise from north.
+    pub heading: f32,
+}
+
+#[wasm_bindgen]
+impl CameraView {
+    #[wasm_bindgen(constructor)]
+    pub fn new(lat: f32, lon: f32, dist: f32, heading: f32) -> CameraView {
+        CameraView {
+            lat,
+            lon,
+            dist,
+            heading,
+        }
+    }
+}
+
+// Eased camera move, timed in milliseconds from `Date.now()`.
+struct Flight {
+    from: [f32; 4],
+    to: [f32; 4],
+    from_dist: f32,
+    to_dist: f32,
+    start: f64,
+    duration: f64,
+}
+
+// Eased change of radius, timed in milliseconds from `Date.now()`.
+struct RadiusAnimation {
+    from: f32,
+    to: f32,
+    start: f64,
+    duration: f64,
 }
 
 #[wasm_bindgen]
 impl Globe {
     #[wasm_bindgen(constructor)]
-    pub fn new(canvas: HtmlCanvasElement) -> Result<Globe, JsValue> {
+    pub fn new(canvas: HtmlCanvasElement, mesh: Option<SphereMesh>) -> Result<Globe, JsValue> {
         let gl: GL = canvas
             .get_context("webgl2")?
             .ok_or("WebGL2 unavailable")?
@@ -28,37 +176,46 @@ impl Globe {
         precision mediump float;
 
         in vec3 a_pos;
-        in vec2 a_uv;
 
         uniform mat4 u_mvp;
 
-        out vec2 v_uv;
+        out vec3 v_pos;
 
         void main() {
-            v_uv = a_uv;
+            v_pos = a_pos;
             gl_Position = u_mvp * vec4(a_pos, 1.0);
         }"#;
 
-        let frag_src = r#"#version 300 es
-        precision mediump float;
-        in vec2 v_uv;
+        let frag_src = [
+            r#"#version 300 es
+        precision highp float; // texture coordinates of a full-size map
+        in vec3 v_pos;
         uniform sampler2D u_tex;
         out vec4 outColor;
+        "#,
+            SPHERE_UV_GLSL,
+            SHADE_GLSL,
+            r#"
         void main() {
-            outColor = texture(u_tex, v_uv);
-        }"#;
+            vec4 color = sample_sphere(u_tex, v_pos);
+            outColor = vec4(shade(color.rgb, v_pos, sphere_uv(v_pos)), color.a);
+        }"#,
+        ]
+        .concat();
 
-        let program = link_program(&gl, vert_src, frag_src)?;
+        let program = link_program(&gl, vert_src, &frag_src)?;
 
         // --- sphere geometry ---
-        let (verts, uvs, indices) = make_sphere(512, 1024); // higher-res
-        let interleaved = interleave(&verts, &uvs);
+        let (verts, indices) = match mesh.unwrap_or(SphereMesh::UvSphere) {
+            SphereMesh::UvSphere => make_sphere(UV_SPHERE_SIZE.0, UV_SPHERE_SIZE.1),
+            SphereMesh::Icosphere => make_icosphere(ICOSPHERE_LEVEL),
+        };
 
         // vertex buffer
         let sphere_vbo = gl.create_buffer().unwrap();
         gl.bind_buffer(GL::ARRAY_BUFFER, Some(&sphere_vbo));
         unsafe {
-            let arr = Float32Array::view(&interleaved);
+            let arr = Float32Array::view(&verts);
             gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &arr, GL::STATIC_DRAW);
         }
 
@@ -73,7 +230,7 @@ impl Globe {
         // texture
         let tex = gl.create_texture().unwrap();
         gl.bind_texture(GL::TEXTURE_2D, Some(&tex));
-        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::REPEAT as i32); // across the dateline
         gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
         gl.tex_parameteri(
             GL::TEXTURE_2D,
@@ -82,6 +239,12 @@ impl Globe {
         );
         gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
 
+        let tiles = TileSet::new(&gl)?;
+        let scalars = ScalarLayer::new(&gl)?;
+        let reference = ReferenceSphere::new(&gl)?;
+        let vectors = VectorLayers::new(&gl)?;
+        let lights = Lights::new(&gl)?;
+
         Ok(Self {
             gl,
             program,
@@ -89,28 +252,223 @@ impl Globe {
             sphere_ibo,
             index_count: indices.len() as i32,
             tex,
+            tiles,
+            scalars,
+            reference,
+            vectors,
+            lights,
             dist: 2.2,
             orient: [0.0, 0.0, 0.0, 1.0],
+            radius: 1.0,
+            radius_anim: None,
+            flight: None,
+            spin: [0.0, 0.0],
+            dragging: false,
+            last_drag: 0.0,
+            auto_rotate: 0.0,
+            last_frame: 0.0,
+            camera_mode: CameraMode::Free,
         })
     }
 
     pub fn apply_drag(&mut self, dx: f32, dy: f32, scale: f32) {
-        // Screen/world axes (camera looks down -Z, Y is up, X is right)
-        let yaw_world = quat_from_axis_angle([0.0, 1.0, 0.0], dx * scale); // left/right drag → yaw
-        let pitch_world = quat_from_axis_angle([1.0, 0.0, 0.0], dy * scale); // up/down drag → pitch
+        self.rotate(dx * scale, dy * scale);
+        self.flight = None;
+
+        // velocity for the inertia after `end_drag`, smoothed over the moves
+        if self.dragging {
+            let now = js_sys::Date::now();
+            let dt = (now - self.last_drag).max(1.0) as f32;
+            let velocity = [dx * scale / dt, dy * scale / dt];
+            self.spin = [0, 1].map(|i| 0.5 * self.spin[i] + 0.5 * velocity[i]);
+            self.last_drag = now;
+        }
+    }
 
-        // Pre-multiply so rotations are in world/screen space BEFORE current orientation
-        let dq = quat_mul(yaw_world, pitch_world);
-        self.orient = quat_normalize(quat_mul(dq, self.orient));
+    /// Start a drag with the pointer: the globe stops spinning, and
+    /// [`Globe::apply_drag`] records its velocity until [`Globe::end_drag`].
+    pub fn begin_drag(&mut self) {
+        self.dragging = true;
+        self.last_drag = js_sys::Date::now();
+        self.spin = [0.0, 0.0];
+        self.flight = None;
+    }
+
+    /// Let go of the globe, which keeps spinning and slows down unless the
+    /// pointer had come to rest.
+    pub fn end_drag(&mut self) {
+        self.dragging = false;
+        if js_sys::Date::now() - self.last_drag > DRAG_REST_MS {
+            self.spin = [0.0, 0.0];
+        }
     }
 
     pub fn apply_twist(&mut self, delta: f32) {
-        let rz = quat_from_axis_angle([0.0, 0.0, -1.0], delta); // try -Z; flip sign if it feels backward
-        self.orient = quat_normalize(quat_mul(rz, self.orient)); // pre-multiply = screen/world space
+        if self.camera_mode == CameraMode::NorthUp {
+            return;
+        }
+        let rz = quat::from_axis_angle([0.0, 0.0, -1.0], delta); // try -Z; flip sign if it feels backward
+        self.orient = quat::normalize(quat::mul(rz, self.orient)); // pre-multiply = screen/world space
+        self.flight = None;
     }
 
     pub fn set_distance(&mut self, d: f32) {
         self.dist = d.clamp(1.2, 10.0);
+        self.flight = None;
+    }
+
+    /// Distance of the camera from the globe's center, in present-day radii.
+    pub fn distance(&self) -> f32 {
+        self.dist
+    }
+
+    /// Turn the camera to look down on `(lat, lon)` (degrees) with north up,
+    /// from `dist`, easing along the shortest arc over `duration_ms`.
+    pub fn fly_to(&mut self, lat: f32, lon: f32, dist: f32, duration_ms: f64) {
+        self.spin = [0.0, 0.0];
+        self.flight = Some(Flight {
+            from: self.orient,
+            to: quat::looking_at(lon, lat),
+            from_dist: self.dist,
+            to_dist: dist.clamp(1.2, 10.0),
+            start: js_sys::Date::now(),
+            duration: duration_ms.max(1.0),
+        });
+    }
+
+    /// Spin the globe about its axis, west to east, at `deg_per_s`; 0 stops
+    /// it. Paused while dragging or flying.
+    pub fn set_auto_rotate(&mut self, deg_per_s: f32) {
+        self.auto_rotate = deg_per_s.to_radians() / 1000.0;
+    }
+
+    /// How drags turn the globe. Switching to [`CameraMode::NorthUp`] turns
+    /// north up over `duration_ms`.
+    pub fn set_camera_mode(&mut self, mode: CameraMode, duration_ms: f64) {
+        self.camera_mode = mode;
+        if mode == CameraMode::NorthUp {
+            self.reset_north(duration_ms);
+        }
+    }
+
+    /// Turn north up over `duration_ms`, looking at the same point from the
+    /// same distance.
+    pub fn reset_north(&mut self, duration_ms: f64) {
+        let view = self.get_view();
+        let lat = view.lat.clamp(-MAX_NORTH_UP_LAT, MAX_NORTH_UP_LAT);
+        self.fly_to(lat, view.lon, view.dist, duration_ms);
+    }
+
+    /// The current view, see [`CameraView`].
+    pub fn get_view(&self) -> CameraView {
+        let (lon, lat) = sphere::to_lon_lat(self.view().cam_dir);
+        // what is left after facing (lat, lon) north up is a turn about the
+        // view axis
+        let base = quat::looking_at(lon, lat);
+        let twist = quat::mul(self.orient, quat::conjugate(base));
+        let heading = (2.0 * twist[2].atan2(twist[3])).to_degrees();
+        CameraView {
+            lat,
+            lon,
+            dist: self.dist,
+            heading: (heading + 180.0).rem_euclid(360.0) - 180.0,
+        }
+    }
+
+    /// Jump to a view from [`Globe::get_view`], stopping any motion. The
+    /// heading is ignored in [`CameraMode::NorthUp`].
+    pub fn set_view(&mut self, view: &CameraView) {
+        let heading = match self.camera_mode {
+            CameraMode::Free => view.heading,
+            CameraMode::NorthUp => 0.0,
+        };
+        let twist = quat::from_axis_angle([0.0, 0.0, 1.0], heading.to_radians());
+        self.orient = quat::normalize(quat::mul(twist, quat::looking_at(view.lon, view.lat)));
+        self.dist = view.dist.clamp(1.2, 10.0);
+        self.flight = None;
+        self.spin = [0.0, 0.0];
+    }
+
+    /// Radius of the globe as a fraction of the present day's, e.g. for the
+    /// surface left at some age. Cancels any running animation.
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.clamp(0.05, 1.0);
+        self.radius_anim = None;
+    }
+
+    /// Ease from the current radius to `radius` over `duration_ms`.
+    pub fn animate_radius(&mut self, radius: f32, duration_ms: f64) {
+        self.radius_anim = Some(RadiusAnimation {
+            from: self.radius,
+            to: radius.clamp(0.05, 1.0),
+            start: js_sys::Date::now(),
+            duration: duration_ms.max(1.0),
+        });
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Radius of a globe holding only the crust at least `age` (Myr) old and
+    /// the areas without ages (continents): the square root of their share of
+    /// today's area. 1 before the ages are loaded.
+    pub fn surviving_radius(&self, age: f32) -> f32 {
+        self.scalars
+            .surviving_fraction(age)
+            .map_or(1.0, |fraction| fraction.sqrt())
+    }
+
+    /// Show a translucent sphere at the present-day radius.
+    pub fn set_reference_visible(&mut self, visible: bool) {
+        self.reference.visible = visible;
+    }
+
+    /// Kilometers covered by a pixel at the point of the globe nearest the
+    /// camera, for a scale bar.
+    pub fn km_per_pixel(&self) -> f32 {
+        let h = self.gl.drawing_buffer_height().max(1) as f32;
+        let pixel = 2.0 * (self.dist - self.radius) * (FOVY_DEG.to_radians() / 2.0).tan() / h;
+        pixel * EARTH_RADIUS_KM
+    }
+
+    /// The point of the globe under `(x, y)`, in drawing buffer pixels from
+    /// the top left of the canvas (CSS pixels × `devicePixelRatio`), and the
+    /// scalar values there. `None` off the globe.
+    pub fn pick(&self, x: f32, y: f32) -> Option<Pick> {
+        let w = self.gl.drawing_buffer_width().max(1) as f32;
+        let h = self.gl.drawing_buffer_height().max(1) as f32;
+        let f = (FOVY_DEG.to_radians() / 2.0).tan();
+
+        // ray from the camera (at the origin, looking down -Z) through the pixel
+        let ndc = [2.0 * x / w - 1.0, 1.0 - 2.0 * y / h];
+        let d = normalize([ndc[0] * f * w / h, ndc[1] * f, -1.0]);
+        // nearest intersection with the globe centered at (0, 0, -dist)
+        let b = -self.dist * d[2]; // dot(d, center)
+        let disc = b * b - (self.dist * self.dist - self.radius * self.radius);
+        if disc < 0.0 {
+            return None;
+        }
+        let t = b - disc.sqrt();
+        let hit = [d[0] * t, d[1] * t, d[2] * t + self.dist];
+
+        // back to the model space of the unit sphere
+        let inv = quat::conjugate(self.orient);
+        let p = quat::rotate(inv, hit);
+        let uv = sphere::to_uv(p);
+        let (lon, lat) = sphere::to_lon_lat(p);
+
+        let values = self.scalars.values_at(&self.gl, uv);
+        let bearing = values.east.zip(values.north).and_then(|(east, north)| {
+            (east != 0.0 || north != 0.0).then(|| east.atan2(north).to_degrees().rem_euclid(360.0))
+        });
+        Some(Pick {
+            lat,
+            lon,
+            age: values.age,
+            magnitude: values.magnitude,
+            bearing,
+        })
     }
 
     // Upload texture from JS
@@ -146,46 +504,443 @@ impl Globe {
         gl.generate_mipmap(GL::TEXTURE_2D);
     }
 
+    /// Draw tiles from an equirectangular quadtree, as exported by the model
+    /// crate (`tiles/manifest.json`), instead of an image from `set_image`:
+    /// level 0 covers the whole globe, and finer levels draw over it.
+    pub fn set_tile_source(&mut self, tile_size: u32, max_level: u32) {
+        self.tiles.set_source(&self.gl, tile_size, max_level);
+    }
+
+    /// Up to `max` tiles to fetch for the current view, as flat (z, x, y)
+    /// triples. Answer each with `set_tile` or `tile_failed`.
+    pub fn needed_tiles(&mut self, max: u32) -> Vec<u32> {
+        let view = self.view();
+        self.tiles
+            .needed(&view, max as usize)
+            .into_iter()
+            .flat_map(|(z, x, y)| [z, x, y])
+            .collect()
+    }
+
+    pub fn set_tile(&mut self, z: u32, x: u32, y: u32, img: &web_sys::ImageBitmap) {
+        self.tiles.insert(&self.gl, (z, x, y), img);
+    }
+
+    pub fn tile_failed(&mut self, z: u32, x: u32, y: u32) {
+        self.tiles.failed((z, x, y));
+    }
+
+    /// Upload one of the packed scalar images exported by the model crate
+    /// (`scalars/metadata.json`). Decode it with `createImageBitmap(blob,
+    /// { premultiplyAlpha: 'none', colorSpaceConversion: 'none' })`.
+    pub fn set_scalar_image(&mut self, which: ScalarImage, img: &web_sys::ImageBitmap) {
+        self.scalars.set_image(&self.gl, which, img);
+    }
+
+    /// Range the field was quantized over, from the metadata.
+    pub fn set_field_range(&mut self, field: ScalarField, min: f32, max: f32) {
+        self.scalars.set_decode_range(field, min, max);
+    }
+
+    /// Colormap strip for the age and magnitude modes, low values on the left.
+    pub fn set_colormap(&self, img: &web_sys::ImageBitmap) {
+        self.scalars.set_colormap(&self.gl, img);
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.scalars.mode = mode;
+    }
+
+    /// Values mapped to the ends of the colormap.
+    pub fn set_value_range(&mut self, min: f32, max: f32) {
+        self.scalars.value_range = [min, max];
+    }
+
+    /// Only color values within [min, max]; the image shows through elsewhere.
+    pub fn set_thresholds(&mut self, min: f32, max: f32) {
+        self.scalars.thresholds = [min, max];
+    }
+
+    /// Opacity of the colorized fields over the image.
+    pub fn set_scalar_opacity(&mut self, opacity: f32) {
+        self.scalars.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    /// Hide crust younger than `age` (Myr), in every render mode. Cheap enough
+    /// to call on every slider move; 0 shows everything again.
+    pub fn set_age_threshold(&mut self, age: f32) {
+        self.scalars.age_threshold = age.max(0.0);
+    }
+
+    /// Recompute the partitions of the crust left by the age threshold, for
+    /// [`RenderMode::Partition`]. Slower: call it once the slider is released.
+    /// Returns the number of partitions (0 before the ages are loaded).
+    pub fn update_partitions(&mut self) -> u32 {
+        self.scalars
+            .update_partitions(&self.gl)
+            .map_or(0, |count| count as u32)
+    }
+
+    pub fn set_lighting(&mut self, mode: Lighting) {
+        self.lights.mode = mode;
+    }
+
+    /// Light the globe from straight above `(lon, lat)`, in degrees.
+    pub fn set_light_direction(&mut self, lon: f32, lat: f32) {
+        self.lights.set_direction(lon, lat);
+    }
+
+    /// Light the globe like the sun at `unix_ms` (e.g. `Date.now()`), for a
+    /// day and night terminator.
+    pub fn set_sun_time(&mut self, unix_ms: f64) {
+        let (lon, lat) = subsolar_point(unix_ms);
+        self.lights.set_direction(lon, lat);
+    }
+
+    /// Light on the unlit side, in [0, 1].
+    pub fn set_ambient(&mut self, ambient: f32) {
+        self.lights.ambient = ambient.clamp(0.0, 1.0);
+    }
+
+    /// Normal map for relief under the light, as exported by the model crate
+    /// (`normals.webp`, north-up). Decode it with `colorSpaceConversion:
+    /// 'none'`.
+    pub fn set_normal_map(&mut self, img: &web_sys::ImageBitmap) {
+        self.lights.set_normal_map(&self.gl, img);
+    }
+
+    /// Exaggeration of the normal map's relief; 0 for a smooth sphere.
+    pub fn set_normal_strength(&mut self, strength: f32) {
+        self.lights.normal_strength = strength.max(0.0);
+    }
+
+    /// Show a thin glowing atmosphere around the globe.
+    pub fn set_atmosphere_visible(&mut self, visible: bool) {
+        self.lights.atmosphere_visible = visible;
+    }
+
+    /// Draw the lines and points of a GeoJSON document (ridges, isochrons,
+    /// coastlines, markers...) over the globe, in `color` (RGBA in [0, 1]) with
+    /// lines `width` pixels wide. Returns an id for the other layer methods.
+    pub fn add_layer(&mut self, geojson: &str, color: &[f32], width: f32) -> Result<u32, JsValue> {
+        let color = rgba(color)?;
+        Ok(self.vectors.add(&self.gl, geojson, color, width)?)
+    }
+
+    /// Remove a layer added with [`Globe::add_layer`]; false if there is none.
+    pub fn remove_layer(&mut self, id: u32) -> bool {
+        self.vectors.remove(&self.gl, id)
+    }
+
+    pub fn set_layer_visible(&mut self, id: u32, visible: bool) {
+        self.vectors.set_visible(id, visible);
+    }
+
+    /// Color, line width and point diameter (pixels) of a layer.
+    pub fn set_layer_style(
+        &mut self,
+        id: u32,
+        color: &[f32],
+        width: f32,
+        point_size: f32,
+    ) -> Result<(), JsValue> {
+        self.vectors.set_style(id, rgba(color)?, width, point_size);
+        Ok(())
+    }
+
+    /// Draw a layer's lines dashed, `dash` and `gap` in degrees of arc, the
+    /// dashes moving from the start of each line towards its end at `speed`
+    /// degrees per second (0 for static dashes). A `dash` of 0 draws them
+    /// solid again.
+    pub fn set_layer_dashes(&mut self, id: u32, dash: f32, gap: f32, speed: f32) {
+        self.vectors.set_dashes(id, dash, gap, speed);
+    }
+
     pub fn render(&mut self) {
+        self.advance_radius();
+        self.advance_camera();
+        let w = self.gl.drawing_buffer_width();
+        let h = self.gl.drawing_buffer_height();
+        let proj = mat4::perspective(FOVY_DEG.to_radians(), w as f32 / h as f32, 0.01, 100.0);
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        self.draw(w, h, &proj, 1.0);
+    }
+
+    /// Render the current view, overlays included, at `width` × `height`
+    /// pixels and return it as PNG bytes. Tiles are those loaded for the
+    /// canvas; lines and points are scaled with the image's height so it
+    /// looks like the canvas, only sharper. Large images are rendered in
+    /// pieces no bigger than the GPU allows.
+    pub fn capture(&mut self, width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+        if width == 0 || height == 0 {
+            return Err(JsValue::from_str("capture size must be positive"));
+        }
+        // allocated first, so a size beyond memory is an error, not a trap
+        let len = image_len(width, height).ok_or("capture too large")?;
+        let mut image = Vec::new();
+        image
+            .try_reserve_exact(len)
+            .map_err(|_| "capture too large")?;
+        image.resize(len, 0u8);
+        let gl = &self.gl;
+        let limit = |name| {
+            gl.get_parameter(name)
+                .ok()
+                .and_then(|v| v.as_f64())
+                .unwrap_or(2048.0) as u32
+        };
+        let piece = limit(GL::MAX_RENDERBUFFER_SIZE)
+            .min(limit(GL::MAX_TEXTURE_SIZE))
+            .min(MAX_CAPTURE_PIECE);
+        let (piece_w, piece_h) = (width.min(piece), height.min(piece));
+
+        let fb = gl
+            .create_framebuffer()
+            .ok_or("cannot create a framebuffer")?;
+        let color = gl
+            .create_renderbuffer()
+            .ok_or("cannot create a renderbuffer")?;
+        let depth = gl
+            .create_renderbuffer()
+            .ok_or("cannot create a renderbuffer")?;
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&fb));
+        for (buffer, format, attachment) in [
+            (&color, GL::RGBA8, GL::COLOR_ATTACHMENT0),
+            (&depth, GL::DEPTH_COMPONENT24, GL::DEPTH_ATTACHMENT),
+        ] {
+            gl.bind_renderbuffer(GL::RENDERBUFFER, Some(buffer));
+            gl.renderbuffer_storage(GL::RENDERBUFFER, format, piece_w as i32, piece_h as i32);
+            gl.framebuffer_renderbuffer(
+                GL::FRAMEBUFFER,
+                attachment,
+                GL::RENDERBUFFER,
+                Some(buffer),
+            );
+        }
+        let complete = gl.check_framebuffer_status(GL::FRAMEBUFFER) == GL::FRAMEBUFFER_COMPLETE;
+
+        if complete {
+            let proj = mat4::perspective(
+                FOVY_DEG.to_radians(),
+                width as f32 / height as f32,
+                0.01,
+                100.0,
+            );
+            let pixel_scale = height as f32 / self.gl.drawing_buffer_height().max(1) as f32;
+            let mut pixels = vec![0u8; 4 * piece_w as usize * piece_h as usize];
+            // pieces from the bottom left, as GL counts pixels
+            for y0 in (0..height).step_by(piece_h as usize) {
+                for x0 in (0..width).step_by(piece_w as usize) {
+                    let (w, h) = ((width - x0).min(piece_w), (height - y0).min(piece_h));
+                    let ndc = |p: u32, size: u32| 2.0 * p as f32 / size as f32 - 1.0;
+                    let window = mat4::crop(
+                        [ndc(x0, width), ndc(x0 + w, width)],
+                        [ndc(y0, height), ndc(y0 + h, height)],
+                    );
+                    self.draw(w as i32, h as i32, &mat4::mul(&window, &proj), pixel_scale);
+                    let (w4, stride) = (4 * w as usize, 4 * width as usize);
+                    let len = w4 * h as usize;
+                    self.gl.read_pixels_with_opt_u8_array(
+                        0,
+                        0,
+                        w as i32,
+                        h as i32,
+                        GL::RGBA,
+                        GL::UNSIGNED_BYTE,
+                        Some(&mut pixels[..len]),
+                    )?;
+                    for row in 0..h {
+                        let src = row as usize * w4;
+                        let y = (height - 1 - (y0 + row)) as usize;
+                        let dst = y * stride + 4 * x0 as usize;
+                        image[dst..dst + w4].copy_from_slice(&pixels[src..src + w4]);
+                    }
+                }
+            }
+        }
+
+        let gl = &self.gl;
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        gl.bind_renderbuffer(GL::RENDERBUFFER, None);
+        gl.delete_framebuffer(Some(&fb));
+        gl.delete_renderbuffer(Some(&color));
+        gl.delete_renderbuffer(Some(&depth));
+        if !complete {
+            return Err(JsValue::from_str("capture framebuffer incomplete"));
+        }
+        encode_png(&image, width, height).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+// Bytes of a `width` × `height` RGBA image, `None` if they don't fit in
+// memory's address space.
+fn image_len(width: u32, height: u32) -> Option<usize> {
+    let len = (width as u64).checked_mul(height as u64)?.checked_mul(4)?;
+    usize::try_from(len)
+        .ok()
+        .filter(|&len| len <= isize::MAX as usize)
+}
+
+impl Globe {
+    // Draw everything into the bound framebuffer, `w` × `h` pixels, with
+    // line widths and point sizes multiplied by `pixel_scale`.
+    fn draw(&mut self, w: i32, h: i32, proj: &[f32; 16], pixel_scale: f32) {
         let gl = &self.gl;
-        let w = gl.drawing_buffer_width();
-        let h = gl.drawing_buffer_height();
         gl.viewport(0, 0, w, h);
         gl.clear_color(0.05, 0.1, 0.2, 1.0);
         gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
         gl.enable(GL::DEPTH_TEST);
-        gl.disable(GL::CULL_FACE); // TODO: remove
+        // every mesh is counter-clockwise from outside, except vector lines
+        gl.enable(GL::CULL_FACE);
+        gl.cull_face(GL::BACK);
         gl.use_program(Some(&self.program));
 
         gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.sphere_vbo));
         gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.sphere_ibo));
 
-        let stride = (5 * std::mem::size_of::<f32>()) as i32;
         let pos_loc = gl.get_attrib_location(&self.program, "a_pos") as u32;
         gl.enable_vertex_attrib_array(pos_loc);
-        gl.vertex_attrib_pointer_with_i32(pos_loc, 3, GL::FLOAT, false, stride, 0);
+        gl.vertex_attrib_pointer_with_i32(pos_loc, 3, GL::FLOAT, false, 0, 0);
 
-        let uv_loc = gl.get_attrib_location(&self.program, "a_uv") as u32;
-        gl.enable_vertex_attrib_array(uv_loc);
-        gl.vertex_attrib_pointer_with_i32(uv_loc, 2, GL::FLOAT, false, stride, (3 * 4) as i32);
-
-        let aspect = w as f32 / h as f32;
-        let proj = perspective(60f32.to_radians(), aspect, 0.01, 100.0);
-
-        let rot = mat4_from_quat(self.orient);
-        let tz = translate_z(-self.dist);
-        let model = mul4x4(&tz, &rot); // model = Tz * R(q)
+        let rot = mat4::from_quat(self.orient);
+        let tz = mat4::translate_z(-self.dist);
+        let unit_model = mat4::mul(&tz, &rot); // Tz * R(q)
+        let model = mat4::mul(&unit_model, &mat4::scale(self.radius)); // model = Tz * R(q) * S(r)
 
         // mvp = proj * model
-        let mvp = mul4x4(&proj, &model);
+        let mvp = mat4::mul(proj, &model);
 
         let loc = gl.get_uniform_location(&self.program, "u_mvp");
         if let Some(loc) = loc {
             gl.uniform_matrix4fv_with_f32_array(Some(&loc), false, &mvp);
         }
+        self.lights.apply(gl, &self.program);
 
         gl.bind_texture(GL::TEXTURE_2D, Some(&self.tex));
         gl.draw_elements_with_i32(GL::TRIANGLES, self.index_count, GL::UNSIGNED_INT, 0);
+
+        let view = self.view();
+        self.tiles.render(&self.gl, &mvp, &view, &self.lights);
+        self.scalars.render(
+            &self.gl,
+            &mvp,
+            &self.sphere_vbo,
+            &self.sphere_ibo,
+            self.index_count,
+            &self.lights,
+        );
+        self.vectors.render(
+            &self.gl,
+            &mvp,
+            [w as f32, h as f32],
+            pixel_scale,
+            js_sys::Date::now() / 1000.0,
+        );
+        // the camera in the model space of the unit sphere
+        let eye = view.cam_dir.map(|c| c * view.dist);
+        self.lights.render_atmosphere(
+            &self.gl,
+            &mvp,
+            eye,
+            &self.sphere_vbo,
+            &self.sphere_ibo,
+            self.index_count,
+        );
+        self.reference.render(
+            &self.gl,
+            proj,
+            &unit_model,
+            &self.sphere_vbo,
+            &self.sphere_ibo,
+            self.index_count,
+        );
+    }
+
+    fn advance_radius(&mut self) {
+        let Some(anim) = &self.radius_anim else {
+            return;
+        };
+        let t = ((js_sys::Date::now() - anim.start) / anim.duration).clamp(0.0, 1.0) as f32;
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.radius = anim.from + (anim.to - anim.from) * eased;
+        if t >= 1.0 {
+            self.radius_anim = None;
+        }
+    }
+
+    // Turn the globe for a drag of `yaw` radians to the right and `pitch`
+    // down, as the camera mode says.
+    fn rotate(&mut self, yaw: f32, pitch: f32) {
+        match self.camera_mode {
+            CameraMode::Free => {
+                // Screen/world axes (camera looks down -Z, Y is up, X is right)
+                let yaw_world = quat::from_axis_angle([0.0, 1.0, 0.0], yaw); // left/right drag → yaw
+                let pitch_world = quat::from_axis_angle([1.0, 0.0, 0.0], pitch); // up/down drag → pitch
+
+                // Pre-multiply so rotations are in world/screen space BEFORE current orientation
+                let dq = quat::mul(yaw_world, pitch_world);
+                self.orient = quat::normalize(quat::mul(dq, self.orient));
+            }
+            CameraMode::NorthUp => {
+                // about the polar axis, and towards a pole short of it
+                let view = self.get_view();
+                let lat =
+                    (view.lat + pitch.to_degrees()).clamp(-MAX_NORTH_UP_LAT, MAX_NORTH_UP_LAT);
+                self.orient = quat::looking_at(view.lon - yaw.to_degrees(), lat);
+            }
+        }
+    }
+
+    fn advance_camera(&mut self) {
+        let now = js_sys::Date::now();
+        let dt = if self.last_frame > 0.0 {
+            (now - self.last_frame).min(100.0) as f32
+        } else {
+            0.0
+        };
+        self.last_frame = now;
+
+        if let Some(flight) = &self.flight {
+            let t = ((now - flight.start) / flight.duration).clamp(0.0, 1.0) as f32;
+            let eased = t * t * (3.0 - 2.0 * t);
+            self.orient = quat::slerp(flight.from, flight.to, eased);
+            self.dist = flight.from_dist + (flight.to_dist - flight.from_dist) * eased;
+            if t >= 1.0 {
+                self.flight = None;
+            }
+            return;
+        }
+        if self.dragging {
+            return;
+        }
+        if self.spin != [0.0, 0.0] {
+            self.rotate(self.spin[0] * dt, self.spin[1] * dt);
+            let damping = (-dt / SPIN_DAMPING_MS).exp();
+            self.spin = self.spin.map(|v| v * damping);
+            if self.spin[0].hypot(self.spin[1]) < 1e-6 {
+                self.spin = [0.0, 0.0];
+            }
+        }
+        if self.auto_rotate != 0.0 {
+            // post-multiply: about the globe's own axis
+            let axis = quat::from_axis_angle([0.0, 1.0, 0.0], self.auto_rotate * dt);
+            self.orient = quat::normalize(quat::mul(self.orient, axis));
+        }
+    }
+
+    fn view(&self) -> View {
+        let w = self.gl.drawing_buffer_width() as f32;
+        let h = self.gl.drawing_buffer_height() as f32;
+        // the camera sits on +Z in world space; undo the globe's rotation
+        let inv = quat::conjugate(self.orient);
+        View {
+            cam_dir: quat::rotate(inv, [0.0, 0.0, 1.0]),
+            // tiles are picked for a unit sphere: the same view, scaled
+            dist: self.dist / self.radius,
+            fovy: FOVY_DEG.to_radians(),
+            aspect: w / h.max(1.0),
+            height: h.max(1.0),
+        }
     }
 }
 
@@ -226,28 +981,30 @@ fn compile_shader(gl: &GL, ty: u32, src: &str) -> Result<web_sys::WebGlShader, J
     Ok(shader)
 }
 
-fn interleave(verts: &[f32], uvs: &[f32]) -> Vec<f32> {
-    let mut data = Vec::with_capacity(verts.len() / 3 * 5);
-    for i in 0..verts.len() / 3 {
-        data.extend_from_slice(&verts[i * 3..i * 3 + 3]);
-        data.extend_from_slice(&uvs[i * 2..i * 2 + 2]);
+fn rgba(color: &[f32]) -> Result<[f32; 4], JsValue> {
+    match *color {
+        [r, g, b] => Ok([r, g, b, 1.0]),
+        [r, g, b, a] => Ok([r, g, b, a]),
+        _ => Err(JsValue::from_str("expected an RGB or RGBA color")),
     }
-    data
 }
 
-fn make_sphere(lat: u32, lon: u32) -> (Vec<f32>, Vec<f32>, Vec<u32>) {
+fn encode_png(rgba: &[u8], width: u32, height: u32) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(out)
+}
+
+fn make_sphere(lat: u32, lon: u32) -> (Vec<f32>, Vec<u32>) {
     let mut verts = vec![];
-    let mut uvs = vec![];
     let mut idx = vec![];
     for y in 0..=lat {
         let v = y as f32 / lat as f32;
-        let theta = v * std::f32::consts::PI;
         for x in 0..=lon {
-            let u = x as f32 / lon as f32;
-            let phi = -u * std::f32::consts::TAU;
-            let sin_t = theta.sin();
-            verts.extend_from_slice(&[phi.cos() * sin_t, theta.cos(), phi.sin() * sin_t]);
-            uvs.extend_from_slice(&[u, v]);
+            verts.extend_from_slice(&sphere::from_uv(x as f32 / lon as f32, v));
         }
     }
     for y in 0..lat {
@@ -258,86 +1015,144 @@ fn make_sphere(lat: u32, lon: u32) -> (Vec<f32>, Vec<f32>, Vec<u32>) {
             idx.extend_from_slice(&[a, b, a + 1, b, b + 1, a + 1]);
         }
     }
-    (verts, uvs, idx)
+    (verts, idx)
 }
 
-#[rustfmt::skip]
-fn mul4x4(a: &[f32;16], b: &[f32;16]) -> [f32;16] {
-    // column-major: out = a * b
-    let mut out = [0.0;16];
-    for col in 0..4 {
-        for row in 0..4 {
-            out[col*4 + row] =
-                a[0*4 + row]*b[col*4 + 0] +
-                a[1*4 + row]*b[col*4 + 1] +
-                a[2*4 + row]*b[col*4 + 2] +
-                a[3*4 + row]*b[col*4 + 3];
-        }
+// Icosahedron with each face split into 4^`level` triangles, pushed out to
+// the unit sphere. Counter-clockwise seen from outside, like `make_sphere`.
+fn make_icosphere(level: u32) -> (Vec<f32>, Vec<u32>) {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut verts: Vec<[f32; 3]> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(normalize)
+    .collect();
+    #[rustfmt::skip]
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..level {
+        let mut midpoints = std::collections::HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let (p, q) = (verts[a as usize], verts[b as usize]);
+                verts.push(normalize([p[0] + q[0], p[1] + q[1], p[2] + q[2]]));
+                verts.len() as u32 - 1
+            })
+        };
+        faces = faces
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
     }
-    out
-}
 
-#[rustfmt::skip]
-fn perspective(fovy: f32, aspect: f32, znear: f32, zfar: f32) -> [f32;16] {
-    let f = 1.0 / (0.5*fovy).tan();
-    let nf = 1.0 / (znear - zfar);
-    [
-        f/aspect, 0.0, 0.0,  0.0,
-        0.0,      f,   0.0,  0.0,
-        0.0,      0.0,(zfar+znear)*nf, -1.0,
-        0.0,      0.0,(2.0*zfar*znear)*nf, 0.0,
-    ]
+    // make every face counter-clockwise from outside
+    let idx = faces
+        .into_iter()
+        .flat_map(|[a, b, c]| {
+            let [p, q, r] = [a, b, c].map(|i| verts[i as usize]);
+            let (u, v) = (sub(q, p), sub(r, p));
+            let n = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            if n[0] * p[0] + n[1] * p[1] + n[2] * p[2] >= 0.0 {
+                [a, b, c]
+            } else {
+                [a, c, b]
+            }
+        })
+        .collect();
+    (verts.into_iter().flatten().collect(), idx)
 }
 
-#[rustfmt::skip]
-fn translate_z(z: f32) -> [f32;16] {
-    [
-        1.0,0.0,0.0,0.0,
-        0.0,1.0,0.0,0.0,
-        0.0,0.0,1.0,0.0,
-        0.0,0.0,z,  1.0,
-    ]
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
 }
 
-#[rustfmt::skip]
-fn quat_mul(a: [f32;4], b: [f32;4]) -> [f32;4] {
-    // (ax,ay,az,aw) * (bx,by,bz,bw)
-    let (ax,ay,az,aw) = (a[0],a[1],a[2],a[3]);
-    let (bx,by,bz,bw) = (b[0],b[1],b[2],b[3]);
-    [
-        aw*bx + ax*bw + ay*bz - az*by,
-        aw*by - ax*bz + ay*bw + az*bx,
-        aw*bz + ax*by - ay*bx + az*bw,
-        aw*bw - ax*bx - ay*by - az*bz,
-    ]
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use small_world_math::dot;
 
-fn quat_normalize(q: [f32; 4]) -> [f32; 4] {
-    let len = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
-    [q[0] / len, q[1] / len, q[2] / len, q[3] / len]
-}
+    fn cross(u: [f32; 3], v: [f32; 3]) -> [f32; 3] {
+        [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ]
+    }
 
-fn quat_from_axis_angle(axis: [f32; 3], angle: f32) -> [f32; 4] {
-    let (sx, sy, sz) = (axis[0], axis[1], axis[2]);
-    let n = (sx * sx + sy * sy + sz * sz).sqrt().max(1e-8);
-    let (x, y, z) = (sx / n, sy / n, sz / n);
-    let half = 0.5 * angle;
-    let s = half.sin();
-    [x * s, y * s, z * s, half.cos()]
-}
+    // Every triangle with an area is counter-clockwise seen from outside;
+    // returns how many have none (the UV sphere's at the poles)
+    fn assert_outward((verts, idx): &(Vec<f32>, Vec<u32>)) -> usize {
+        let vert = |i: u32| {
+            let i = 3 * i as usize;
+            [verts[i], verts[i + 1], verts[i + 2]]
+        };
+        let mut degenerate = 0;
+        for (t, tri) in idx.chunks_exact(3).enumerate() {
+            let [p, q, r] = [tri[0], tri[1], tri[2]].map(vert);
+            // corners within 1e-5 of each other, like the UV sphere's at a pole
+            let close = |a: [f32; 3], b: [f32; 3]| dot(sub(a, b), sub(a, b)) < 1e-10;
+            if close(p, q) || close(q, r) || close(r, p) {
+                degenerate += 1;
+                continue;
+            }
+            let n = cross(sub(q, p), sub(r, p));
+            assert!(dot(n, p) > 0.0, "triangle {t} {tri:?} faces inwards");
+        }
+        degenerate
+    }
 
-#[rustfmt::skip]
-fn mat4_from_quat(q: [f32;4]) -> [f32;16] {
-    // column-major
-    let (x,y,z,w) = (q[0],q[1],q[2],q[3]);
-    // let (x2,y2,z2) = (x+x, y+y, z+z);
-    let (xx,yy,zz) = (x*x, y*y, z*z);
-    let (xy,xz,yz) = (x*y, x*z, y*z);
-    let (wx,wy,wz) = (w*x, w*y, w*z);
-    [
-        1.0 - 2.0*(yy+zz),  2.0*(xy+ wz),     2.0*(xz - wy),     0.0,
-        2.0*(xy - wz),      1.0 - 2.0*(xx+zz),2.0*(yz + wx),     0.0,
-        2.0*(xz + wy),      2.0*(yz - wx),    1.0 - 2.0*(xx+yy), 0.0,
-        0.0,                0.0,              0.0,               1.0,
-    ]
+    #[test]
+    fn test_image_len() {
+        assert_eq!(image_len(7680, 4320), Some(7680 * 4320 * 4));
+        // 4.8 GB: too much for wasm32, where a u32 product used to wrap
+        let big = usize::try_from(4_800_000_000u64).ok();
+        assert_eq!(image_len(40_000, 30_000), big);
+        assert_eq!(image_len(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn test_uv_sphere_winding() {
+        let (lat, lon) = UV_SPHERE_SIZE;
+        let mesh = make_sphere(lat, lon);
+        assert_eq!(mesh.1.len() as u32, 6 * lat * lon);
+        // one triangle of each quad touching a pole is a point
+        assert_eq!(assert_outward(&mesh), 2 * lon as usize);
+    }
+
+    #[test]
+    fn test_icosphere() {
+        let mesh = make_icosphere(ICOSPHERE_LEVEL);
+        let faces = 20 * 4usize.pow(ICOSPHERE_LEVEL);
+        assert_eq!(faces, 327_680);
+        assert_eq!(mesh.1.len(), 3 * faces);
+        assert_eq!(mesh.0.len(), 3 * (faces / 2 + 2));
+        assert_eq!(assert_outward(&mesh), 0);
+        for p in mesh.0.chunks_exact(3) {
+            assert!((dot([p[0], p[1], p[2]], [p[0], p[1], p[2]]) - 1.0).abs() < 1e-5);
+        }
+    }
 }
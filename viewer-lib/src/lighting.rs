@@ -0,0 +1,259 @@
+use crate::link_program;
+use small_world_math::sphere;
+use std::f64::consts::TAU;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as GL, WebGlBuffer, WebGlProgram, WebGlTexture};
+
+const NORMAL_MAP_UNIT: u32 = 5; // after the scalar layer's textures
+const ATMOSPHERE_HEIGHT: f32 = 0.04; // of the globe's radius
+
+/// How the surface is lit.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lighting {
+    /// Colors as they are.
+    Off = 0,
+    /// Diffuse light from one direction plus some ambient: day and night.
+    Lambert = 1,
+    /// Soft light from a sky in the light's direction, a dim ground opposite.
+    Hemisphere = 2,
+}
+
+/// GLSL for the fragment shaders of what is drawn on the surface: call
+/// `shade(color, p, uv)` with the model-space point `p` of the unit sphere and
+/// its texture coordinates (v = 0 at the north pole).
+pub(crate) const SHADE_GLSL: &str = r#"
+        uniform int u_lighting;
+        uniform vec3 u_light_dir; // model space, towards the light
+        uniform float u_ambient;
+        uniform sampler2D u_normal_map;
+        uniform float u_normal_strength; // 0 without a normal map
+
+        vec3 surface_normal(vec3 p, vec2 uv) {
+            vec3 n = normalize(p);
+            // east on the sphere, as in `make_sphere`; undefined at the poles
+            vec3 east = vec3(n.z, 0.0, -n.x);
+            if (u_normal_strength <= 0.0 || dot(east, east) < 1e-10) {
+                return n;
+            }
+            east = normalize(east);
+            vec3 north = cross(n, east);
+            vec3 t = texture(u_normal_map, uv).xyz * 2.0 - 1.0;
+            t.xy *= u_normal_strength;
+            return normalize(t.x * east + t.y * north + t.z * n);
+        }
+
+        vec3 shade(vec3 color, vec3 p, vec2 uv) {
+            if (u_lighting == 0) {
+                return color;
+            }
+            float d = dot(surface_normal(p, uv), u_light_dir);
+            float light = u_lighting == 1
+                ? u_ambient + (1.0 - u_ambient) * max(d, 0.0)
+                : mix(u_ambient, 1.0, 0.5 + 0.5 * d);
+            return color * light;
+        }
+"#;
+
+/// Lighting state shared by the surface shaders, and the atmosphere.
+pub(crate) struct Lights {
+    pub mode: Lighting,
+    /// Unit vector towards the light, in model space.
+    pub direction: [f32; 3],
+    pub ambient: f32,
+    pub normal_strength: f32,
+    normal_map: WebGlTexture,
+    has_normal_map: bool,
+    atmosphere: WebGlProgram,
+    pub atmosphere_visible: bool,
+}
+
+impl Lights {
+    pub fn new(gl: &GL) -> Result<Self, JsValue> {
+        // the shell around the globe, glowing most where a view ray grazes
+        // the surface
+        let vert_src = r#"#version 300 es
+        precision highp float;
+
+        in vec3 a_pos;
+
+        uniform mat4 u_mvp;
+        uniform float u_scale;
+
+        out vec3 v_pos;
+
+        void main() {
+            v_pos = a_pos * u_scale;
+            gl_Position = u_mvp * vec4(v_pos, 1.0);
+        }"#;
+
+        let frag_src = r#"#version 300 es
+        precision highp float;
+
+        in vec3 v_pos;
+
+        uniform vec3 u_eye; // camera position in model space
+        uniform float u_scale;
+        uniform int u_lighting;
+        uniform vec3 u_light_dir;
+
+        out vec4 outColor;
+
+        void main() {
+            vec3 ray = normalize(v_pos - u_eye);
+            float closest = length(cross(v_pos, ray)); // ray's distance to the center
+            float glow = closest < 1.0
+                ? pow(closest, 8.0)
+                : 1.0 - smoothstep(1.0, u_scale, closest);
+            if (u_lighting != 0) {
+                glow *= 0.2 + 0.8 * smoothstep(-0.3, 0.3, dot(normalize(v_pos), u_light_dir));
+            }
+            outColor = vec4(vec3(0.45, 0.7, 1.0) * glow, 1.0);
+        }"#;
+
+        let normal_map = gl.create_texture().unwrap();
+        gl.bind_texture(GL::TEXTURE_2D, Some(&normal_map));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            1,
+            1,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&[128, 128, 255, 255]),
+        )?;
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::REPEAT as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+        Ok(Self {
+            mode: Lighting::Off,
+            direction: [0.0, 0.0, 1.0],
+            ambient: 0.25,
+            normal_strength: 1.0,
+            normal_map,
+            has_normal_map: false,
+            atmosphere: link_program(gl, vert_src, frag_src)?,
+            atmosphere_visible: false,
+        })
+    }
+
+    /// Light from straight above `(lon, lat)`, in degrees.
+    pub fn set_direction(&mut self, lon: f32, lat: f32) {
+        self.direction = sphere::from_lon_lat(lon, lat);
+    }
+
+    /// Upload a north-up normal map: east, north and up components of the
+    /// surface normal mapped from [-1, 1] to RGB.
+    pub fn set_normal_map(&mut self, gl: &GL, img: &web_sys::ImageBitmap) {
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.normal_map));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            img,
+        )
+        .unwrap();
+        self.has_normal_map = true;
+    }
+
+    /// Set the uniforms of [`SHADE_GLSL`] on `program`, which must be in use.
+    pub fn apply(&self, gl: &GL, program: &WebGlProgram) {
+        let uniform = |name: &str| gl.get_uniform_location(program, name);
+        gl.uniform1i(uniform("u_lighting").as_ref(), self.mode as i32);
+        gl.uniform3fv_with_f32_array(uniform("u_light_dir").as_ref(), &self.direction);
+        gl.uniform1f(uniform("u_ambient").as_ref(), self.ambient);
+        let strength = if self.has_normal_map {
+            self.normal_strength
+        } else {
+            0.0
+        };
+        gl.uniform1f(uniform("u_normal_strength").as_ref(), strength);
+        gl.active_texture(GL::TEXTURE0 + NORMAL_MAP_UNIT);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.normal_map));
+        gl.uniform1i(uniform("u_normal_map").as_ref(), NORMAL_MAP_UNIT as i32);
+        gl.active_texture(GL::TEXTURE0);
+    }
+
+    /// Draw the atmosphere over everything else, with the unit sphere mesh;
+    /// `mvp` includes the globe's radius and `eye` is the camera in the same
+    /// model space.
+    pub fn render_atmosphere(
+        &self,
+        gl: &GL,
+        mvp: &[f32; 16],
+        eye: [f32; 3],
+        vbo: &WebGlBuffer,
+        ibo: &WebGlBuffer,
+        count: i32,
+    ) {
+        if !self.atmosphere_visible {
+            return;
+        }
+        let program = &self.atmosphere;
+        gl.use_program(Some(program));
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(vbo));
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(ibo));
+        let pos_loc = gl.get_attrib_location(program, "a_pos") as u32;
+        gl.enable_vertex_attrib_array(pos_loc);
+        gl.vertex_attrib_pointer_with_i32(pos_loc, 3, GL::FLOAT, false, 0, 0);
+
+        let uniform = |name: &str| gl.get_uniform_location(program, name);
+        gl.uniform_matrix4fv_with_f32_array(uniform("u_mvp").as_ref(), false, mvp);
+        gl.uniform3fv_with_f32_array(uniform("u_eye").as_ref(), &eye);
+        gl.uniform1f(uniform("u_scale").as_ref(), 1.0 + ATMOSPHERE_HEIGHT);
+        gl.uniform1i(uniform("u_lighting").as_ref(), self.mode as i32);
+        gl.uniform3fv_with_f32_array(uniform("u_light_dir").as_ref(), &self.direction);
+
+        // the near side of the shell, added to what is behind it
+        gl.depth_mask(false);
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::ONE, GL::ONE);
+        gl.draw_elements_with_i32(GL::TRIANGLES, count, GL::UNSIGNED_INT, 0);
+        gl.disable(GL::BLEND);
+        gl.depth_mask(true);
+    }
+}
+
+/// Point under the sun at `unix_ms`, as (lon, lat) in degrees. Good to about
+/// half a degree in longitude (the equation of time to within a couple of
+/// minutes) and a degree in latitude.
+pub(crate) fn subsolar_point(unix_ms: f64) -> (f32, f32) {
+    let days = unix_ms / 86_400_000.0;
+    let hours = days.rem_euclid(1.0) * 24.0;
+    // days since the start of the year, 1970-01-01 being a year start
+    let day_of_year = days.rem_euclid(365.2422);
+    // minutes the sun is ahead of the clock, up to about 16
+    let b = TAU / 364.0 * (day_of_year + 1.0 - 81.0);
+    let equation_of_time = 9.87 * (2.0 * b).sin() - 7.53 * b.cos() - 1.5 * b.sin();
+    let solar_hours = hours + equation_of_time / 60.0;
+    let lon = (-15.0 * (solar_hours - 12.0) + 180.0).rem_euclid(360.0) - 180.0;
+    let declination = -23.44 * (TAU / 365.2422 * (day_of_year + 10.0)).cos();
+    (lon as f32, declination as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subsolar_point() {
+        // noon UTC, with the sun 16 min ahead of the clock, 14 min behind it
+        // and at the June solstice
+        for (unix_ms, lon, lat) in [
+            (1_730_635_200_000.0, -4.1, -15.0), // 2024-11-03
+            (1_707_652_800_000.0, 3.55, -14.3), // 2024-02-11
+            (1_718_971_200_000.0, 0.4, 23.44),  // 2024-06-21
+        ] {
+            let (l, p) = subsolar_point(unix_ms);
+            assert!((l - lon).abs() < 0.5, "lon {l}, expected {lon}");
+            assert!((p - lat).abs() < 1.0, "lat {p}, expected {lat}");
+        }
+    }
+}
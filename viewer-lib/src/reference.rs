@@ -0,0 +1,88 @@
+use crate::link_program;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as GL, WebGlBuffer, WebGlProgram};
+
+/// Translucent shell at the present-day radius, to compare a shrunken globe
+/// against. Mostly see-through, brighter towards the limb.
+pub(crate) struct ReferenceSphere {
+    program: WebGlProgram,
+    pub visible: bool,
+    /// Color and opacity at the limb.
+    pub color: [f32; 4],
+}
+
+impl ReferenceSphere {
+    pub fn new(gl: &GL) -> Result<Self, JsValue> {
+        let vert_src = r#"#version 300 es
+        precision mediump float;
+
+        in vec3 a_pos;
+
+        uniform mat4 u_proj;
+        uniform mat4 u_model;
+
+        out float v_rim;
+
+        void main() {
+            vec4 p = u_model * vec4(a_pos, 1.0);
+            vec3 n = normalize(mat3(u_model) * a_pos);
+            v_rim = 1.0 - abs(dot(n, normalize(-p.xyz)));
+            gl_Position = u_proj * p;
+        }"#;
+
+        let frag_src = r#"#version 300 es
+        precision mediump float;
+
+        in float v_rim;
+
+        uniform vec4 u_color;
+
+        out vec4 outColor;
+
+        void main() {
+            outColor = vec4(u_color.rgb, u_color.a * (0.15 + 0.85 * pow(v_rim, 3.0)));
+        }"#;
+
+        Ok(Self {
+            program: link_program(gl, vert_src, frag_src)?,
+            visible: false,
+            color: [0.8, 0.9, 1.0, 0.6],
+        })
+    }
+
+    /// Draw over the globe with the unit sphere mesh; `model` must not include
+    /// the globe's radius.
+    pub fn render(
+        &self,
+        gl: &GL,
+        proj: &[f32; 16],
+        model: &[f32; 16],
+        vbo: &WebGlBuffer,
+        ibo: &WebGlBuffer,
+        count: i32,
+    ) {
+        if !self.visible {
+            return;
+        }
+        gl.use_program(Some(&self.program));
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(vbo));
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(ibo));
+
+        let pos_loc = gl.get_attrib_location(&self.program, "a_pos") as u32;
+        gl.enable_vertex_attrib_array(pos_loc);
+        gl.vertex_attrib_pointer_with_i32(pos_loc, 3, GL::FLOAT, false, 0, 0);
+
+        let uniform = |name: &str| gl.get_uniform_location(&self.program, name);
+        gl.uniform_matrix4fv_with_f32_array(uniform("u_proj").as_ref(), false, proj);
+        gl.uniform_matrix4fv_with_f32_array(uniform("u_model").as_ref(), false, model);
+        gl.uniform4fv_with_f32_array(uniform("u_color").as_ref(), &self.color);
+
+        // only the near side, without hiding what is drawn after it
+        gl.depth_mask(false);
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        gl.draw_elements_with_i32(GL::TRIANGLES, count, GL::UNSIGNED_INT, 0);
+        gl.disable(GL::BLEND);
+        gl.depth_mask(true);
+    }
+}
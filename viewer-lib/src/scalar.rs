@@ -0,0 +1,572 @@
+use crate::lighting::{Lights, SHADE_GLSL};
+use crate::{link_program, SPHERE_UV_GLSL};
+use std::f32::consts::PI;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as GL, WebGlBuffer, WebGlProgram, WebGlTexture};
+
+const LIFT: f32 = 1.002; // above the finest tiles
+
+/// The packed images exported by the model crate (`scalars/metadata.json`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarImage {
+    /// Age in red/green, gradient magnitude in blue/alpha.
+    AgeMagnitude = 0,
+    /// Gradient east component in red/green, north in blue/alpha.
+    Gradient = 1,
+    /// Partition id in red/green.
+    Partition = 2,
+}
+
+/// The fields packed in the [`ScalarImage`]s, for their decoding ranges.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarField {
+    Age = 0,
+    Magnitude = 1,
+    East = 2,
+    North = 3,
+    Partition = 4,
+}
+
+/// What the scalar layer shows over the globe's image.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Nothing: only the image or tiles.
+    Image = 0,
+    /// Age through the colormap.
+    Age = 1,
+    /// Gradient magnitude through the colormap.
+    Magnitude = 2,
+    /// Gradient bearing as hue, like the baked gradient map.
+    Bearing = 3,
+    /// A distinct color per partition.
+    Partition = 4,
+}
+
+/// Scalar fields decoded and colorized in the fragment shader, drawn over the
+/// image with alpha blending.
+pub(crate) struct ScalarLayer {
+    program: WebGlProgram,
+    images: [WebGlTexture; 3],
+    sizes: [Option<(u32, u32)>; 3], // of the loaded images
+    colormap: WebGlTexture,
+    decode: [[f32; 2]; 5],
+    pub mode: RenderMode,
+    /// Values mapped to the ends of the colormap.
+    pub value_range: [f32; 2],
+    /// Values outside of this range aren't drawn.
+    pub thresholds: [f32; 2],
+    pub opacity: f32,
+    /// Crust younger than this (Myr) is hidden; 0 shows everything.
+    pub age_threshold: f32,
+    /// Color of hidden crust.
+    pub hidden: [f32; 3],
+    // codes of the age field, read back for partitioning on the CPU
+    age_codes: Option<(Vec<u16>, u32, u32)>,
+}
+
+/// Decoded values at a point, see [`ScalarLayer::values_at`].
+pub(crate) struct FieldValues {
+    pub age: Option<f32>,
+    pub magnitude: Option<f32>,
+    pub east: Option<f32>,
+    pub north: Option<f32>,
+}
+
+impl ScalarLayer {
+    pub fn new(gl: &GL) -> Result<Self, JsValue> {
+        let vert_src = r#"#version 300 es
+        precision highp float;
+
+        in vec3 a_pos;
+
+        uniform mat4 u_mvp;
+        uniform float u_lift;
+
+        out vec3 v_pos;
+
+        void main() {
+            v_pos = a_pos;
+            gl_Position = u_mvp * vec4(a_pos * u_lift, 1.0);
+        }"#;
+
+        let frag_src = [
+            r#"#version 300 es
+        precision highp float;
+
+        in vec3 v_pos;
+
+        uniform sampler2D u_age_mag;
+        uniform sampler2D u_gradient;
+        uniform sampler2D u_partition;
+        uniform sampler2D u_cmap;
+        uniform vec2 u_decode[5]; // min, max of age, magnitude, east, north, partition
+        uniform int u_mode;
+        uniform vec2 u_range;
+        uniform vec2 u_thresholds;
+        uniform float u_opacity;
+        uniform float u_age_threshold;
+        uniform vec3 u_hidden;
+
+        out vec4 outColor;
+
+        vec2 v_uv; // per fragment, see main
+        "#,
+            SPHERE_UV_GLSL,
+            SHADE_GLSL,
+            r#"
+        // 16-bit code of the texel under v_uv, from two channels; 0 = no data
+        float code(sampler2D tex, bool second) {
+            ivec2 size = textureSize(tex, 0);
+            ivec2 p = clamp(ivec2(v_uv * vec2(size)), ivec2(0), size - 1);
+            vec4 t = round(texelFetch(tex, p, 0) * 255.0);
+            return second ? t.b * 256.0 + t.a : t.r * 256.0 + t.g;
+        }
+
+        float decode(float q, int field) {
+            vec2 r = u_decode[field];
+            return r.x + (q - 1.0) / 65534.0 * (r.y - r.x);
+        }
+
+        vec3 hsv2rgb(vec3 c) {
+            vec3 p = abs(fract(c.xxx + vec3(0.0, 2.0, 1.0) / 3.0) * 6.0 - 3.0);
+            return c.z * mix(vec3(1.0), clamp(p - 1.0, 0.0, 1.0), c.y);
+        }
+
+        void main() {
+            v_uv = sphere_uv(v_pos);
+
+            // crust younger than the threshold is removed, whatever the mode
+            if (u_age_threshold > 0.0) {
+                float qa = code(u_age_mag, false);
+                if (qa > 0.0 && decode(qa, 0) < u_age_threshold) {
+                    outColor = vec4(u_hidden, 1.0);
+                    return;
+                }
+            }
+
+            float q;
+            float value;
+            vec3 color;
+            if (u_mode == 1 || u_mode == 2) {
+                q = code(u_age_mag, u_mode == 2);
+                value = decode(q, u_mode - 1);
+                float t = (value - u_range.x) / (u_range.y - u_range.x);
+                color = texture(u_cmap, vec2(clamp(t, 0.0, 1.0), 0.5)).rgb;
+            } else if (u_mode == 3) {
+                q = code(u_age_mag, true);
+                value = decode(q, 1);
+                float e = decode(code(u_gradient, false), 2);
+                float n = decode(code(u_gradient, true), 3);
+                // bearing clockwise from north
+                float hue = fract(atan(e, n) / 6.28318530718 + 1.0);
+                color = hsv2rgb(vec3(hue, 1.0, 1.0));
+            } else if (u_mode == 4) {
+                q = code(u_partition, false);
+                value = decode(q, 4);
+                float hue = fract(value * 0.618033988749);
+                color = hsv2rgb(vec3(hue, 0.6, 0.9));
+            } else {
+                discard;
+            }
+            // thresholds apply to values, not to partition ids
+            bool outside = value < u_thresholds.x || value > u_thresholds.y;
+            if (q == 0.0 || (u_mode != 4 && outside)) {
+                discard;
+            }
+            outColor = vec4(shade(color, v_pos, v_uv), u_opacity);
+        }"#,
+        ]
+        .concat();
+
+        let program = link_program(gl, vert_src, &frag_src)?;
+
+        let images = [data_texture(gl), data_texture(gl), data_texture(gl)];
+        let colormap = gl.create_texture().unwrap();
+        gl.bind_texture(GL::TEXTURE_2D, Some(&colormap));
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+        Ok(Self {
+            program,
+            images,
+            sizes: [None; 3],
+            colormap,
+            decode: [[0.0, 1.0]; 5],
+            mode: RenderMode::Image,
+            value_range: [0.0, 1.0],
+            thresholds: [f32::MIN, f32::MAX],
+            opacity: 1.0,
+            age_threshold: 0.0,
+            hidden: [0.05, 0.1, 0.2],
+            age_codes: None,
+        })
+    }
+
+    /// Upload a packed image. It must have been decoded as is: no
+    /// premultiplied alpha and no color space conversion.
+    pub fn set_image(&mut self, gl: &GL, which: ScalarImage, img: &web_sys::ImageBitmap) {
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.images[which as usize]));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.pixel_storei(GL::UNPACK_PREMULTIPLY_ALPHA_WEBGL, 0);
+        gl.pixel_storei(GL::UNPACK_COLORSPACE_CONVERSION_WEBGL, GL::NONE as i32);
+        gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA8 as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            img,
+        )
+        .unwrap();
+        gl.pixel_storei(
+            GL::UNPACK_COLORSPACE_CONVERSION_WEBGL,
+            GL::BROWSER_DEFAULT_WEBGL as i32,
+        );
+        let (w, h) = (img.width(), img.height());
+        self.sizes[which as usize] = Some((w, h));
+
+        if which == ScalarImage::AgeMagnitude {
+            let rgba = read_texels(gl, &self.images[0], 0, 0, w, h);
+            let codes = rgba
+                .chunks_exact(4)
+                .map(|px| u16::from_be_bytes([px[0], px[1]]))
+                .collect();
+            self.age_codes = Some((codes, w, h));
+        }
+    }
+
+    /// Recompute the partitions of the crust at least `age_threshold` old (and
+    /// of areas without data, like continents), as in the model's
+    /// `partition_crust`, and show them in [`RenderMode::Partition`]. Returns
+    /// the number of partitions, or `None` before the ages are loaded.
+    pub fn update_partitions(&mut self, gl: &GL) -> Option<usize> {
+        let (keep, w, h) = self.surviving(self.age_threshold)?;
+        let (labels, count) = label_crust(&keep, w, h);
+
+        // same packing as the exported ids: q = id + 1 in red and green
+        let mut rgba = Vec::with_capacity(labels.len() * 4);
+        for label in labels {
+            let q = match label {
+                0 => 0,
+                id => ((id - 1) % 65535 + 1) as u16,
+            };
+            let [hi, lo] = q.to_be_bytes();
+            rgba.extend_from_slice(&[hi, lo, 0, 255]);
+        }
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.images[2]));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA8 as i32,
+            w as i32,
+            h as i32,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&rgba),
+        )
+        .unwrap();
+        self.decode[ScalarField::Partition as usize] = [0.0, 65534.0];
+        self.sizes[2] = Some((w as u32, h as u32));
+        Some(count)
+    }
+
+    /// Fraction of the globe's area left by hiding crust younger than `age`,
+    /// or `None` before the ages are loaded.
+    pub fn surviving_fraction(&self, age: f32) -> Option<f32> {
+        let (keep, w, h) = self.surviving(age)?;
+        Some(area_fraction(&keep, w, h))
+    }
+
+    /// Values of the fields at texture coordinates `uv` (v = 0 at the north
+    /// pole), read back from the GPU: `None` where there is no data or the
+    /// image isn't loaded.
+    pub fn values_at(&self, gl: &GL, uv: [f32; 2]) -> FieldValues {
+        let texel = |which: ScalarImage| {
+            let (w, h) = self.sizes[which as usize]?;
+            let x = ((uv[0] * w as f32) as u32).min(w - 1);
+            let y = ((uv[1] * h as f32) as u32).min(h - 1);
+            let px = read_texels(gl, &self.images[which as usize], x, y, 1, 1);
+            Some([
+                u16::from_be_bytes([px[0], px[1]]),
+                u16::from_be_bytes([px[2], px[3]]),
+            ])
+        };
+        let age_mag = texel(ScalarImage::AgeMagnitude);
+        let gradient = texel(ScalarImage::Gradient);
+        let value = |field, q: Option<u16>| q.and_then(|q| self.decode(field, q));
+        FieldValues {
+            age: value(ScalarField::Age, age_mag.map(|q| q[0])),
+            magnitude: value(ScalarField::Magnitude, age_mag.map(|q| q[1])),
+            east: value(ScalarField::East, gradient.map(|q| q[0])),
+            north: value(ScalarField::North, gradient.map(|q| q[1])),
+        }
+    }
+
+    // Value of a 16-bit code of `field`, `None` for no data.
+    fn decode(&self, field: ScalarField, q: u16) -> Option<f32> {
+        let [min, max] = self.decode[field as usize];
+        (q != 0).then(|| min + (q - 1) as f32 / 65534.0 * (max - min))
+    }
+
+    // Cells at least `age` old, or without data (like continents).
+    fn surviving(&self, age: f32) -> Option<(Vec<bool>, usize, usize)> {
+        let (codes, w, h) = self.age_codes.as_ref()?;
+        let keep = codes
+            .iter()
+            .map(|&q| self.decode(ScalarField::Age, q).is_none_or(|a| a >= age))
+            .collect();
+        Some((keep, *w as usize, *h as usize))
+    }
+
+    pub fn set_decode_range(&mut self, field: ScalarField, min: f32, max: f32) {
+        self.decode[field as usize] = [min, max];
+    }
+
+    /// Use a colormap strip (N×1, left = low values).
+    pub fn set_colormap(&self, gl: &GL, img: &web_sys::ImageBitmap) {
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.colormap));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            img,
+        )
+        .unwrap();
+    }
+
+    fn ready(&self) -> bool {
+        match self.mode {
+            RenderMode::Image => self.sizes[0].is_some() && self.age_threshold > 0.0,
+            RenderMode::Age | RenderMode::Magnitude => self.sizes[0].is_some(),
+            RenderMode::Bearing => self.sizes[0].is_some() && self.sizes[1].is_some(),
+            RenderMode::Partition => self.sizes[2].is_some(),
+        }
+    }
+
+    /// Draw over the globe, using its sphere mesh (positions).
+    pub fn render(
+        &self,
+        gl: &GL,
+        mvp: &[f32; 16],
+        vbo: &WebGlBuffer,
+        ibo: &WebGlBuffer,
+        count: i32,
+        lights: &Lights,
+    ) {
+        if !self.ready() {
+            return;
+        }
+        gl.use_program(Some(&self.program));
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(vbo));
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(ibo));
+
+        let pos_loc = gl.get_attrib_location(&self.program, "a_pos") as u32;
+        gl.enable_vertex_attrib_array(pos_loc);
+        gl.vertex_attrib_pointer_with_i32(pos_loc, 3, GL::FLOAT, false, 0, 0);
+
+        let uniform = |name: &str| gl.get_uniform_location(&self.program, name);
+        gl.uniform_matrix4fv_with_f32_array(uniform("u_mvp").as_ref(), false, mvp);
+        gl.uniform1f(uniform("u_lift").as_ref(), LIFT);
+        let decode: Vec<f32> = self.decode.iter().flatten().copied().collect();
+        gl.uniform2fv_with_f32_array(uniform("u_decode").as_ref(), &decode);
+        gl.uniform1i(uniform("u_mode").as_ref(), self.mode as i32);
+        gl.uniform2f(
+            uniform("u_range").as_ref(),
+            self.value_range[0],
+            self.value_range[1],
+        );
+        gl.uniform2f(
+            uniform("u_thresholds").as_ref(),
+            self.thresholds[0],
+            self.thresholds[1],
+        );
+        gl.uniform1f(uniform("u_opacity").as_ref(), self.opacity);
+        gl.uniform1f(uniform("u_age_threshold").as_ref(), self.age_threshold);
+        gl.uniform3fv_with_f32_array(uniform("u_hidden").as_ref(), &self.hidden);
+        lights.apply(gl, &self.program);
+
+        let textures = [
+            ("u_age_mag", &self.images[0]),
+            ("u_gradient", &self.images[1]),
+            ("u_partition", &self.images[2]),
+            ("u_cmap", &self.colormap),
+        ];
+        for (unit, (name, tex)) in textures.into_iter().enumerate() {
+            gl.active_texture(GL::TEXTURE0 + unit as u32);
+            gl.bind_texture(GL::TEXTURE_2D, Some(tex));
+            gl.uniform1i(uniform(name).as_ref(), unit as i32);
+        }
+        gl.active_texture(GL::TEXTURE0);
+
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        gl.draw_elements_with_i32(GL::TRIANGLES, count, GL::UNSIGNED_INT, 0);
+        gl.disable(GL::BLEND);
+    }
+}
+
+// Packed values can't be interpolated: sample the nearest texel only.
+fn data_texture(gl: &GL) -> WebGlTexture {
+    let tex = gl.create_texture().unwrap();
+    gl.bind_texture(GL::TEXTURE_2D, Some(&tex));
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::NEAREST as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::NEAREST as i32);
+    tex
+}
+
+// Pixels of a region of a texture, rows from the first uploaded one.
+fn read_texels(gl: &GL, tex: &WebGlTexture, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+    let fb = gl.create_framebuffer().unwrap();
+    gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&fb));
+    gl.framebuffer_texture_2d(
+        GL::FRAMEBUFFER,
+        GL::COLOR_ATTACHMENT0,
+        GL::TEXTURE_2D,
+        Some(tex),
+        0,
+    );
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    gl.read_pixels_with_opt_u8_array(
+        x as i32,
+        y as i32,
+        width as i32,
+        height as i32,
+        GL::RGBA,
+        GL::UNSIGNED_BYTE,
+        Some(&mut pixels),
+    )
+    .unwrap();
+    gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+    gl.delete_framebuffer(Some(&fb));
+    pixels
+}
+
+// Fraction of the globe's area covered by the `keep` cells of an
+// equirectangular grid, north row first.
+fn area_fraction(keep: &[bool], width: usize, height: usize) -> f32 {
+    let (mut kept, mut total) = (0.0, 0.0);
+    for (y, row) in keep.chunks_exact(width).enumerate() {
+        let lat = PI * (0.5 - (y as f32 + 0.5) / height as f32);
+        let weight = lat.cos();
+        kept += weight * row.iter().filter(|&&k| k).count() as f32;
+        total += weight * width as f32;
+    }
+    kept / total
+}
+
+// Connected regions of `keep` cells (4-neighbors, wrapping in longitude),
+// labelled from 1 in row-major order of their first cell; 0 elsewhere.
+fn label_crust(keep: &[bool], width: usize, height: usize) -> (Vec<u32>, usize) {
+    let mut labels = vec![0u32; keep.len()];
+    let mut count = 0;
+    let mut stack = vec![];
+    for start in 0..keep.len() {
+        if !keep[start] || labels[start] != 0 {
+            continue;
+        }
+        count += 1;
+        labels[start] = count as u32;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            let row = y * width;
+            let mut neighbours = [
+                Some(row + (x + width - 1) % width),
+                Some(row + (x + 1) % width),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            for n in neighbours.iter_mut().flatten() {
+                if keep[*n] && labels[*n] == 0 {
+                    labels[*n] = count as u32;
+                    stack.push(*n);
+                }
+            }
+        }
+    }
+    (labels, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use small_world_model::partition::partition_crust;
+
+    fn grid(rows: &[&str]) -> (Vec<bool>, usize, usize) {
+        let keep = rows
+            .iter()
+            .flat_map(|r| r.chars().map(|c| c == '#'))
+            .collect();
+        (keep, rows[0].len(), rows.len())
+    }
+
+    #[test]
+    fn test_label_crust_wraps_the_dateline() {
+        let (keep, w, h) = grid(&["#..#", "....", "#..."]);
+        let (labels, count) = label_crust(&keep, w, h);
+        assert_eq!(count, 2);
+        assert_eq!(labels[0], labels[3]);
+        assert_eq!(labels[8], 2);
+        assert_eq!(labels[1], 0);
+    }
+
+    #[test]
+    fn test_label_crust_pole_rows() {
+        // the poles don't wrap into each other, and a full row is one region
+        let (keep, w, h) = grid(&["####", "....", "####"]);
+        let (labels, count) = label_crust(&keep, w, h);
+        assert_eq!(count, 2);
+        assert!(labels[..4].iter().all(|&l| l == 1));
+        assert!(labels[8..].iter().all(|&l| l == 2));
+
+        let (keep, w, h) = grid(&[".#..", ".#..", ".##."]);
+        assert_eq!(label_crust(&keep, w, h).1, 1);
+    }
+
+    #[test]
+    fn test_label_crust_matches_the_model() {
+        let (w, h) = (24, 12);
+        let ages: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let (x, y) = ((i % w) as f32, (i / w) as f32);
+                if (x * 7.0 + y * 3.0) % 11.0 < 1.0 {
+                    f32::NAN
+                } else {
+                    ((x * 1.3).sin() + (y * 0.9).cos()) * 50.0 + 60.0
+                }
+            })
+            .collect();
+        for min_age in [20.0, 60.0, 100.0] {
+            let keep: Vec<bool> = ages.iter().map(|&a| a.is_nan() || a >= min_age).collect();
+            let (labels, count) = label_crust(&keep, w, h);
+            let patches = partition_crust(&ages, (w, h), min_age);
+            assert_eq!(count, patches.len(), "min_age {min_age}");
+            for (id, patch) in patches.iter().enumerate() {
+                assert!(patch.iter().all(|&i| labels[i] == id as u32 + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_area_fraction() {
+        let (keep, w, h) = grid(&["##", "##"]);
+        assert_eq!(area_fraction(&keep, w, h), 1.0);
+        // each hemisphere is half the globe, whatever the row count
+        let (keep, w, h) = grid(&["##", "##", "..", ".."]);
+        assert!((area_fraction(&keep, w, h) - 0.5).abs() < 1e-6);
+        // rows near the poles count for less than rows at the equator
+        let (keep, w, h) = grid(&["##", "..", "..", ".."]);
+        assert!(area_fraction(&keep, w, h) < 0.25);
+    }
+}
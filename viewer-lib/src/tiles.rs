@@ -0,0 +1,360 @@
+use crate::lighting::{Lights, SHADE_GLSL};
+use crate::link_program;
+use js_sys::{Float32Array, Uint16Array};
+use small_world_math::sphere::{angle, from_uv};
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+use web_sys::{WebGl2RenderingContext as GL, WebGlBuffer, WebGlProgram, WebGlTexture};
+
+const PATCH_SEGMENTS: u16 = 32; // per side of a tile
+const MAX_TILES: usize = 256; // textures kept on the GPU
+const LIFT_PER_LEVEL: f32 = 0.0002; // finer levels float just above coarser ones
+
+/// (level, column, row) of a tile in the equirectangular quadtree exported by
+/// the model crate: level `z` has `2^(z+1)` columns and `2^z` rows, row 0 at
+/// the north pole and column 0 at -180°.
+pub(crate) type TileKey = (u32, u32, u32);
+
+struct Tile {
+    tex: WebGlTexture,
+    last_used: u64,
+}
+
+/// Where the camera is, as needed to pick tiles.
+pub(crate) struct View {
+    /// Unit vector from the globe center towards the camera, in model space.
+    pub cam_dir: [f32; 3],
+    pub dist: f32,
+    pub fovy: f32,
+    pub aspect: f32,
+    /// Drawing buffer height, in pixels.
+    pub height: f32,
+}
+
+/// Tiles loaded on demand, drawn as patches over the base sphere.
+pub(crate) struct TileSet {
+    program: WebGlProgram,
+    vbo: WebGlBuffer,
+    ibo: WebGlBuffer,
+    index_count: i32,
+    source: Option<(u32, u32)>, // tile size, max level
+    tiles: HashMap<TileKey, Tile>,
+    requested: HashSet<TileKey>,
+    frame: u64,
+}
+
+impl TileSet {
+    pub fn new(gl: &GL) -> Result<Self, wasm_bindgen::JsValue> {
+        let vert_src = r#"#version 300 es
+        precision highp float;
+
+        in vec2 a_st;
+
+        uniform mat4 u_mvp;
+        uniform vec4 u_bounds; // u0, u1, v0, v1 of the tile on the base sphere
+        uniform float u_lift;
+
+        out vec2 v_uv;
+        out vec2 v_globe_uv;
+        out vec3 v_pos;
+
+        void main() {
+            float u = mix(u_bounds.x, u_bounds.y, a_st.x);
+            float v = mix(u_bounds.z, u_bounds.w, a_st.y);
+            float phi = -u * 6.28318530718;
+            float theta = v * 3.14159265359;
+            vec3 pos = vec3(cos(phi) * sin(theta), cos(theta), sin(phi) * sin(theta));
+            v_uv = a_st;
+            v_globe_uv = vec2(u, v);
+            v_pos = pos;
+            gl_Position = u_mvp * vec4(pos * u_lift, 1.0);
+        }"#;
+
+        let frag_src = [
+            r#"#version 300 es
+        precision mediump float;
+        in vec2 v_uv;
+        in vec2 v_globe_uv;
+        in vec3 v_pos;
+        uniform sampler2D u_tex;
+        out vec4 outColor;
+        "#,
+            SHADE_GLSL,
+            r#"
+        void main() {
+            vec4 color = texture(u_tex, v_uv);
+            outColor = vec4(shade(color.rgb, v_pos, v_globe_uv), color.a);
+        }"#,
+        ]
+        .concat();
+
+        let program = link_program(gl, vert_src, &frag_src)?;
+
+        let n = PATCH_SEGMENTS;
+        let mut st = Vec::with_capacity((n as usize + 1).pow(2) * 2);
+        for y in 0..=n {
+            for x in 0..=n {
+                st.extend_from_slice(&[x as f32 / n as f32, y as f32 / n as f32]);
+            }
+        }
+        let mut indices = Vec::with_capacity(n as usize * n as usize * 6);
+        for y in 0..n {
+            for x in 0..n {
+                let a = y * (n + 1) + x;
+                let b = a + n + 1;
+                indices.extend_from_slice(&[a, b, a + 1, b, b + 1, a + 1]);
+            }
+        }
+
+        let vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&vbo));
+        unsafe {
+            let arr = Float32Array::view(&st);
+            gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &arr, GL::STATIC_DRAW);
+        }
+        let ibo = gl.create_buffer().unwrap();
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&ibo));
+        unsafe {
+            let arr = Uint16Array::view(&indices);
+            gl.buffer_data_with_array_buffer_view(GL::ELEMENT_ARRAY_BUFFER, &arr, GL::STATIC_DRAW);
+        }
+
+        Ok(Self {
+            program,
+            vbo,
+            ibo,
+            index_count: indices.len() as i32,
+            source: None,
+            tiles: HashMap::new(),
+            requested: HashSet::new(),
+            frame: 0,
+        })
+    }
+
+    pub fn set_source(&mut self, gl: &GL, tile_size: u32, max_level: u32) {
+        for (_, tile) in self.tiles.drain() {
+            gl.delete_texture(Some(&tile.tex));
+        }
+        self.requested.clear();
+        self.source = Some((tile_size, max_level));
+    }
+
+    /// Up to `max` tiles that should be fetched next, coarse and central first.
+    /// They count as requested until [`TileSet::insert`] or [`TileSet::failed`].
+    pub fn needed(&mut self, view: &View, max: usize) -> Vec<TileKey> {
+        let Some((tile_size, max_level)) = self.source else {
+            return vec![];
+        };
+        let z = level_for(view, tile_size, max_level);
+        // level 0 is the fallback everywhere, so it's always wanted
+        let mut wanted = visible_tiles(view, 0);
+        if z > 0 {
+            wanted.extend(visible_tiles(view, z));
+        }
+        let out: Vec<TileKey> = wanted
+            .into_iter()
+            .filter(|k| !self.tiles.contains_key(k) && !self.requested.contains(k))
+            .take(max)
+            .collect();
+        self.requested.extend(out.iter().copied());
+        out
+    }
+
+    pub fn insert(&mut self, gl: &GL, key: TileKey, img: &web_sys::ImageBitmap) {
+        self.requested.remove(&key);
+        if self.source.is_none() {
+            return;
+        }
+        let tex = gl.create_texture().unwrap();
+        gl.bind_texture(GL::TEXTURE_2D, Some(&tex));
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(
+            GL::TEXTURE_2D,
+            GL::TEXTURE_MIN_FILTER,
+            GL::LINEAR_MIPMAP_LINEAR as i32,
+        );
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        // tiles are stored north-up, matching v = 0 at the north pole
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            img,
+        )
+        .unwrap();
+        gl.generate_mipmap(GL::TEXTURE_2D);
+
+        if let Some(old) = self.tiles.insert(
+            key,
+            Tile {
+                tex,
+                last_used: self.frame,
+            },
+        ) {
+            gl.delete_texture(Some(&old.tex));
+        }
+        self.evict(gl);
+    }
+
+    pub fn failed(&mut self, key: TileKey) {
+        self.requested.remove(&key);
+    }
+
+    /// Draw the loaded tiles in view, from coarse to fine, with the same `mvp`
+    /// as the base sphere.
+    pub fn render(&mut self, gl: &GL, mvp: &[f32; 16], view: &View, lights: &Lights) {
+        let Some((tile_size, max_level)) = self.source else {
+            return;
+        };
+        self.frame += 1;
+        let z_max = level_for(view, tile_size, max_level);
+
+        gl.use_program(Some(&self.program));
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.vbo));
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.ibo));
+        let st_loc = gl.get_attrib_location(&self.program, "a_st") as u32;
+        gl.enable_vertex_attrib_array(st_loc);
+        gl.vertex_attrib_pointer_with_i32(st_loc, 2, GL::FLOAT, false, 0, 0);
+
+        let mvp_loc = gl.get_uniform_location(&self.program, "u_mvp");
+        gl.uniform_matrix4fv_with_f32_array(mvp_loc.as_ref(), false, mvp);
+        lights.apply(gl, &self.program);
+        let bounds_loc = gl.get_uniform_location(&self.program, "u_bounds");
+        let lift_loc = gl.get_uniform_location(&self.program, "u_lift");
+
+        for z in 0..=z_max {
+            for key in visible_tiles(view, z) {
+                let Some(tile) = self.tiles.get_mut(&key) else {
+                    continue;
+                };
+                tile.last_used = self.frame;
+                let (_, x, y) = key;
+                let (cols, rows) = ((2u32 << z) as f32, (1u32 << z) as f32);
+                gl.uniform4f(
+                    bounds_loc.as_ref(),
+                    x as f32 / cols,
+                    (x + 1) as f32 / cols,
+                    y as f32 / rows,
+                    (y + 1) as f32 / rows,
+                );
+                gl.uniform1f(lift_loc.as_ref(), 1.0 + LIFT_PER_LEVEL * (z + 1) as f32);
+                gl.bind_texture(GL::TEXTURE_2D, Some(&tile.tex));
+                gl.draw_elements_with_i32(GL::TRIANGLES, self.index_count, GL::UNSIGNED_SHORT, 0);
+            }
+        }
+        gl.disable_vertex_attrib_array(st_loc);
+    }
+
+    // Drop the least recently drawn tiles beyond `MAX_TILES`, keeping level 0
+    fn evict(&mut self, gl: &GL) {
+        if self.tiles.len() <= MAX_TILES {
+            return;
+        }
+        let mut old: Vec<(u64, TileKey)> = self
+            .tiles
+            .iter()
+            .filter(|(k, _)| k.0 > 0)
+            .map(|(k, t)| (t.last_used, *k))
+            .collect();
+        old.sort_unstable();
+        for (_, key) in old.into_iter().take(self.tiles.len() - MAX_TILES) {
+            if let Some(tile) = self.tiles.remove(&key) {
+                gl.delete_texture(Some(&tile.tex));
+            }
+        }
+    }
+}
+
+/// Finest level whose texels are no smaller than a screen pixel at the point
+/// of the globe nearest the camera.
+fn level_for(view: &View, tile_size: u32, max_level: u32) -> u32 {
+    let pixel_arc = (view.dist - 1.0).max(1e-4) * view.fovy / view.height;
+    let texels = PI / (tile_size as f32 * pixel_arc); // tile rows of texels needed
+    (texels.log2().ceil().max(0.0) as u32).min(max_level)
+}
+
+/// Tiles of level `z` that may be on screen, nearest to the view center first.
+fn visible_tiles(view: &View, z: u32) -> Vec<TileKey> {
+    // angular radius of the part of the globe that can be seen at all
+    let horizon = (1.0 / view.dist).acos();
+    let half_diag = ((view.fovy / 2.0).tan() * (1.0 + view.aspect * view.aspect).sqrt()).atan();
+    let s = view.dist * half_diag.sin();
+    let reach = if s < 1.0 {
+        (s.asin() - half_diag).min(horizon)
+    } else {
+        horizon
+    };
+
+    let (cols, rows) = (2u32 << z, 1u32 << z);
+    let mut out = vec![];
+    for y in 0..rows {
+        for x in 0..cols {
+            let (u0, u1) = (x as f32 / cols as f32, (x + 1) as f32 / cols as f32);
+            let (v0, v1) = (y as f32 / rows as f32, (y + 1) as f32 / rows as f32);
+            let center = from_uv((u0 + u1) / 2.0, (v0 + v1) / 2.0);
+            let radius = [
+                (u0, v0),
+                (u1, v0),
+                (u0, v1),
+                (u1, v1),
+                (u0, (v0 + v1) / 2.0),
+            ]
+            .into_iter()
+            .map(|(u, v)| angle(center, from_uv(u, v)))
+            .fold(0.0, f32::max);
+            let off = angle(center, view.cam_dir);
+            if off <= reach + radius {
+                out.push((off, (z, x, y)));
+            }
+        }
+    }
+    out.sort_by(|a, b| a.0.total_cmp(&b.0));
+    out.into_iter().map(|(_, k)| k).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use small_world_math::sphere::from_lon_lat;
+
+    fn view(lon: f32, lat: f32, dist: f32) -> View {
+        View {
+            cam_dir: from_lon_lat(lon, lat),
+            dist,
+            fovy: 0.8,
+            aspect: 1.5,
+            height: 800.0,
+        }
+    }
+
+    #[test]
+    fn test_level_for_zoom() {
+        let levels: Vec<u32> = [5.0, 3.0, 2.0, 1.5, 1.1, 1.01, 1.001]
+            .into_iter()
+            .map(|dist| level_for(&view(0.0, 0.0, dist), 256, 20))
+            .collect();
+        assert!(levels.windows(2).all(|l| l[0] <= l[1]), "{levels:?}");
+        assert!(levels[0] < levels[levels.len() - 1], "{levels:?}");
+
+        assert_eq!(level_for(&view(0.0, 0.0, 1.001), 256, 3), 3);
+        assert_eq!(level_for(&view(0.0, 0.0, 1.0), 256, 3), 3);
+        assert_eq!(level_for(&view(0.0, 0.0, 100.0), 256, 3), 0);
+    }
+
+    #[test]
+    fn test_visible_tiles() {
+        // looking at 179.9°E, 10°N on level 3: 16 columns of 22.5°, 8 rows
+        let tiles = visible_tiles(&view(179.9, 10.0, 1.2), 3);
+        assert_eq!(tiles[0], (3, 15, 3));
+        // the column just across the dateline, at 180°W
+        assert!(tiles.contains(&(3, 0, 3)));
+        // the far side of the globe, around 0°
+        assert!(!tiles.contains(&(3, 7, 3)));
+        assert!(!tiles.contains(&(3, 8, 4)));
+        assert!(tiles.len() < 16 * 8 / 2);
+    }
+}
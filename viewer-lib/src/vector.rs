@@ -0,0 +1,444 @@
+use crate::link_program;
+use js_sys::{Float32Array, Uint32Array};
+use small_world_math::geojson::{parse_geojson, Geometry, LonLat};
+use small_world_math::sphere::{angle, from_lon_lat};
+use std::f32::consts::PI;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as GL, WebGlBuffer, WebGlProgram};
+
+const LIFT: f32 = 1.003; // above the scalar layer
+const MAX_SEGMENT_DEG: f32 = 1.0; // lines follow great circles in steps this long
+const ANTIPODAL: f32 = 1e-3; // radians short of 180° where a segment's great circle is ambiguous
+
+struct VectorLayer {
+    lines: Option<(WebGlBuffer, WebGlBuffer, i32)>, // vbo, ibo, index count
+    points: Option<(WebGlBuffer, i32)>,             // vbo, point count
+    color: [f32; 4],
+    width: f32,
+    point_size: f32,
+    visible: bool,
+    dashes: Option<Dashes>,
+}
+
+// Dash pattern along lines, in degrees of arc, moving from the start of each
+// line towards its end.
+#[derive(Clone, Copy)]
+struct Dashes {
+    dash: f32,
+    gap: f32,
+    speed: f32, // degrees per second
+}
+
+/// Lines and points from GeoJSON, drawn just above the globe with widths in
+/// screen pixels.
+pub(crate) struct VectorLayers {
+    line_program: WebGlProgram,
+    point_program: WebGlProgram,
+    layers: Vec<(u32, VectorLayer)>,
+    next_id: u32,
+}
+
+impl VectorLayers {
+    pub fn new(gl: &GL) -> Result<Self, JsValue> {
+        // each segment is a quad: both ends, pushed sideways in screen space
+        let line_vert = r#"#version 300 es
+        precision highp float;
+
+        in vec3 a_pos;
+        in vec3 a_other; // the other end of the segment
+        in float a_side;
+        in float a_dist; // along the line, in degrees
+
+        uniform mat4 u_mvp;
+        uniform float u_lift;
+        uniform vec2 u_viewport;
+        uniform float u_width;
+
+        out float v_dist;
+
+        void main() {
+            v_dist = a_dist;
+            vec4 p = u_mvp * vec4(a_pos * u_lift, 1.0);
+            vec4 q = u_mvp * vec4(a_other * u_lift, 1.0);
+            vec2 d = q.xy / q.w * u_viewport - p.xy / p.w * u_viewport;
+            vec2 n = length(d) > 0.0 ? normalize(vec2(-d.y, d.x)) : vec2(0.0);
+            p.xy += n * a_side * u_width / u_viewport * p.w;
+            gl_Position = p;
+        }"#;
+
+        let point_vert = r#"#version 300 es
+        precision highp float;
+
+        in vec3 a_pos;
+
+        uniform mat4 u_mvp;
+        uniform float u_lift;
+        uniform float u_size;
+
+        void main() {
+            gl_Position = u_mvp * vec4(a_pos * u_lift, 1.0);
+            gl_PointSize = u_size;
+        }"#;
+
+        let line_frag = r#"#version 300 es
+        precision highp float;
+
+        in float v_dist;
+
+        uniform vec4 u_color;
+        uniform vec3 u_dashes; // dash, gap, offset; solid if dash is 0
+
+        out vec4 outColor;
+
+        void main() {
+            if (u_dashes.x > 0.0 && mod(v_dist - u_dashes.z, u_dashes.x + u_dashes.y) > u_dashes.x) {
+                discard;
+            }
+            outColor = u_color;
+        }"#;
+
+        let point_frag = r#"#version 300 es
+        precision mediump float;
+
+        uniform vec4 u_color;
+
+        out vec4 outColor;
+
+        void main() {
+            if (length(gl_PointCoord - 0.5) > 0.5) discard;
+            outColor = u_color;
+        }"#;
+
+        Ok(Self {
+            line_program: link_program(gl, line_vert, line_frag)?,
+            point_program: link_program(gl, point_vert, point_frag)?,
+            layers: vec![],
+            next_id: 1,
+        })
+    }
+
+    /// Add the geometries of a GeoJSON document as a new layer and return its
+    /// id. Polygons are drawn as their rings.
+    pub fn add(
+        &mut self,
+        gl: &GL,
+        geojson: &str,
+        color: [f32; 4],
+        width: f32,
+    ) -> Result<u32, String> {
+        let (mut lines, mut points) = (vec![], vec![]);
+        for geometry in parse_geojson(geojson)? {
+            match geometry {
+                Geometry::Point(p) => points.push(p),
+                Geometry::LineString(line) => lines.push(line),
+                Geometry::Polygon(rings) => lines.extend(rings),
+            }
+        }
+
+        let layer = VectorLayer {
+            lines: line_mesh(&lines).map(|(verts, indices)| {
+                let count = indices.len() as i32;
+                (
+                    upload(gl, GL::ARRAY_BUFFER, &Float32Array::from(&verts[..])),
+                    upload(
+                        gl,
+                        GL::ELEMENT_ARRAY_BUFFER,
+                        &Uint32Array::from(&indices[..]),
+                    ),
+                    count,
+                )
+            }),
+            points: (!points.is_empty()).then(|| {
+                let verts: Vec<f32> = points.iter().flat_map(|&p| to_sphere(p)).collect();
+                (
+                    upload(gl, GL::ARRAY_BUFFER, &Float32Array::from(&verts[..])),
+                    points.len() as i32,
+                )
+            }),
+            color,
+            width,
+            point_size: 6.0,
+            visible: true,
+            dashes: None,
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.layers.push((id, layer));
+        Ok(id)
+    }
+
+    /// Free a layer's buffers; false if there is no such layer.
+    pub fn remove(&mut self, gl: &GL, id: u32) -> bool {
+        let Some(i) = self.layers.iter().position(|(k, _)| *k == id) else {
+            return false;
+        };
+        let (_, layer) = self.layers.remove(i);
+        if let Some((vbo, ibo, _)) = &layer.lines {
+            gl.delete_buffer(Some(vbo));
+            gl.delete_buffer(Some(ibo));
+        }
+        if let Some((vbo, _)) = &layer.points {
+            gl.delete_buffer(Some(vbo));
+        }
+        true
+    }
+
+    pub fn set_visible(&mut self, id: u32, visible: bool) {
+        if let Some(layer) = self.get_mut(id) {
+            layer.visible = visible;
+        }
+    }
+
+    /// Color (RGBA in [0, 1]), line width and point diameter, in pixels.
+    pub fn set_style(&mut self, id: u32, color: [f32; 4], width: f32, point_size: f32) {
+        if let Some(layer) = self.get_mut(id) {
+            layer.color = color;
+            layer.width = width;
+            layer.point_size = point_size;
+        }
+    }
+
+    /// Draw lines as `dash`es separated by `gap`s (degrees of arc) flowing
+    /// along them at `speed` degrees per second; solid if `dash` is 0.
+    pub fn set_dashes(&mut self, id: u32, dash: f32, gap: f32, speed: f32) {
+        if let Some(layer) = self.get_mut(id) {
+            layer.dashes = (dash > 0.0).then_some(Dashes { dash, gap, speed });
+        }
+    }
+
+    /// `viewport` in pixels; widths and sizes are multiplied by
+    /// `pixel_scale`; `time` in seconds, for moving dashes.
+    pub fn render(
+        &self,
+        gl: &GL,
+        mvp: &[f32; 16],
+        viewport: [f32; 2],
+        pixel_scale: f32,
+        time: f64,
+    ) {
+        if !self.layers.iter().any(|(_, l)| l.visible) {
+            return;
+        }
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        gl.depth_mask(false);
+        // line quads face either way
+        gl.disable(GL::CULL_FACE);
+
+        let program = &self.line_program;
+        gl.use_program(Some(program));
+        let uniform = |name: &str| gl.get_uniform_location(program, name);
+        gl.uniform_matrix4fv_with_f32_array(uniform("u_mvp").as_ref(), false, mvp);
+        gl.uniform1f(uniform("u_lift").as_ref(), LIFT);
+        gl.uniform2fv_with_f32_array(uniform("u_viewport").as_ref(), &viewport);
+        let stride = (8 * std::mem::size_of::<f32>()) as i32;
+        let attribs = [
+            ("a_pos", 3, 0),
+            ("a_other", 3, 3),
+            ("a_side", 1, 6),
+            ("a_dist", 1, 7),
+        ];
+        for (_, layer) in self.layers.iter().filter(|(_, l)| l.visible) {
+            let Some((vbo, ibo, count)) = &layer.lines else {
+                continue;
+            };
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(vbo));
+            gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(ibo));
+            for (name, size, offset) in attribs {
+                let loc = gl.get_attrib_location(program, name) as u32;
+                gl.enable_vertex_attrib_array(loc);
+                gl.vertex_attrib_pointer_with_i32(loc, size, GL::FLOAT, false, stride, offset * 4);
+            }
+            gl.uniform4fv_with_f32_array(uniform("u_color").as_ref(), &layer.color);
+            gl.uniform1f(uniform("u_width").as_ref(), layer.width * pixel_scale);
+            let dashes = layer.dashes.map_or([0.0; 3], |d| {
+                let offset = (time * d.speed as f64).rem_euclid((d.dash + d.gap) as f64);
+                [d.dash, d.gap, offset as f32]
+            });
+            gl.uniform3fv_with_f32_array(uniform("u_dashes").as_ref(), &dashes);
+            gl.draw_elements_with_i32(GL::TRIANGLES, *count, GL::UNSIGNED_INT, 0);
+        }
+        for (name, _, _) in attribs {
+            gl.disable_vertex_attrib_array(gl.get_attrib_location(program, name) as u32);
+        }
+
+        let program = &self.point_program;
+        gl.use_program(Some(program));
+        let uniform = |name: &str| gl.get_uniform_location(program, name);
+        gl.uniform_matrix4fv_with_f32_array(uniform("u_mvp").as_ref(), false, mvp);
+        gl.uniform1f(uniform("u_lift").as_ref(), LIFT);
+        let pos_loc = gl.get_attrib_location(program, "a_pos") as u32;
+        for (_, layer) in self.layers.iter().filter(|(_, l)| l.visible) {
+            let Some((vbo, count)) = &layer.points else {
+                continue;
+            };
+            gl.bind_buffer(GL::ARRAY_BUFFER, Some(vbo));
+            gl.enable_vertex_attrib_array(pos_loc);
+            gl.vertex_attrib_pointer_with_i32(pos_loc, 3, GL::FLOAT, false, 0, 0);
+            gl.uniform4fv_with_f32_array(uniform("u_color").as_ref(), &layer.color);
+            gl.uniform1f(uniform("u_size").as_ref(), layer.point_size * pixel_scale);
+            gl.draw_arrays(GL::POINTS, 0, *count);
+        }
+        gl.disable_vertex_attrib_array(pos_loc);
+
+        gl.enable(GL::CULL_FACE);
+        gl.depth_mask(true);
+        gl.disable(GL::BLEND);
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut VectorLayer> {
+        self.layers
+            .iter_mut()
+            .find(|(k, _)| *k == id)
+            .map(|(_, layer)| layer)
+    }
+}
+
+fn upload(gl: &GL, target: u32, data: &js_sys::Object) -> WebGlBuffer {
+    let buffer = gl.create_buffer().unwrap();
+    gl.bind_buffer(target, Some(&buffer));
+    gl.buffer_data_with_array_buffer_view(target, data, GL::STATIC_DRAW);
+    buffer
+}
+
+fn to_sphere((lon, lat): LonLat) -> [f32; 3] {
+    from_lon_lat(lon, lat)
+}
+
+// Quads along the lines, as (pos, other end, side, distance along the line)
+// vertices and indices; `None` if there are no segments.
+fn line_mesh(lines: &[Vec<LonLat>]) -> Option<(Vec<f32>, Vec<u32>)> {
+    let (mut verts, mut indices) = (vec![], vec![]);
+    for line in lines {
+        let path = great_circles(line);
+        let mut dist = 0.0;
+        for pair in path.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let next = dist + angle(a, b).to_degrees();
+            let base = (verts.len() / 8) as u32;
+            // seen from b, a's sides swap: quad corners a+, a-, b-, b+
+            for (p, q, side, d) in [
+                (a, b, 1.0, dist),
+                (a, b, -1.0, dist),
+                (b, a, 1.0, next),
+                (b, a, -1.0, next),
+            ] {
+                verts.extend_from_slice(&p);
+                verts.extend_from_slice(&q);
+                verts.extend_from_slice(&[side, d]);
+            }
+            dist = next;
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+    (!indices.is_empty()).then_some((verts, indices))
+}
+
+// Points of a line on the sphere, with great circle arcs between the given
+// ones cut into steps of at most MAX_SEGMENT_DEG.
+fn great_circles(line: &[LonLat]) -> Vec<[f32; 3]> {
+    let mut out: Vec<[f32; 3]> = vec![];
+    for (i, &p) in line.iter().enumerate() {
+        if i > 0 {
+            arc(&mut out, line[i - 1], p);
+        }
+        out.push(to_sphere(p));
+    }
+    out
+}
+
+// Points strictly between `a` and `b` on the great circle arc joining them.
+// (Nearly) antipodal ends are joined by many great circles, so the arc goes
+// through their midpoint in longitude and latitude, e.g. along the equator
+// from 0° to 180°.
+fn arc(out: &mut Vec<[f32; 3]>, a: LonLat, b: LonLat) {
+    let (pa, pb) = (to_sphere(a), to_sphere(b));
+    let theta = angle(pa, pb);
+    if theta > PI - ANTIPODAL {
+        let mid = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        arc(out, a, mid);
+        out.push(to_sphere(mid));
+        arc(out, mid, b);
+        return;
+    }
+    let steps = (theta.to_degrees() / MAX_SEGMENT_DEG).ceil().max(1.0) as usize;
+    for k in 1..steps {
+        out.push(slerp(pa, pb, theta, k as f32 / steps as f32));
+    }
+}
+
+fn slerp(a: [f32; 3], b: [f32; 3], theta: f32, t: f32) -> [f32; 3] {
+    let s = theta.sin();
+    let (wa, wb) = (((1.0 - t) * theta).sin() / s, (t * theta).sin() / s);
+    [
+        wa * a[0] + wb * b[0],
+        wa * a[1] + wb * b[1],
+        wa * a[2] + wb * b[2],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use small_world_math::sphere::to_lon_lat;
+
+    // (position, distance along the line) of the start of each segment, and
+    // of the end of the last one
+    fn stations(line: &[LonLat]) -> Vec<([f32; 3], f32)> {
+        let (verts, indices) = line_mesh(&[line.to_vec()]).unwrap();
+        let quads: Vec<&[f32]> = verts.chunks_exact(32).collect();
+        assert_eq!(indices.len(), 6 * quads.len());
+        let mut out: Vec<([f32; 3], f32)> =
+            quads.iter().map(|q| ([q[0], q[1], q[2]], q[7])).collect();
+        let last = quads.last().unwrap();
+        out.push(([last[16], last[17], last[18]], last[23]));
+        out
+    }
+
+    fn assert_steps(stations: &[([f32; 3], f32)]) {
+        for pair in stations.windows(2) {
+            let ((a, da), (b, db)) = (pair[0], pair[1]);
+            let step = angle(a, b).to_degrees();
+            assert!(step > 0.0 && step <= MAX_SEGMENT_DEG + 1e-3, "step {step}");
+            assert!(db > da);
+            assert!((db - da - step).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_segments_are_split() {
+        let path = stations(&[(0.0, 0.0), (10.0, 0.0)]);
+        assert!(path.len() >= 11);
+        assert_steps(&path);
+        assert!((path.last().unwrap().1 - 10.0).abs() < 0.01);
+        for (p, _) in &path {
+            assert!(to_lon_lat(*p).1.abs() < 1e-3, "off the equator");
+        }
+        assert!(line_mesh(&[vec![(5.0, 5.0)]]).is_none());
+    }
+
+    #[test]
+    fn test_segments_cross_the_dateline() {
+        // the short way, 20° across 180°, not 340° around the globe
+        let path = stations(&[(170.0, 10.0), (-170.0, 10.0)]);
+        assert_steps(&path);
+        assert!(path.last().unwrap().1 < 20.0);
+        for (p, _) in &path {
+            assert!(to_lon_lat(*p).0.abs() >= 170.0 - 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_antipodal_segments() {
+        let path = stations(&[(0.0, 0.0), (180.0, 0.0)]);
+        assert_steps(&path);
+        assert!((path.last().unwrap().1 - 180.0).abs() < 0.05);
+        let (lon, lat) = to_lon_lat(path[90].0);
+        assert!((lon - 90.0).abs() < 1e-3 && lat.abs() < 1e-3);
+
+        // pole to pole, along the meridian halfway between the given longitudes
+        let path = stations(&[(20.0, 90.0), (40.0, -90.0)]);
+        assert_steps(&path);
+        assert!((path.last().unwrap().1 - 180.0).abs() < 0.05);
+    }
+}